@@ -0,0 +1,5 @@
+// the solver as a bot for any frontend speaking the tetris bot protocol. it
+// talks over stdin and stdout and needs no window, so it builds without sdl
+fn main() -> Result<(), String> {
+    pc_trainer::tbp::serve(std::io::stdin().lock(), std::io::stdout())
+}
@@ -0,0 +1,422 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::iter;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::clock::Timestamp;
+use crate::input_manager::{Action, InputManager};
+use crate::settings::Settings;
+use crate::solver::{
+    self, cells, fits_board, reachable, Board, Placement, Position, Solver, SolverState,
+};
+use crate::tetris::{Piece, Rotation, State, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+type Cells = [(usize, usize); 4];
+// whether to hold first, and where the piece then goes
+type Target = (bool, Cells);
+
+const SPAWN: Position = (Rotation::Spawn, 3, 4);
+// the solver is only asked for perfect clears while the stack is this low
+pub const PERFECT_CLEAR_HEIGHT: usize = 4;
+
+// a computer player. it presses keys through its own input manager, the same
+// way a player would, placing at most `pps` pieces a second. perfect clears
+// found by the solver come first, and otherwise each piece goes where it
+// leaves the best looking board. pieces are planned on another thread, so
+// the solver never holds up a frame
+pub struct Bot {
+    input_manager: InputManager,
+    interval: Timestamp, // least time in ms between placing pieces
+    mistake_rate: u32,   // percent chance of dropping a piece somewhere at random
+    last_move: Option<Timestamp>,
+    rng: StdRng,
+    // the plan for the piece after this many placements, or the thread
+    // working it out
+    planning: Option<(u32, Receiver<Option<Target>>)>,
+    plan: Option<(u32, Option<Target>)>,
+}
+
+// what the bot sees when planning a piece
+struct Turn {
+    board: Board,
+    piece: Piece,
+    start: Position,
+    hold: Option<Piece>,
+    queue: Vec<Piece>,
+}
+
+impl Bot {
+    pub fn new(settings: &Settings, seed: u64) -> Bot {
+        // keys are pressed and released at once, so soft drops have to be
+        // instant and hard drops can't wait for protection from accidents
+        let mut settings = settings.clone();
+        settings.handling.sdf = 100;
+        settings.handling.pahd = 0;
+        Bot {
+            input_manager: InputManager::new(&settings),
+            interval: (1000.0 / settings.bot.pps) as Timestamp,
+            mistake_rate: settings.bot.mistake_rate,
+            last_move: None,
+            rng: StdRng::seed_from_u64(seed),
+            planning: None,
+            plan: None,
+        }
+    }
+
+    // steps the bot's board up to `to`, like a player's input manager
+    pub fn advance(&mut self, game: &mut Tetris, from: Timestamp, to: Timestamp) {
+        self.input_manager.advance(game, from, to);
+    }
+
+    // plans the active piece and places it once the plan is ready and enough
+    // time has passed since the last one
+    pub fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        if game.state() != State::Playing || game.active_piece().0 == Piece::None {
+            return;
+        }
+        // plans go stale once a piece locks, e.g. on its own while planning
+        let placed = game.stats().pieces;
+        let planned = matches!(self.plan, Some((count, _)) if count == placed);
+        let planning = matches!(self.planning, Some((count, _)) if count == placed);
+        if !planned && !planning {
+            self.start_planning(game);
+        }
+        if let Some((count, receiver)) = &self.planning {
+            let plan = match receiver.try_recv() {
+                Ok(target) => target,
+                Err(TryRecvError::Disconnected) => None,
+                Err(TryRecvError::Empty) => return,
+            };
+            self.plan = Some((*count, plan));
+            self.planning = None;
+        }
+        let target = match self.plan {
+            Some((count, target)) if count == placed => target,
+            _ => return,
+        };
+        if let Some(last_move) = self.last_move {
+            if timestamp < last_move + self.interval {
+                return;
+            }
+        }
+        self.last_move = Some(timestamp);
+        self.plan = None;
+
+        let mut actions = Vec::new();
+        if let Some((swap, cells)) = target {
+            if swap {
+                self.press(Action::Swap, game, timestamp);
+            }
+            // the piece may have fallen while its plan was being made
+            let (piece, rotation, line, col) = game.active_piece();
+            let start = (rotation, line as i32, col as i32);
+            actions =
+                find_path(&game.board_without_active(), piece, start, &cells).unwrap_or_default();
+        }
+        for action in actions.into_iter().chain(iter::once(Action::HardDrop)) {
+            self.press(action, game, timestamp);
+        }
+    }
+
+    fn press(&mut self, action: Action, game: &mut Tetris, timestamp: Timestamp) {
+        self.input_manager
+            .process_action(action, true, game, timestamp);
+        self.input_manager
+            .process_action(action, false, game, timestamp);
+    }
+
+    fn start_planning(&mut self, game: &Tetris) {
+        let (piece, rotation, line, col) = game.active_piece();
+        let turn = Turn {
+            board: game.board_without_active(),
+            piece,
+            start: (rotation, line as i32, col as i32),
+            hold: game.get_hold(),
+            queue: game.get_queue(),
+        };
+        let placed = game.stats().pieces;
+        if self.rng.gen_range(0..100) < self.mistake_rate {
+            let mut spots = reachable(&turn.board, piece);
+            spots.shuffle(&mut self.rng);
+            let target = spots
+                .into_iter()
+                .find(|cells| find_path(&turn.board, piece, turn.start, cells).is_some())
+                .map(|cells| (false, cells));
+            self.plan = Some((placed, target));
+            self.planning = None;
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // the bot may be gone by now, in which case nobody is listening
+            let _ = sender.send(plan(&turn));
+        });
+        self.planning = Some((placed, receiver));
+    }
+}
+
+// where the bot would put `piece` on `board`, coming from spawn, or the piece
+// it would hold instead. `queue` is the pieces after `piece`
+pub fn suggest(
+    board: &Board,
+    piece: Piece,
+    hold: Option<Piece>,
+    queue: &[Piece],
+) -> Option<Placement> {
+    let turn = Turn {
+        board: *board,
+        piece,
+        start: SPAWN,
+        hold,
+        queue: queue.to_vec(),
+    };
+    let (swap, mut target) = plan(&turn)?;
+    let piece = if swap {
+        hold.or_else(|| queue.first().copied())?
+    } else {
+        piece
+    };
+    // some pieces cover the same cells in two rotations, the first will do
+    target.sort();
+    let rotations = [
+        Rotation::Spawn,
+        Rotation::Right,
+        Rotation::Flip,
+        Rotation::Left,
+    ];
+    rotations.into_iter().find_map(|rotation| {
+        (0..BOARD_HEIGHT).find_map(|line| {
+            (0..BOARD_WIDTH).find_map(|col| {
+                let mut covered = cells(piece, rotation, line as i32, col as i32)
+                    .map(|(line, col)| (line as usize, col as usize));
+                covered.sort();
+                (covered == target).then_some(Placement {
+                    piece,
+                    rotation,
+                    line,
+                    col,
+                })
+            })
+        })
+    })
+}
+
+// where to put the turn's piece, or the piece it could hold instead
+fn plan(turn: &Turn) -> Option<Target> {
+    let mut candidates = vec![(false, turn.piece, turn.start)];
+    let other = turn.hold.or_else(|| turn.queue.first().copied());
+    if let Some(other) = other.filter(|other| *other != turn.piece) {
+        candidates.push((true, other, SPAWN));
+    }
+    if let Some(target) = perfect_clear(turn, &candidates) {
+        return Some(target);
+    }
+
+    // every spot each piece can go, best first
+    let mut targets: Vec<(bool, Piece, Position, Cells, f64)> = Vec::new();
+    for (swap, piece, start) in candidates.iter().copied() {
+        for cells in reachable(&turn.board, piece) {
+            let score = evaluate(&turn.board, &cells);
+            targets.push((swap, piece, start, cells, score));
+        }
+    }
+    targets.sort_by(|a, b| b.4.total_cmp(&a.4));
+    targets
+        .into_iter()
+        .find(|(_, piece, start, cells, _)| find_path(&turn.board, *piece, *start, cells).is_some())
+        .map(|(swap, _, _, cells, _)| (swap, cells))
+}
+
+// a move that still leaves a perfect clear with the pieces that follow, if
+// the stack is low enough to look for one
+fn perfect_clear(turn: &Turn, candidates: &[(bool, Piece, Position)]) -> Option<Target> {
+    let stack = turn
+        .board
+        .iter()
+        .position(|line| line.iter().any(|cell| *cell != Piece::None))
+        .map_or(0, |line| BOARD_HEIGHT - line);
+    if stack > PERFECT_CLEAR_HEIGHT {
+        return None;
+    }
+    let mut state = SolverState::new(0);
+    state.board = turn.board;
+    state.queue = turn.queue.iter().copied().collect();
+    state.piece_active = turn.piece;
+    state.swap_piece = turn.hold.unwrap_or(Piece::None);
+    let solutions = Solver::new().solve(&state).unwrap_or_default();
+
+    // solutions don't say which order their pieces go in, so check that the
+    // rest can still be placed after each one
+    let mut tried = Vec::new();
+    for solution in solutions.iter() {
+        for (piece, cells) in solution.pieces() {
+            for (swap, candidate, start) in candidates.iter().copied() {
+                if candidate != *piece || tried.contains(&(swap, *cells)) {
+                    continue;
+                }
+                tried.push((swap, *cells));
+                if find_path(&turn.board, candidate, start, cells).is_some()
+                    && still_clears(&state, swap, cells)
+                {
+                    return Some((swap, *cells));
+                }
+            }
+        }
+    }
+    None
+}
+
+// whether `state` can still be perfect cleared after placing on `cells`,
+// holding first if `swap` is set
+fn still_clears(state: &SolverState, swap: bool, cells: &Cells) -> bool {
+    let (board, _) = place(&state.board, Piece::Garbage, cells);
+    if board.iter().flatten().all(|cell| *cell == Piece::None) {
+        return true;
+    }
+    let mut next = state.clone();
+    next.board = board;
+    if swap {
+        if next.swap_piece == Piece::None {
+            next.queue.pop_front();
+        }
+        next.swap_piece = state.piece_active;
+    }
+    next.piece_active = next.queue.pop_front().unwrap_or(Piece::None);
+    Solver::new()
+        .solve(&next)
+        .is_ok_and(|solutions| !solutions.is_empty())
+}
+
+// `board` with `piece` on `cells` and full lines cleared, along with how many
+// lines that cleared
+pub fn place(board: &Board, piece: Piece, cells: &Cells) -> (Board, usize) {
+    let mut placed = *board;
+    for (line, col) in cells {
+        placed[*line][*col] = piece;
+    }
+    let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+    let rows: Vec<_> = placed
+        .iter()
+        .filter(|line| line.contains(&Piece::None))
+        .collect();
+    for (line, row) in board.iter_mut().rev().zip(rows.iter().rev()) {
+        *line = **row;
+    }
+    (board, BOARD_HEIGHT - rows.len())
+}
+
+// how good `board` looks after placing a piece on `cells` and clearing lines,
+// weighing the stack's height, holes and bumpiness against lines cleared
+fn evaluate(board: &Board, cells: &Cells) -> f64 {
+    let (board, cleared) = place(board, Piece::Garbage, cells);
+    let mut heights = [0; BOARD_WIDTH];
+    let mut holes = 0;
+    for (col, height) in heights.iter_mut().enumerate() {
+        if let Some(top) = board.iter().position(|line| line[col] != Piece::None) {
+            *height = BOARD_HEIGHT - top;
+            holes += board[top..]
+                .iter()
+                .filter(|line| line[col] == Piece::None)
+                .count();
+        }
+    }
+    let height: usize = heights.iter().sum();
+    let bumpiness: usize = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+    -0.51 * height as f64 + 0.76 * cleared as f64 - 0.36 * holes as f64 - 0.18 * bumpiness as f64
+}
+
+// the fewest key presses moving `piece` from `start` to where it lands on
+// `goal`, not counting the hard drop
+fn find_path(board: &Board, piece: Piece, start: Position, goal: &Cells) -> Option<Vec<Action>> {
+    let mut goal = *goal;
+    goal.sort();
+    let fits = |position| fits_board(board, piece, position);
+    let drop = |position| solver::drop(fits, position);
+
+    if !fits(start) {
+        return None;
+    }
+    // how each position was first reached
+    let mut parents: HashMap<Position, Option<(Position, Action)>> = HashMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(position) = queue.pop_front() {
+        let (rot, line, col) = drop(position);
+        let mut landed = cells(piece, rot, line, col).map(|(l, c)| (l as usize, c as usize));
+        landed.sort();
+        if landed == goal {
+            let mut actions = Vec::new();
+            let mut at = position;
+            while let Some((from, action)) = parents[&at] {
+                actions.push(action);
+                at = from;
+            }
+            actions.reverse();
+            return Some(actions);
+        }
+
+        let mut moves = solver::moves(piece, position, fits);
+        moves.push((drop(position), Action::SoftDrop));
+        for (next, action) in moves {
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(Some((position, action)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::BotSettings;
+    use crate::tetris::fixtures::{board, game};
+    use std::time::{Duration, Instant};
+
+    fn bot(pps: f64, mistake_rate: u32) -> Bot {
+        let settings = Settings {
+            bot: BotSettings { pps, mistake_rate },
+            ..Settings::default()
+        };
+        Bot::new(&settings, 0)
+    }
+
+    // updates the bot at `timestamp` until it places a piece or has a plan
+    // ready for the next one
+    fn settle(bot: &mut Bot, game: &mut Tetris, timestamp: Timestamp) {
+        let start = Instant::now();
+        let placed = game.stats().pieces;
+        loop {
+            bot.update(game, timestamp);
+            let pieces = game.stats().pieces;
+            if pieces != placed || matches!(bot.plan, Some((count, _)) if count == pieces) {
+                return;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn takes_the_perfect_clear() {
+        // only an upright i fills the well, and it is in the hold
+        let mut game = game(None, 0);
+        game.load_position(board(4, 0..1), Some(Piece::I), &[Piece::S, Piece::Z], 0);
+
+        settle(&mut bot(2.0, 0), &mut game, 0);
+        assert_eq!(game.stats().perfect_clears, 1);
+    }
+
+    #[test]
+    fn waits_between_pieces() {
+        let mut game = game(None, 3);
+        let mut bot = bot(2.0, 50);
+        for (timestamp, pieces) in [(0, 1), (499, 1), (500, 2), (600, 2), (1000, 3)] {
+            settle(&mut bot, &mut game, timestamp);
+            assert_eq!(game.stats().pieces, pieces);
+        }
+    }
+}
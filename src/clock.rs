@@ -0,0 +1,139 @@
+use std::time::Instant;
+
+// time in ms since the clock started. The engine only ever sees these, so the
+// same timestamps always produce the same game.
+pub type Timestamp = u64;
+
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+// wall clock for live play; monotonic, so it never goes backwards
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock::new()
+    }
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Timestamp {
+        self.start.elapsed().as_millis() as Timestamp
+    }
+}
+
+// the clock a live game is played by. it holds at 0 through a countdown
+// before the game, and stands still while paused, so gravity, lock delay, das
+// and timed modes all wait along with it
+pub struct GameClock<C: Clock> {
+    clock: C,
+    origin: Timestamp, // wall time at which game time was 0, less time spent paused
+    paused_at: Option<Timestamp>, // game time when paused
+}
+
+impl<C: Clock> GameClock<C> {
+    pub fn new(clock: C, countdown: Timestamp) -> GameClock<C> {
+        let origin = clock.now() + countdown;
+        GameClock {
+            clock,
+            origin,
+            paused_at: None,
+        }
+    }
+
+    // takes game time back to 0, counting down `countdown` ms first
+    pub fn restart(&mut self, countdown: Timestamp) {
+        self.origin = self.clock.now() + countdown;
+        self.paused_at = None;
+    }
+
+    // the seconds left to count down, rounded up, if the game hasn't started
+    pub fn countdown(&self) -> Option<Timestamp> {
+        let now = self.clock.now();
+        if self.paused_at.is_some() || now >= self.origin {
+            return None;
+        }
+        Some((self.origin - now).div_ceil(1000))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    // pausing waits for the countdown to finish, as there's nothing to stop yet
+    pub fn toggle_pause(&mut self) {
+        match self.paused_at.take() {
+            Some(paused_at) => self.origin = self.clock.now() - paused_at,
+            None if self.countdown().is_none() => self.paused_at = Some(self.now()),
+            None => {}
+        }
+    }
+}
+
+impl<C: Clock> Clock for GameClock<C> {
+    fn now(&self) -> Timestamp {
+        self.paused_at
+            .unwrap_or_else(|| self.clock.now().saturating_sub(self.origin))
+    }
+}
+
+// formats a duration as m:ss.s
+pub fn format_time(millis: Timestamp) -> String {
+    format!(
+        "{}:{:02}.{}",
+        millis / 60000,
+        millis / 1000 % 60,
+        millis / 100 % 10
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // wall time that only moves when told to
+    struct ManualClock(Rc<Cell<Timestamp>>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Timestamp {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn game_time_holds_through_the_countdown_and_pauses() {
+        let wall = Rc::new(Cell::new(500));
+        let mut clock = GameClock::new(ManualClock(wall.clone()), 3000);
+        assert_eq!((clock.now(), clock.countdown()), (0, Some(3)));
+        wall.set(2600);
+        assert_eq!((clock.now(), clock.countdown()), (0, Some(1)));
+        // too early to pause
+        clock.toggle_pause();
+        assert!(!clock.is_paused());
+
+        wall.set(4000);
+        assert_eq!((clock.now(), clock.countdown()), (500, None));
+        clock.toggle_pause();
+        wall.set(60000);
+        assert_eq!(clock.now(), 500);
+        clock.toggle_pause();
+        wall.set(60250);
+        assert_eq!(clock.now(), 750);
+
+        clock.restart(0);
+        assert_eq!((clock.now(), clock.countdown()), (0, None));
+    }
+}
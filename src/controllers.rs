@@ -0,0 +1,55 @@
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::{GameControllerSubsystem, Sdl};
+
+use crate::input_manager::InputManager;
+
+// the game controllers plugged in. sdl only reports a controller's buttons
+// while it's open, and reports the ones plugged in before it started as if
+// they were plugged in then
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<(GameController, usize)>, // each with the player it was handed to
+    status: Option<String>,             // why the last controller plugged in couldn't be used
+}
+
+impl Controllers {
+    pub fn new(sdl: &Sdl) -> Result<Controllers, String> {
+        Ok(Controllers {
+            subsystem: sdl.game_controller()?,
+            open: Vec::new(),
+            status: None,
+        })
+    }
+
+    // opens and closes controllers as they come and go. each one opened is
+    // handed to whichever of `input_managers` has the fewest, so players in
+    // versus get one each. the input managers let go of an unplugged
+    // controller themselves, when they're given the event
+    pub fn process_event(&mut self, event: &Event, input_managers: &mut [InputManager]) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    let player = (0..input_managers.len())
+                        .min_by_key(|player| self.open.iter().filter(|c| c.1 == *player).count())
+                        .unwrap_or(0);
+                    if let Some(input_manager) = input_managers.get_mut(player) {
+                        input_manager.add_controller(controller.instance_id(), &controller.name());
+                    }
+                    self.open.push((controller, player));
+                    self.status = None;
+                }
+                Err(e) => self.status = Some(format!("COULD NOT OPEN CONTROLLER {}: {}", which, e)),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.open
+                    .retain(|(controller, _)| controller.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn hud(&self) -> Vec<String> {
+        self.status.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+
+use crate::clock::Timestamp;
+use crate::fumen::piece_to_char;
+use crate::history_manager::HistoryManager;
+use crate::practice::{self, Attempt, PracticeLog};
+use crate::setups::Setup;
+use crate::solver::PlacedPiece;
+use crate::tetris::{Piece, Tetris};
+
+// drills whichever setup from the library the bag can build, preferring the
+// one most due for practice. every placement is checked against the setup,
+// and one that leaves it unbuildable is undone through the history so the
+// placement that should have been made can be shown
+pub struct Drill {
+    library: Vec<Setup>,
+    previews: usize,
+    setup: Option<usize>,
+    checked: usize,   // placements of the game checked so far
+    first_try: usize, // pieces of the setup placed correctly on the first attempt
+    mistakes: usize,
+    finesse_faults: u32, // as of the last placement checked
+    missed: bool,        // whether the piece being placed was placed wrong before
+    hint: Option<PlacedPiece>,
+    finished: bool,
+    started: Timestamp,
+    review: Option<u64>, // days until the setup is drilled again, once it's built
+}
+
+impl Drill {
+    // only the active piece, the hold and `previews` pieces of the queue are
+    // looked at, the same as the player sees
+    pub fn new(previews: u32) -> Drill {
+        Drill {
+            library: Setup::library(),
+            previews: previews as usize,
+            setup: None,
+            checked: 0,
+            first_try: 0,
+            mistakes: 0,
+            finesse_faults: 0,
+            missed: false,
+            hint: None,
+            finished: false,
+            started: 0,
+            review: None,
+        }
+    }
+
+    // picks the setup to drill for a freshly started game. leaving a setup
+    // unfinished counts as failing it
+    pub fn start(&mut self, game: &Tetris, log: &mut PracticeLog, timestamp: Timestamp) {
+        if let Some(index) = self.setup.filter(|_| !self.finished && self.checked > 0) {
+            let attempt = Attempt {
+                success: false,
+                time: timestamp - self.started,
+                mistakes: self.mistakes as u32,
+                finesse_faults: self.finesse_faults,
+            };
+            log.record(
+                &practice::setup_key(&self.library[index].name),
+                &attempt,
+                practice::today(),
+            );
+        }
+
+        let board = game.board_without_active();
+        let pieces = pieces(game, self.previews);
+        let hold = game.get_hold();
+        // a setup saving a piece is only drilled with that piece in sight
+        let in_sight = |piece| hold == Some(piece) || pieces.contains(&piece);
+        self.setup = (0..self.library.len())
+            .filter(|i| {
+                let setup = &self.library[*i];
+                setup.keep.is_none_or(in_sight) && setup.plan(&board, &pieces, hold, true).is_some()
+            })
+            .min_by_key(|i| log.due(&practice::setup_key(&self.library[*i].name)));
+        self.checked = game.placements().len();
+        self.first_try = 0;
+        self.mistakes = 0;
+        self.finesse_faults = 0;
+        self.missed = false;
+        self.hint = None;
+        self.finished = false;
+        self.started = timestamp;
+        self.review = None;
+    }
+
+    pub fn update(
+        &mut self,
+        game: &mut Tetris,
+        history: &RefCell<HistoryManager>,
+        log: &mut PracticeLog,
+        timestamp: Timestamp,
+    ) {
+        let setup = match self.setup {
+            Some(index) => &self.library[index],
+            None => return,
+        };
+        if self.finished || game.placements().len() <= self.checked {
+            return;
+        }
+        self.checked = game.placements().len();
+        self.finesse_faults = game.finesse_faults();
+
+        let plan = setup.plan(
+            &game.board_without_active(),
+            &pieces(game, self.previews),
+            game.get_hold(),
+            true,
+        );
+        if let Some(plan) = plan {
+            if !self.missed {
+                self.first_try += 1;
+            }
+            self.missed = false;
+            self.hint = None;
+            self.finished = plan.is_empty();
+            if self.finished {
+                let attempt = Attempt {
+                    success: true,
+                    time: timestamp - self.started,
+                    mistakes: self.mistakes as u32,
+                    finesse_faults: self.finesse_faults,
+                };
+                let record = log.record(
+                    &practice::setup_key(&setup.name),
+                    &attempt,
+                    practice::today(),
+                );
+                self.review = Some(record.interval);
+            }
+            return;
+        }
+
+        // go back to before the placement, keeping it as a branch of the history
+        let node = history.borrow_mut().undo();
+        if let Some(node) = node {
+            game.rewind(&node, timestamp);
+            self.checked -= 1;
+            self.mistakes += 1;
+            self.missed = true;
+            self.hint = setup
+                .plan(
+                    &game.board_without_active(),
+                    &pieces(game, self.previews),
+                    game.get_hold(),
+                    true,
+                )
+                .and_then(|plan| plan.first().copied());
+        }
+    }
+
+    // the placement to show after a mistake
+    pub fn hint(&self) -> Option<PlacedPiece> {
+        self.hint
+    }
+
+    pub fn hud(&self, game: &Tetris, history: &HistoryManager) -> Vec<String> {
+        let setup = match self.setup {
+            Some(index) => &self.library[index],
+            None => return vec!["NO SETUP FITS THIS BAG".to_string()],
+        };
+        let mut text = vec![
+            "DRILL".to_string(),
+            setup.name.to_uppercase(),
+            format!("MISTAKES {}", self.mistakes),
+            format!("FINESSE {}", game.finesse_faults()),
+        ];
+        if let Some(keep) = setup.keep {
+            text.push(format!("SAVE THE {}", piece_to_char(keep)));
+        }
+        if self.hint.is_some() {
+            text.push(format!("TRIED {} HERE", history.branches()));
+        }
+        if self.finished {
+            text.push(format!(
+                "BUILT {}%",
+                self.first_try * 100 / setup.targets().len()
+            ));
+        }
+        if let Some(days) = self.review {
+            text.push(format!("AGAIN IN {}D", days));
+        }
+        text
+    }
+}
+
+// the active piece followed by the first `previews` of the queue
+fn pieces(game: &Tetris, previews: usize) -> Vec<Piece> {
+    let (active, ..) = game.active_piece();
+    std::iter::once(active)
+        .chain(game.queue().iter().take(previews).copied())
+        .filter(|piece| *piece != Piece::None)
+        .collect()
+}
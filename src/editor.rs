@@ -0,0 +1,144 @@
+use sdl2::event::Event;
+use sdl2::keyboard::{Mod, Scancode};
+use sdl2::mouse::MouseButton;
+
+use crate::clock::Timestamp;
+use crate::fumen::piece_to_char;
+use crate::render::Renderer;
+use crate::tetris::{Piece, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+const BRUSHES: [Piece; 8] = [
+    Piece::T,
+    Piece::I,
+    Piece::J,
+    Piece::L,
+    Piece::S,
+    Piece::Z,
+    Piece::O,
+    Piece::Garbage,
+];
+
+// edits a paused board. the left mouse button paints with the brush and the
+// right one erases, 1-8 pick the brush, piece letters add to the queue (or
+// set the hold with shift), backspace removes the last queued piece, delete
+// clears the hold and c clears the board. g pushes a garbage line up from the
+// bottom, or with shift queues one to come in once play resumes
+pub struct Editor {
+    brush: Piece,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            brush: Piece::Garbage,
+        }
+    }
+
+    pub fn process_event(
+        &mut self,
+        event: &Event,
+        game: &mut Tetris,
+        renderer: &Renderer,
+        timestamp: Timestamp,
+    ) {
+        match *event {
+            Event::MouseButtonDown {
+                mouse_btn, x, y, ..
+            } => match mouse_btn {
+                MouseButton::Left => self.paint(game, renderer, x, y, self.brush),
+                MouseButton::Right => self.paint(game, renderer, x, y, Piece::None),
+                _ => {}
+            },
+            Event::MouseMotion {
+                mousestate, x, y, ..
+            } => {
+                if mousestate.left() {
+                    self.paint(game, renderer, x, y, self.brush);
+                } else if mousestate.right() {
+                    self.paint(game, renderer, x, y, Piece::None);
+                }
+            }
+            Event::KeyDown {
+                scancode: Some(scancode),
+                keymod,
+                ..
+            } => {
+                let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                if let Some(piece) = brush_for(scancode) {
+                    self.brush = piece;
+                } else if let Some(piece) = piece_for(scancode) {
+                    if shift {
+                        game.set_hold(Some(piece));
+                    } else {
+                        let mut queue: Vec<Piece> = game.queue().iter().copied().collect();
+                        queue.push(piece);
+                        game.set_queue(&queue);
+                    }
+                } else {
+                    match scancode {
+                        Scancode::Backspace => {
+                            let mut queue: Vec<Piece> = game.queue().iter().copied().collect();
+                            queue.pop();
+                            game.set_queue(&queue);
+                        }
+                        Scancode::Delete => game.set_hold(None),
+                        Scancode::C => game.set_board([[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT]),
+                        Scancode::G if shift => game.receive_garbage(1, timestamp),
+                        Scancode::G => game.insert_garbage(1),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn paint(&self, game: &mut Tetris, renderer: &Renderer, x: i32, y: i32, piece: Piece) {
+        if let Some((line, col)) = renderer.board_cell_at(x, y) {
+            game.set_cell(line, col, piece);
+        }
+    }
+
+    pub fn hud(&self, game: &Tetris) -> Vec<String> {
+        vec![
+            "EDITING".to_string(),
+            format!("BRUSH {}", piece_to_char(self.brush)),
+            format!("QUEUE {}", game.queue().len()),
+            format!("INCOMING {}", game.incoming_garbage()),
+        ]
+    }
+}
+
+fn brush_for(scancode: Scancode) -> Option<Piece> {
+    let index = match scancode {
+        Scancode::Num1 => 0,
+        Scancode::Num2 => 1,
+        Scancode::Num3 => 2,
+        Scancode::Num4 => 3,
+        Scancode::Num5 => 4,
+        Scancode::Num6 => 5,
+        Scancode::Num7 => 6,
+        Scancode::Num8 => 7,
+        _ => return None,
+    };
+    Some(BRUSHES[index])
+}
+
+fn piece_for(scancode: Scancode) -> Option<Piece> {
+    match scancode {
+        Scancode::T => Some(Piece::T),
+        Scancode::I => Some(Piece::I),
+        Scancode::J => Some(Piece::J),
+        Scancode::L => Some(Piece::L),
+        Scancode::S => Some(Piece::S),
+        Scancode::Z => Some(Piece::Z),
+        Scancode::O => Some(Piece::O),
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::scoring::Spin;
+use crate::solver::Placement;
+use crate::tetris::{Direction, Piece, Rotation};
+
+// everything that happens to the active piece and the board, in the order it
+// happens. a hard drop reports the moves down before the drop itself
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameEvent {
+    Spawned { piece: Piece },
+    Moved { direction: Direction },
+    Rotated { rotation: Rotation, kick: usize }, // kick is the index of the kick test used
+    Held { piece: Piece },                       // the piece that went into the hold
+    HardDropped { lines: usize },                // how far the piece fell
+    Locked { placement: Placement },
+    LinesCleared { lines: u32, spin: Spin },
+    PerfectClear,
+    ToppedOut,
+}
+
+// everyone listening to a game. a clone of a game, e.g. one the bot looks
+// ahead with, starts out without listeners so it doesn't report moves that
+// never happened
+#[derive(Default)]
+pub struct Subscribers {
+    senders: Vec<Sender<GameEvent>>,
+}
+
+impl Subscribers {
+    pub fn subscribe(&mut self) -> Receiver<GameEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    // sends `event` to everyone still listening, forgetting the rest
+    pub fn emit(&mut self, event: GameEvent) {
+        self.senders.retain(|sender| sender.send(event).is_ok());
+    }
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Subscribers::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetris::fixtures::{board, game};
+    use crate::tetris::{Piece, BOARD_HEIGHT};
+
+    #[test]
+    fn reports_clears_as_they_happen() {
+        let mut game = game(None, 0);
+        // an i clears the top line, and another the bottom one
+        let mut board = board(2, 3..7);
+        board[BOARD_HEIGHT - 1][3..7].fill(Piece::Garbage);
+        board[BOARD_HEIGHT - 1][0..4].fill(Piece::None);
+        game.load_position(board, None, &[Piece::I, Piece::I, Piece::T], 0);
+        let events = game.subscribe();
+
+        game.move_active(Direction::Right);
+        game.rot_active(Rotation::Flip);
+        game.harddrop(0);
+        let received: Vec<GameEvent> = events.try_iter().collect();
+        let drop = BOARD_HEIGHT - 5;
+        assert_eq!(
+            received[0],
+            GameEvent::Moved {
+                direction: Direction::Right
+            }
+        );
+        assert_eq!(
+            received[1],
+            GameEvent::Rotated {
+                rotation: Rotation::Flip,
+                kick: 0
+            }
+        );
+        assert_eq!(received[drop + 2], GameEvent::HardDropped { lines: drop });
+        assert_eq!(
+            received[drop + 4..],
+            [
+                GameEvent::LinesCleared {
+                    lines: 1,
+                    spin: Spin::None
+                },
+                GameEvent::Spawned { piece: Piece::I },
+            ]
+        );
+
+        while game.move_active(Direction::Left) {}
+        game.harddrop(0);
+        let received: Vec<GameEvent> = events.try_iter().collect();
+        assert!(received.ends_with(&[
+            GameEvent::LinesCleared {
+                lines: 1,
+                spin: Spin::None
+            },
+            GameEvent::PerfectClear,
+            GameEvent::Spawned { piece: Piece::T },
+        ]));
+    }
+
+    #[test]
+    fn clones_and_dropped_receivers_are_not_reported_to() {
+        let mut game = game(None, 0);
+        let events = game.subscribe();
+        let mut clone = game.clone();
+        clone.swap();
+        assert_eq!(events.try_iter().count(), 0);
+
+        game.swap();
+        assert!(matches!(events.try_recv(), Ok(GameEvent::Held { .. })));
+        drop(events);
+        game.harddrop(0);
+    }
+}
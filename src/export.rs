@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::clock::Timestamp;
+use crate::finesse;
+use crate::history_manager::HistoryNode;
+use crate::session::SessionStats;
+use crate::tetris::{Piece, BOARD_WIDTH};
+
+pub const EXPORT_DIR: &str = "exports";
+
+const PIECE_COLUMNS: [&str; 12] = [
+    "piece_number",
+    "time",
+    "duration",
+    "piece",
+    "rotation",
+    "line",
+    "col",
+    "inputs",
+    "min_inputs",
+    "finesse_fault",
+    "lines",
+    "perfect_clear",
+];
+
+const SESSION_COLUMNS: [&str; 10] = [
+    "started",
+    "time",
+    "pieces",
+    "keys",
+    "finesse_faults",
+    "lines",
+    "holds",
+    "perfect_clears",
+    "attempts",
+    "pc_time",
+];
+
+// one locked piece, as it went
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct PieceRecord {
+    pub piece_number: usize,
+    pub time: Timestamp,     // ms into the game when the piece locked
+    pub duration: Timestamp, // ms since the piece before it locked
+    pub piece: String,
+    pub rotation: String,
+    pub line: usize,
+    pub col: usize,
+    pub inputs: u32,
+    pub min_inputs: Option<u32>, // none if the piece couldn't have been placed there
+    pub finesse_fault: bool,
+    pub lines: u32,
+    pub perfect_clear: bool,
+}
+
+impl PieceRecord {
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.piece_number.to_string(),
+            self.time.to_string(),
+            self.duration.to_string(),
+            self.piece.clone(),
+            self.rotation.clone(),
+            self.line.to_string(),
+            self.col.to_string(),
+            self.inputs.to_string(),
+            self.min_inputs.map_or(String::new(), |min| min.to_string()),
+            self.finesse_fault.to_string(),
+            self.lines.to_string(),
+            self.perfect_clear.to_string(),
+        ]
+    }
+}
+
+// the pieces placed on a branch of the history, oldest first
+pub fn pieces(branch: &[&HistoryNode]) -> Vec<PieceRecord> {
+    let mut last_lock = 0;
+    let mut records = Vec::new();
+    for (index, node) in branch.iter().enumerate() {
+        let placement = node.placement;
+        // the state was recorded with the piece in place but no lines cleared
+        let board = node.state.board;
+        let mut before = board;
+        for (line, col) in placement.cells() {
+            before[line][col] = Piece::None;
+        }
+        let min_inputs = finesse::min_inputs(&before, &placement);
+        let full = |line: &[Piece; BOARD_WIDTH]| line.iter().all(|cell| *cell != Piece::None);
+        let empty = |line: &[Piece; BOARD_WIDTH]| line.iter().all(|cell| *cell == Piece::None);
+        records.push(PieceRecord {
+            piece_number: index + 1,
+            time: node.timestamp,
+            duration: node.timestamp.saturating_sub(last_lock),
+            piece: format!("{:?}", placement.piece),
+            rotation: format!("{:?}", placement.rotation),
+            line: placement.line,
+            col: placement.col,
+            inputs: node.inputs,
+            min_inputs,
+            finesse_fault: min_inputs.is_some_and(|min| node.inputs > min),
+            lines: board.iter().filter(|line| full(line)).count() as u32,
+            perfect_clear: board.iter().all(|line| full(line) || empty(line)),
+        });
+        last_lock = node.timestamp;
+    }
+    records
+}
+
+fn session_row(stats: &SessionStats) -> Vec<String> {
+    vec![
+        stats.started.to_string(),
+        stats.time.to_string(),
+        stats.pieces.to_string(),
+        stats.keys.to_string(),
+        stats.finesse_faults.to_string(),
+        stats.lines.to_string(),
+        stats.holds.to_string(),
+        stats.perfect_clears.to_string(),
+        stats.attempts.to_string(),
+        stats.pc_time.to_string(),
+    ]
+}
+
+// none of the values have commas, quotes or line breaks, so nothing is quoted
+fn csv(columns: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut csv = columns.join(",") + "\n";
+    for row in rows {
+        csv += &(row.join(",") + "\n");
+    }
+    csv
+}
+
+pub fn pieces_csv(pieces: &[PieceRecord]) -> String {
+    csv(&PIECE_COLUMNS, pieces.iter().map(PieceRecord::row))
+}
+
+pub fn sessions_csv(sessions: &[SessionStats]) -> String {
+    csv(&SESSION_COLUMNS, sessions.iter().map(session_row))
+}
+
+// writes the pieces and sessions to `dir` as <name>-pieces.csv,
+// <name>-pieces.json, <name>-sessions.csv and <name>-sessions.json, for
+// spreadsheets and notebooks. returns the files written
+pub fn export<P: AsRef<Path>>(
+    dir: P,
+    name: &str,
+    pieces: &[PieceRecord],
+    sessions: &[SessionStats],
+) -> Result<Vec<PathBuf>, String> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let files = [
+        ("pieces.csv", pieces_csv(pieces)),
+        (
+            "pieces.json",
+            serde_json::to_string_pretty(pieces).map_err(|e| e.to_string())?,
+        ),
+        ("sessions.csv", sessions_csv(sessions)),
+        (
+            "sessions.json",
+            serde_json::to_string_pretty(sessions).map_err(|e| e.to_string())?,
+        ),
+    ];
+    let mut paths = Vec::new();
+    for (suffix, contents) in files {
+        let path = dir.join(format!("{}-{}", name, suffix));
+        fs::write(&path, contents)
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history_manager::HistoryManager;
+    use crate::tetris::fixtures::{board, game};
+    use crate::tetris::{Direction, Rotation};
+    use std::cell::RefCell;
+
+    #[test]
+    fn records_a_perfect_clear_with_wasted_inputs() {
+        let history = RefCell::new(HistoryManager::new());
+        let mut game = game(Some(&history), 0);
+        game.load_position(board(2, 4..6), None, &[Piece::O, Piece::T], 0);
+
+        // right and back again, when the o spawns right over the gap
+        game.count_input();
+        game.move_active(Direction::Right);
+        game.count_input();
+        game.move_active(Direction::Left);
+        game.harddrop(1500);
+
+        let pieces = pieces(&history.borrow().branch());
+        assert_eq!(pieces.len(), 1);
+        let piece = &pieces[0];
+        assert_eq!((piece.time, piece.duration), (1500, 1500));
+        assert_eq!((piece.inputs, piece.min_inputs), (2, Some(0)));
+        assert!(piece.finesse_fault);
+        assert_eq!(piece.lines, 2);
+        assert!(piece.perfect_clear);
+
+        let csv = pieces_csv(&pieces);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(PIECE_COLUMNS.join(",").as_str()));
+        assert_eq!(
+            lines.next(),
+            Some(
+                format!(
+                    "1,1500,1500,O,{:?},{},{},2,0,true,2,true",
+                    Rotation::Spawn,
+                    piece.line,
+                    piece.col
+                )
+                .as_str()
+            )
+        );
+    }
+}
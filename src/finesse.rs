@@ -0,0 +1,87 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::solver::{self, cells, fits_board, Board, Placement, Position};
+use crate::tetris::Rotation;
+
+const SPAWN: Position = (Rotation::Spawn, 3, 4);
+
+// the fewest key presses needed to place a piece at `target` on `board`,
+// counting taps, das to a wall, rotations and soft drops to the floor but not
+// the hard drop itself. gravity is ignored, as finesse usually assumes
+pub fn min_inputs(board: &Board, target: &Placement) -> Option<u32> {
+    let piece = target.piece;
+    let mut goal = target.cells();
+    goal.sort();
+    let fits = |position| fits_board(board, piece, position);
+    let drop = |position| solver::drop(fits, position);
+    let slide = |(rot, line, mut col): Position, step: i32| {
+        while fits((rot, line, col + step)) {
+            col += step;
+        }
+        (rot, line, col)
+    };
+
+    if !fits(SPAWN) {
+        return None;
+    }
+    let mut seen = HashSet::from([SPAWN]);
+    let mut queue = VecDeque::from([(SPAWN, 0)]);
+    while let Some((position, inputs)) = queue.pop_front() {
+        let (rot, line, col) = drop(position);
+        let mut landed = cells(piece, rot, line, col).map(|(l, c)| (l as usize, c as usize));
+        landed.sort();
+        if landed == goal {
+            return Some(inputs);
+        }
+
+        // taps and rotations, das to either wall and soft drop to the floor
+        let next = solver::moves(piece, position, fits)
+            .into_iter()
+            .map(|(next, _)| next)
+            .chain([slide(position, -1), slide(position, 1), drop(position)]);
+        for next in next {
+            if seen.insert(next) {
+                queue.push_back((next, inputs + 1));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetris::{Piece, BOARD_HEIGHT, BOARD_WIDTH};
+
+    const EMPTY: Board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+
+    fn inputs(piece: Piece, rotation: Rotation, col: usize) -> Option<u32> {
+        // drop the piece from above to find the line it lands on
+        let mut line = 3;
+        let fits = |line: i32| {
+            cells(piece, rotation, line, col as i32)
+                .iter()
+                .all(|(l, _)| *l < BOARD_HEIGHT as i32)
+        };
+        while fits(line + 1) {
+            line += 1;
+        }
+        let target = Placement {
+            piece,
+            rotation,
+            line: line as usize,
+            col,
+        };
+        min_inputs(&EMPTY, &target)
+    }
+
+    #[test]
+    fn counts_taps_das_and_rotations() {
+        assert_eq!(inputs(Piece::T, Rotation::Spawn, 4), Some(0));
+        assert_eq!(inputs(Piece::T, Rotation::Spawn, 3), Some(1));
+        assert_eq!(inputs(Piece::T, Rotation::Spawn, 1), Some(1)); // das left
+        assert_eq!(inputs(Piece::T, Rotation::Spawn, 2), Some(2));
+        assert_eq!(inputs(Piece::T, Rotation::Flip, 4), Some(1));
+        assert_eq!(inputs(Piece::O, Rotation::Spawn, 8), Some(1));
+    }
+}
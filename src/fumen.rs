@@ -0,0 +1,802 @@
+// Encoder and decoder for fumen (v115) strings, the format the community uses
+// to share boards and setups. The format follows knewjade's tetris-fumen.
+
+use crate::clock::Timestamp;
+use crate::history_manager::HistoryNode;
+use crate::tetris::{Piece, Rotation, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+pub type Board = [[Piece; BOARD_WIDTH]; BOARD_HEIGHT];
+
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_CHAR_VALUES: u64 = COMMENT_TABLE.len() as u64 + 1;
+const FIELD_TOP: usize = 23; // rows in a v115 field, not counting the garbage row
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * BOARD_WIDTH;
+const QUIZ_PREFIX: &str = "#Q=";
+
+#[derive(Clone, Copy)]
+pub struct Operation {
+    pub piece: Piece,
+    pub rotation: Rotation,
+    pub line: usize, // position of the piece, as used by Tetris
+    pub col: usize,
+}
+
+#[derive(Clone)]
+pub struct Page {
+    pub field: Board,
+    pub operation: Option<Operation>,
+    pub comment: String,
+    pub lock: bool, // whether the operation is placed and lines are cleared for the next page
+    pub rise: bool, // whether the garbage row rises after this page
+    pub mirror: bool,
+}
+
+impl Page {
+    pub fn new(field: Board) -> Page {
+        Page {
+            field,
+            operation: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+}
+
+// the hold, current piece and queue stored in a "#Q=[H](C)QUEUE" comment
+pub struct Quiz {
+    pub hold: Option<Piece>,
+    pub current: Option<Piece>,
+    pub queue: Vec<Piece>,
+}
+
+impl Quiz {
+    pub fn parse(comment: &str) -> Option<Quiz> {
+        let rest = comment.strip_prefix(QUIZ_PREFIX)?.strip_prefix('[')?;
+        let (hold, rest) = rest.split_once(']')?;
+        let (current, queue) = rest.strip_prefix('(')?.split_once(')')?;
+        let piece = |s: &str| s.chars().next().and_then(piece_from_char);
+        Some(Quiz {
+            hold: piece(hold),
+            current: piece(current),
+            queue: queue.chars().map_while(piece_from_char).collect(),
+        })
+    }
+
+    pub fn comment(&self) -> String {
+        let name = |piece: Option<Piece>| piece.map(|p| piece_to_char(p).to_string());
+        format!(
+            "{}[{}]({}){}",
+            QUIZ_PREFIX,
+            name(self.hold).unwrap_or_default(),
+            name(self.current).unwrap_or_default(),
+            self.queue
+                .iter()
+                .map(|p| piece_to_char(*p))
+                .collect::<String>()
+        )
+    }
+}
+
+// the current position as a page: the board, the active piece as the
+// operation and the hold and queue in a quiz comment
+pub fn page_from_game(game: &Tetris) -> Page {
+    let (piece, rotation, line, col) = game.active_piece();
+    let mut page = Page::new(game.board_without_active());
+    if piece != Piece::None {
+        page.operation = Some(Operation {
+            piece,
+            rotation,
+            line,
+            col,
+        });
+    }
+    page.comment = Quiz {
+        hold: game.get_hold(),
+        current: Some(piece).filter(|p| *p != Piece::None),
+        queue: game.get_queue(),
+    }
+    .comment();
+    page
+}
+
+// one page per placement on a branch of the HistoryManager. states are
+// recorded as pieces lock, so each page shows the placed piece before lines
+// are cleared
+pub fn pages_from_history(nodes: &[&HistoryNode]) -> Vec<Page> {
+    nodes
+        .iter()
+        .map(|node| {
+            let state = &node.state;
+            let mut page = Page::new(state.board);
+            let mut queue = state.queue.iter().copied();
+            page.comment = Quiz {
+                hold: Some(state.swap_piece).filter(|p| *p != Piece::None),
+                current: queue.next(),
+                queue: queue.collect(),
+            }
+            .comment();
+            page
+        })
+        .collect()
+}
+
+// sets up a game from a page. the hold and queue come from a quiz comment if
+// there is one, otherwise the page's piece is played first
+pub fn load_page(game: &mut Tetris, page: &Page, timestamp: Timestamp) {
+    let (hold, queue): (Option<Piece>, Vec<Piece>) = match Quiz::parse(&page.comment) {
+        Some(quiz) => (
+            quiz.hold,
+            quiz.current.into_iter().chain(quiz.queue).collect(),
+        ),
+        None => (None, page.operation.iter().map(|op| op.piece).collect()),
+    };
+    game.load_position(page.field, hold, &queue, timestamp);
+}
+
+pub fn piece_from_char(c: char) -> Option<Piece> {
+    match c.to_ascii_uppercase() {
+        'T' => Some(Piece::T),
+        'I' => Some(Piece::I),
+        'J' => Some(Piece::J),
+        'L' => Some(Piece::L),
+        'S' => Some(Piece::S),
+        'Z' => Some(Piece::Z),
+        'O' => Some(Piece::O),
+        _ => None,
+    }
+}
+
+pub fn piece_to_char(piece: Piece) -> char {
+    match piece {
+        Piece::T => 'T',
+        Piece::I => 'I',
+        Piece::J => 'J',
+        Piece::L => 'L',
+        Piece::S => 'S',
+        Piece::Z => 'Z',
+        Piece::O => 'O',
+        Piece::Garbage => 'X',
+        Piece::None => ' ',
+    }
+}
+
+pub fn decode(fumen: &str) -> Result<Vec<Page>, String> {
+    let (version, data) = fumen
+        .trim()
+        .split_once('@')
+        .ok_or("not a fumen string: missing '@'")?;
+    if !matches!(version, "v115" | "m115" | "d115") {
+        return Err(format!("unsupported fumen version \"{}\"", version));
+    }
+    let mut values = Values::parse(data)?;
+
+    let mut pages = Vec::new();
+    let mut prev_field = [0u8; FIELD_BLOCKS];
+    let mut repeat_count = 0;
+    let mut prev_comment = String::new();
+    while !values.is_empty() {
+        // field, stored as runs of differences to the previous page
+        let mut field = prev_field;
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_BLOCKS {
+                let run = values.poll(2)? as usize;
+                let diff = run / FIELD_BLOCKS;
+                let count = run % FIELD_BLOCKS + 1;
+                if diff == 8 && count == FIELD_BLOCKS {
+                    repeat_count = values.poll(1)?;
+                }
+                if diff > 16 || index + count > FIELD_BLOCKS {
+                    return Err("invalid fumen field data".to_string());
+                }
+                for cell in field[index..index + count].iter_mut() {
+                    *cell = (*cell as i32 + diff as i32 - 8).clamp(0, 8) as u8;
+                }
+                index += count;
+            }
+        }
+
+        // operation and flags
+        let mut action = values.poll(3)?;
+        let piece = fumen_piece(action % 8);
+        action /= 8;
+        let rotation = fumen_rotation(action % 4);
+        action /= 4;
+        let position = (action % FIELD_BLOCKS as u64) as usize;
+        action /= FIELD_BLOCKS as u64;
+        let rise = action % 2 == 1;
+        let mirror = action / 2 % 2 == 1;
+        let has_comment = action / 8 % 2 == 1;
+        let lock = action / 16 % 2 != 1;
+
+        let comment = if has_comment {
+            let length = values.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = values.poll(5)?;
+                for _ in 0..4 {
+                    let index = (value % COMMENT_CHAR_VALUES) as usize;
+                    escaped.push(*COMMENT_TABLE.get(index).unwrap_or(&b' ') as char);
+                    value /= COMMENT_CHAR_VALUES;
+                }
+            }
+            escaped.truncate(length);
+            unescape(&escaped)
+        } else {
+            prev_comment.clone()
+        };
+
+        let operation = match piece {
+            Piece::None | Piece::Garbage => None,
+            _ => Some(decode_position(piece, rotation, position)?),
+        };
+
+        // work out the field the next page is based on
+        let mut next_field = field;
+        if lock {
+            if let Some(operation) = operation {
+                put_operation(&mut next_field, &operation)?;
+            }
+            clear_lines(&mut next_field);
+            if rise {
+                next_field.copy_within(BOARD_WIDTH.., 0);
+                next_field[FIELD_BLOCKS - BOARD_WIDTH..].fill(0);
+            }
+            if mirror {
+                mirror_field(&mut next_field);
+            }
+        }
+
+        pages.push(Page {
+            field: to_board(&field),
+            operation,
+            comment: comment.clone(),
+            lock,
+            rise,
+            mirror,
+        });
+        prev_field = next_field;
+        prev_comment = comment;
+    }
+    if pages.is_empty() {
+        return Err("fumen string has no pages".to_string());
+    }
+    Ok(pages)
+}
+
+pub fn encode(pages: &[Page]) -> Result<String, String> {
+    let mut values = Values::default();
+    let mut prev_field = [0u8; FIELD_BLOCKS];
+    let mut prev_comment = String::new();
+    let mut repeat_index: Option<usize> = None;
+    for (i, page) in pages.iter().enumerate() {
+        let field = to_field(&page.field);
+
+        // field as runs of (difference + 8, length - 1)
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for (cell, prev) in field.iter().zip(prev_field.iter()) {
+            let diff = (*cell as i32 - *prev as i32 + 8) as usize;
+            match runs.last_mut() {
+                Some((last, count)) if *last == diff => *count += 1,
+                _ => runs.push((diff, 0)),
+            }
+        }
+        if runs.len() == 1 && runs[0].0 == 8 {
+            // unchanged fields are stored once, followed by a repeat count
+            match repeat_index {
+                Some(index) if values.get(index) < 63 => values.increment(index),
+                _ => {
+                    values.push((8 * FIELD_BLOCKS + FIELD_BLOCKS - 1) as u64, 2);
+                    values.push(0, 1);
+                    repeat_index = Some(values.len() - 1);
+                }
+            }
+        } else {
+            for (diff, count) in runs {
+                values.push((diff * FIELD_BLOCKS + count) as u64, 2);
+            }
+            repeat_index = None;
+        }
+
+        // operation and flags
+        let has_comment = page.comment != prev_comment || (i == 0 && !page.comment.is_empty());
+        let (piece, rotation, position) = match page.operation {
+            Some(operation) => encode_position(&operation)?,
+            None => (0, 0, 0),
+        };
+        let mut action = if page.lock { 0 } else { 1 };
+        action = action * 2 + has_comment as u64;
+        action = action * 2 + 1; // guideline colors
+        action = action * 2 + page.mirror as u64;
+        action = action * 2 + page.rise as u64;
+        action = action * FIELD_BLOCKS as u64 + position as u64;
+        action = action * 4 + rotation;
+        action = action * 8 + piece;
+        values.push(action, 3);
+
+        if has_comment {
+            let escaped = escape(&page.comment);
+            let escaped = &escaped.as_bytes()[..escaped.len().min(4095)];
+            values.push(escaped.len() as u64, 2);
+            for chunk in escaped.chunks(4) {
+                let mut value = 0;
+                for (j, c) in chunk.iter().enumerate() {
+                    let index = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0) as u64;
+                    value += index * COMMENT_CHAR_VALUES.pow(j as u32);
+                }
+                values.push(value, 5);
+            }
+        }
+
+        let mut next_field = field;
+        if page.lock {
+            if let Some(operation) = page.operation {
+                put_operation(&mut next_field, &operation)?;
+            }
+            clear_lines(&mut next_field);
+            if page.rise {
+                next_field.copy_within(BOARD_WIDTH.., 0);
+                next_field[FIELD_BLOCKS - BOARD_WIDTH..].fill(0);
+            }
+            if page.mirror {
+                mirror_field(&mut next_field);
+            }
+        }
+        prev_field = next_field;
+        prev_comment = page.comment.clone();
+    }
+
+    // fumen breaks long strings up with '?' so they wrap in chat clients
+    let data: Vec<char> = values.to_code().chars().collect();
+    let mut fumen = String::from("v115@");
+    for (i, c) in data.iter().enumerate() {
+        if i >= 42 && (i - 42) % 47 == 0 {
+            fumen.push('?');
+        }
+        fumen.push(*c);
+    }
+    Ok(fumen)
+}
+
+// fumen data is a stream of base64 digits, least significant first
+#[derive(Default)]
+struct Values {
+    digits: Vec<u64>,
+    read: usize,
+}
+
+impl Values {
+    fn parse(data: &str) -> Result<Values, String> {
+        let mut digits = Vec::new();
+        for c in data.bytes().filter(|c| *c != b'?') {
+            let digit = ENCODE_TABLE
+                .iter()
+                .position(|t| *t == c)
+                .ok_or_else(|| format!("invalid character '{}' in fumen string", c as char))?;
+            digits.push(digit as u64);
+        }
+        Ok(Values { digits, read: 0 })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.read >= self.digits.len()
+    }
+
+    fn poll(&mut self, count: usize) -> Result<u64, String> {
+        if self.read + count > self.digits.len() {
+            return Err("fumen string ended unexpectedly".to_string());
+        }
+        let mut value = 0;
+        for i in (0..count).rev() {
+            value = value * 64 + self.digits[self.read + i];
+        }
+        self.read += count;
+        Ok(value)
+    }
+
+    fn push(&mut self, mut value: u64, count: usize) {
+        for _ in 0..count {
+            self.digits.push(value % 64);
+            value /= 64;
+        }
+    }
+
+    fn get(&self, index: usize) -> u64 {
+        self.digits[index]
+    }
+
+    fn increment(&mut self, index: usize) {
+        self.digits[index] += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.digits.len()
+    }
+
+    fn to_code(&self) -> String {
+        self.digits
+            .iter()
+            .map(|d| ENCODE_TABLE[*d as usize] as char)
+            .collect()
+    }
+}
+
+fn fumen_piece(value: u64) -> Piece {
+    match value {
+        1 => Piece::I,
+        2 => Piece::L,
+        3 => Piece::O,
+        4 => Piece::Z,
+        5 => Piece::T,
+        6 => Piece::J,
+        7 => Piece::S,
+        8 => Piece::Garbage,
+        _ => Piece::None,
+    }
+}
+
+fn fumen_piece_value(piece: Piece) -> u8 {
+    match piece {
+        Piece::None => 0,
+        Piece::I => 1,
+        Piece::L => 2,
+        Piece::O => 3,
+        Piece::Z => 4,
+        Piece::T => 5,
+        Piece::J => 6,
+        Piece::S => 7,
+        Piece::Garbage => 8,
+    }
+}
+
+fn fumen_rotation(value: u64) -> Rotation {
+    match value {
+        0 => Rotation::Flip,
+        1 => Rotation::Right,
+        2 => Rotation::Spawn,
+        _ => Rotation::Left,
+    }
+}
+
+fn fumen_rotation_value(rotation: Rotation) -> u64 {
+    match rotation {
+        Rotation::Flip => 0,
+        Rotation::Right => 1,
+        Rotation::Spawn => 2,
+        Rotation::Left => 3,
+    }
+}
+
+// cells of a piece relative to its rotation center, as (x, y) with y going up
+fn piece_cells(piece: Piece, rotation: Rotation) -> [(i32, i32); 4] {
+    let cells = match piece {
+        Piece::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Piece::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Piece::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Piece::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Piece::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Piece::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Piece::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        Piece::Garbage | Piece::None => [(0, 0); 4],
+    };
+    cells.map(|(x, y)| match rotation {
+        Rotation::Spawn => (x, y),
+        Rotation::Right => (y, -x),
+        Rotation::Flip => (-x, -y),
+        Rotation::Left => (-y, x),
+    })
+}
+
+// fumen stores positions relative to an older reference cell for a few pieces
+fn position_offset(piece: Piece, rotation: Rotation) -> (i32, i32) {
+    match (piece, rotation) {
+        (Piece::O, Rotation::Left) => (1, -1),
+        (Piece::O, Rotation::Flip) => (1, 0),
+        (Piece::O, Rotation::Spawn) => (0, -1),
+        (Piece::I, Rotation::Flip) => (1, 0),
+        (Piece::S, Rotation::Spawn) => (0, -1),
+        (Piece::S, Rotation::Right) => (-1, 0),
+        (Piece::Z, Rotation::Spawn) => (0, -1),
+        (Piece::Z, Rotation::Left) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+fn decode_position(piece: Piece, rotation: Rotation, position: usize) -> Result<Operation, String> {
+    let (dx, dy) = position_offset(piece, rotation);
+    let x = (position % BOARD_WIDTH) as i32 + dx;
+    let y = (FIELD_TOP - position / BOARD_WIDTH - 1) as i32 + dy;
+    let (line, col, rotation) = if piece == Piece::O {
+        // Tetris anchors O pieces at their top left cell, regardless of rotation
+        let cells = piece_cells(piece, rotation);
+        let left = x + cells.iter().map(|c| c.0).min().unwrap_or(0);
+        let top = y + cells.iter().map(|c| c.1).max().unwrap_or(0);
+        (BOARD_HEIGHT as i32 - 1 - top, left, Rotation::Spawn)
+    } else {
+        (BOARD_HEIGHT as i32 - 1 - y, x, rotation)
+    };
+    if line < 1 || line >= BOARD_HEIGHT as i32 || col < 0 || col >= BOARD_WIDTH as i32 {
+        return Err("fumen piece is outside of the field".to_string());
+    }
+    Ok(Operation {
+        piece,
+        rotation,
+        line: line as usize,
+        col: col as usize,
+    })
+}
+
+// returns the fumen (piece, rotation, position) values of an operation
+fn encode_position(operation: &Operation) -> Result<(u64, u64, usize), String> {
+    let (x, y, rotation) = if operation.piece == Piece::O {
+        (
+            operation.col as i32,
+            BOARD_HEIGHT as i32 - 2 - operation.line as i32,
+            Rotation::Spawn,
+        )
+    } else {
+        (
+            operation.col as i32,
+            BOARD_HEIGHT as i32 - 1 - operation.line as i32,
+            operation.rotation,
+        )
+    };
+    let (dx, dy) = position_offset(operation.piece, rotation);
+    let row = FIELD_TOP as i32 - (y - dy) - 1;
+    if row < 0 || row >= FIELD_TOP as i32 {
+        return Err("piece is outside of the fumen field".to_string());
+    }
+    Ok((
+        fumen_piece_value(operation.piece) as u64,
+        fumen_rotation_value(rotation),
+        row as usize * BOARD_WIDTH + (x - dx) as usize,
+    ))
+}
+
+fn put_operation(field: &mut [u8; FIELD_BLOCKS], operation: &Operation) -> Result<(), String> {
+    let (piece, rotation, position) = encode_position(operation)?;
+    let piece = fumen_piece(piece);
+    let rotation = fumen_rotation(rotation);
+    let (dx, dy) = position_offset(piece, rotation);
+    let x = (position % BOARD_WIDTH) as i32 + dx;
+    let y = (FIELD_TOP - position / BOARD_WIDTH - 1) as i32 + dy;
+    for (cx, cy) in piece_cells(piece, rotation) {
+        let (cx, cy) = (x + cx, y + cy);
+        if cx < 0 || cx >= BOARD_WIDTH as i32 || cy < 0 || cy >= FIELD_TOP as i32 {
+            return Err("piece is outside of the fumen field".to_string());
+        }
+        field[(FIELD_TOP - 1 - cy as usize) * BOARD_WIDTH + cx as usize] = fumen_piece_value(piece);
+    }
+    Ok(())
+}
+
+// clears full rows of the playfield; the garbage row is never cleared
+fn clear_lines(field: &mut [u8; FIELD_BLOCKS]) {
+    let mut rows: Vec<[u8; BOARD_WIDTH]> = field[..FIELD_TOP * BOARD_WIDTH]
+        .chunks(BOARD_WIDTH)
+        .filter(|row| row.contains(&0))
+        .map(|row| row.try_into().unwrap())
+        .collect();
+    while rows.len() < FIELD_TOP {
+        rows.insert(0, [0; BOARD_WIDTH]);
+    }
+    for (i, row) in rows.iter().enumerate() {
+        field[i * BOARD_WIDTH..(i + 1) * BOARD_WIDTH].copy_from_slice(row);
+    }
+}
+
+fn mirror_field(field: &mut [u8; FIELD_BLOCKS]) {
+    for row in field[..FIELD_TOP * BOARD_WIDTH].chunks_mut(BOARD_WIDTH) {
+        row.reverse();
+    }
+}
+
+// fumen rows run from the top of a 23 row field down to the garbage row below
+// it, while the board has one more row on top that fumen can't represent
+fn to_board(field: &[u8; FIELD_BLOCKS]) -> Board {
+    let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+    for (row, cells) in field[..FIELD_TOP * BOARD_WIDTH]
+        .chunks(BOARD_WIDTH)
+        .enumerate()
+    {
+        for (col, cell) in cells.iter().enumerate() {
+            board[row + BOARD_HEIGHT - FIELD_TOP][col] = fumen_piece(*cell as u64);
+        }
+    }
+    board
+}
+
+fn to_field(board: &Board) -> [u8; FIELD_BLOCKS] {
+    let mut field = [0; FIELD_BLOCKS];
+    for (row, line) in board[BOARD_HEIGHT - FIELD_TOP..].iter().enumerate() {
+        for (col, cell) in line.iter().enumerate() {
+            field[row * BOARD_WIDTH + col] = fumen_piece_value(*cell);
+        }
+    }
+    field
+}
+
+// javascript's escape(), which fumen applies to comments
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for unit in s.encode_utf16() {
+        match unit {
+            0x41..=0x5a | 0x61..=0x7a | 0x30..=0x39 => escaped.push(unit as u8 as char),
+            0x40 | 0x2a | 0x5f | 0x2b | 0x2d | 0x2e | 0x2f => escaped.push(unit as u8 as char),
+            0..=0xff => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |from: usize, len: usize| {
+            s.get(from..from + len)
+                .and_then(|h| u16::from_str_radix(h, 16).ok())
+        };
+        if bytes[i] == b'%' {
+            if bytes.get(i + 1) == Some(&b'u') {
+                if let Some(unit) = hex(i + 2, 4) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = hex(i + 1, 2) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        units.push(bytes[i] as u16);
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_empty_field() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].operation.is_none());
+        assert!(pages[0].comment.is_empty());
+        assert!(pages[0].field.iter().flatten().all(|c| *c == Piece::None));
+        assert_eq!(encode(&pages).unwrap(), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn round_trips_pages() {
+        let mut field = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        field[BOARD_HEIGHT - 1] = [Piece::Garbage; BOARD_WIDTH];
+        field[BOARD_HEIGHT - 1][3] = Piece::None;
+        field[BOARD_HEIGHT - 2][0] = Piece::J;
+        let mut first = Page::new(field);
+        first.comment = Quiz {
+            hold: None,
+            current: Some(Piece::T),
+            queue: vec![Piece::S, Piece::Z, Piece::O],
+        }
+        .comment();
+        first.operation = Some(Operation {
+            piece: Piece::I,
+            rotation: Rotation::Left,
+            line: BOARD_HEIGHT - 3,
+            col: 3,
+        });
+        // an unchanged comment isn't stored again, but still decodes
+        let mut second = Page::new(field);
+        second.comment = first.comment.clone();
+        let mut third = Page::new(field);
+        third.operation = Some(Operation {
+            piece: Piece::O,
+            rotation: Rotation::Spawn,
+            line: BOARD_HEIGHT - 3,
+            col: 8,
+        });
+        third.comment = "zażółć ok".to_string();
+
+        let pages = decode(&encode(&[first.clone(), second, third.clone()]).unwrap()).unwrap();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0].field, field);
+        assert_eq!(pages[0].comment, first.comment);
+        let op = pages[0].operation.unwrap();
+        assert_eq!((op.piece, op.line, op.col), (Piece::I, BOARD_HEIGHT - 3, 3));
+        assert!(op.rotation == Rotation::Left);
+        assert!(pages[1].operation.is_none());
+        assert_eq!(pages[1].comment, first.comment);
+        let op = pages[2].operation.unwrap();
+        assert_eq!((op.piece, op.line, op.col), (Piece::O, BOARD_HEIGHT - 3, 8));
+        assert_eq!(pages[2].comment, third.comment);
+
+        let quiz = Quiz::parse(&pages[0].comment).unwrap();
+        assert_eq!(quiz.hold, None);
+        assert_eq!(quiz.current, Some(Piece::T));
+        assert_eq!(quiz.queue, vec![Piece::S, Piece::Z, Piece::O]);
+    }
+
+    // fumens with the same piece placed in each rotation in turn, spawn,
+    // flip, right and left (an o always comes out as spawn), then a page
+    // with the field they leave
+    #[test]
+    fn decodes_pieces_in_every_rotation() {
+        let cases: [(&str, &[&str]); 4] = [
+            (
+                "v115@vhExOJhrBJoB5tBAAA",
+                &["________II", "________II", "____IIIIII", "IIII____II"],
+            ),
+            ("v115@vhETJJLqBDrBbsBAAA", &["OOOOOOOO__", "OOOOOOOO__"]),
+            (
+                "v115@vhE3JJHrBvsB/tBAAA",
+                &["______S_S_", "_SS_SSSSSS", "SS_SS__S_S"],
+            ),
+            (
+                "v115@vhE0JJErBMsBctBAAA",
+                &["_______Z_Z", "ZZ_ZZ_ZZZZ", "_ZZ_ZZZ_Z_"],
+            ),
+        ];
+        for (code, rows) in cases {
+            let pages = decode(code).unwrap();
+            assert_eq!(pages.len(), 5);
+            let piece = pages[0].operation.unwrap().piece;
+            let rotations: Vec<Rotation> = pages[..4]
+                .iter()
+                .map(|page| page.operation.unwrap().rotation)
+                .collect();
+            if piece == Piece::O {
+                assert_eq!(rotations, [Rotation::Spawn; 4]);
+            } else {
+                assert_eq!(
+                    rotations,
+                    [
+                        Rotation::Spawn,
+                        Rotation::Flip,
+                        Rotation::Right,
+                        Rotation::Left
+                    ]
+                );
+            }
+
+            let mut field = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+            for (line, row) in field[BOARD_HEIGHT - rows.len()..].iter_mut().zip(rows) {
+                for (cell, c) in line.iter_mut().zip(row.chars()) {
+                    *cell = piece_from_char(c).unwrap_or(Piece::None);
+                }
+            }
+            assert_eq!(pages[4].field, field, "{}", code);
+            assert!(pages[4].operation.is_none());
+        }
+    }
+
+    #[test]
+    fn locked_pieces_carry_over_to_the_next_page() {
+        let empty = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        let mut first = Page::new(empty);
+        first.operation = Some(Operation {
+            piece: Piece::T,
+            rotation: Rotation::Spawn,
+            line: BOARD_HEIGHT - 1,
+            col: 4,
+        });
+        // the next page's field is decoded relative to the locked T
+        let mut field = empty;
+        field[BOARD_HEIGHT - 1][3..6].fill(Piece::T);
+        field[BOARD_HEIGHT - 2][4] = Piece::T;
+        field[BOARD_HEIGHT - 1][0] = Piece::L;
+        let pages = decode(&encode(&[first, Page::new(field)]).unwrap()).unwrap();
+        assert_eq!(pages[1].field, field);
+    }
+}
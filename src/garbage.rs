@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::clock::Timestamp;
+use crate::settings::GameplaySettings;
+use crate::tetris::{Piece, BOARD_WIDTH};
+
+pub type GarbageLine = [Piece; BOARD_WIDTH];
+
+// lines received in one attack, all sharing a hole unless messiness moves it
+#[derive(Clone, Copy)]
+struct Attack {
+    lines: u32,
+    hole: usize,
+    arrives: Timestamp,
+}
+
+// garbage sent to a board. attacks wait for a delay before they can be
+// inserted, which gives the player time to cancel them by attacking back
+#[derive(Clone)]
+pub struct GarbageQueue {
+    incoming: VecDeque<Attack>,
+    delay: u32,     // time in ms before received garbage can be inserted
+    messiness: u32, // percent chance of the hole moving on each line after the first
+    cap: u32,       // most lines inserted after a single piece
+    rng: StdRng,
+}
+
+impl GarbageQueue {
+    pub fn new(settings: &GameplaySettings, seed: u64) -> GarbageQueue {
+        GarbageQueue {
+            incoming: VecDeque::new(),
+            delay: settings.garbage_delay,
+            messiness: settings.garbage_messiness,
+            cap: settings.garbage_cap,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // takes new settings for the garbage still to come
+    pub fn apply_settings(&mut self, settings: &GameplaySettings) {
+        self.delay = settings.garbage_delay;
+        self.messiness = settings.garbage_messiness;
+        self.cap = settings.garbage_cap;
+    }
+
+    pub fn receive(&mut self, lines: u32, timestamp: Timestamp) {
+        if lines == 0 {
+            return;
+        }
+        let hole = self.rng.gen_range(0..BOARD_WIDTH);
+        self.incoming.push_back(Attack {
+            lines,
+            hole,
+            arrives: timestamp + self.delay as Timestamp,
+        });
+    }
+
+    // spends `attack` lines cancelling incoming garbage, oldest first, and
+    // returns what is left to send on
+    pub fn cancel(&mut self, mut attack: u32) -> u32 {
+        while let Some(front) = self.incoming.front_mut() {
+            if attack == 0 {
+                break;
+            }
+            let cancelled = front.lines.min(attack);
+            front.lines -= cancelled;
+            attack -= cancelled;
+            if front.lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+        attack
+    }
+
+    // lines received but not inserted yet, for the incoming damage meter
+    pub fn pending(&self) -> u32 {
+        self.incoming.iter().map(|attack| attack.lines).sum()
+    }
+
+    // takes the garbage whose delay is over by `timestamp`, up to the cap
+    pub fn take_ready(&mut self, timestamp: Timestamp) -> Vec<GarbageLine> {
+        let mut lines = Vec::new();
+        let mut room = self.cap;
+        while let Some(front) = self.incoming.front_mut() {
+            if room == 0 || front.arrives > timestamp {
+                break;
+            }
+            let count = front.lines.min(room);
+            let hole = front.hole;
+            front.lines -= count;
+            room -= count;
+            if front.lines == 0 {
+                self.incoming.pop_front();
+            }
+            lines.extend(garbage_lines(&mut self.rng, count, hole, self.messiness));
+        }
+        lines
+    }
+
+    // `count` lines with a random hole, e.g. to put on the board right away
+    pub fn generate(&mut self, count: u32) -> Vec<GarbageLine> {
+        let hole = self.rng.gen_range(0..BOARD_WIDTH);
+        garbage_lines(&mut self.rng, count, hole, self.messiness)
+    }
+}
+
+// `count` garbage lines starting with the hole at `hole`. after the first
+// line, `messiness` is the percent chance of the hole moving
+pub fn garbage_lines(
+    rng: &mut StdRng,
+    count: u32,
+    mut hole: usize,
+    messiness: u32,
+) -> Vec<GarbageLine> {
+    let mut lines = Vec::new();
+    for i in 0..count {
+        if i > 0 && rng.gen_range(0..100) < messiness {
+            // move the hole to any other column
+            hole = (hole + rng.gen_range(1..BOARD_WIDTH)) % BOARD_WIDTH;
+        }
+        let mut line = [Piece::Garbage; BOARD_WIDTH];
+        line[hole] = Piece::None;
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(messiness: u32) -> GarbageQueue {
+        let settings = GameplaySettings {
+            garbage_delay: 500,
+            garbage_messiness: messiness,
+            garbage_cap: 4,
+            ..GameplaySettings::default()
+        };
+        GarbageQueue::new(&settings, 0)
+    }
+
+    fn hole(line: &GarbageLine) -> usize {
+        line.iter().position(|cell| *cell == Piece::None).unwrap()
+    }
+
+    #[test]
+    fn garbage_waits_for_the_delay_and_the_cap() {
+        let mut garbage = queue(0);
+        garbage.receive(3, 0);
+        garbage.receive(3, 100);
+        assert_eq!(garbage.pending(), 6);
+        assert!(garbage.take_ready(499).is_empty());
+
+        // only the first attack has arrived
+        let lines = garbage.take_ready(500);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| hole(line) == hole(&lines[0])));
+
+        garbage.receive(3, 100);
+        assert_eq!(garbage.take_ready(1000).len(), 4);
+        assert_eq!(garbage.pending(), 2);
+    }
+
+    #[test]
+    fn attacking_cancels_the_oldest_garbage_first() {
+        let mut garbage = queue(0);
+        garbage.receive(2, 0);
+        garbage.receive(4, 0);
+        assert_eq!(garbage.cancel(3), 0);
+        assert_eq!(garbage.pending(), 3);
+        assert_eq!(garbage.cancel(5), 2);
+        assert_eq!(garbage.pending(), 0);
+    }
+
+    #[test]
+    fn messy_garbage_moves_the_hole() {
+        let lines = queue(100).generate(5);
+        for pair in lines.windows(2) {
+            assert_ne!(hole(&pair[0]), hole(&pair[1]));
+        }
+        let lines = queue(0).generate(5);
+        assert!(lines.iter().all(|line| hole(line) == hole(&lines[0])));
+    }
+}
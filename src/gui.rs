@@ -0,0 +1,200 @@
+use egui::epaint::textures::TexturesDelta;
+use egui::{ClippedPrimitive, Context, Modifiers, Pos2, RawInput, Rect, Vec2};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+
+use crate::clock::Timestamp;
+
+// how far a notch of the mouse wheel scrolls, in pixels
+const SCROLL_STEP: f32 = 50.0;
+
+// egui on top of the sdl window. sdl events are fed in as they come, then
+// each frame's ui is run and handed to the renderer to draw
+pub struct Gui {
+    ctx: Context,
+    events: Vec<egui::Event>,
+    modifiers: Modifiers,
+    pointer: Pos2,
+}
+
+impl Default for Gui {
+    fn default() -> Self {
+        Gui::new()
+    }
+}
+
+impl Gui {
+    pub fn new() -> Gui {
+        Gui {
+            ctx: Context::default(),
+            events: Vec::new(),
+            modifiers: Modifiers::default(),
+            pointer: Pos2::ZERO,
+        }
+    }
+
+    pub fn process_event(&mut self, event: &Event) {
+        match event {
+            Event::MouseMotion { x, y, .. } => {
+                self.pointer = Pos2::new(*x as f32, *y as f32);
+                self.events.push(egui::Event::PointerMoved(self.pointer));
+            }
+            Event::MouseButtonDown {
+                mouse_btn, x, y, ..
+            } => self.pointer_button(*mouse_btn, *x, *y, true),
+            Event::MouseButtonUp {
+                mouse_btn, x, y, ..
+            } => self.pointer_button(*mouse_btn, *x, *y, false),
+            Event::MouseWheel { x, y, .. } => self.events.push(egui::Event::Scroll(Vec2::new(
+                *x as f32 * SCROLL_STEP,
+                *y as f32 * SCROLL_STEP,
+            ))),
+            Event::TextInput { text, .. } => self.events.push(egui::Event::Text(text.clone())),
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => self.key(*keycode, *keymod, true),
+            Event::KeyUp {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => self.key(*keycode, *keymod, false),
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => self.events.push(egui::Event::PointerGone),
+            _ => {}
+        }
+    }
+
+    fn pointer_button(&mut self, button: MouseButton, x: i32, y: i32, pressed: bool) {
+        let button = match button {
+            MouseButton::Left => egui::PointerButton::Primary,
+            MouseButton::Right => egui::PointerButton::Secondary,
+            MouseButton::Middle => egui::PointerButton::Middle,
+            _ => return,
+        };
+        self.pointer = Pos2::new(x as f32, y as f32);
+        self.events.push(egui::Event::PointerButton {
+            pos: self.pointer,
+            button,
+            pressed,
+            modifiers: self.modifiers,
+        });
+    }
+
+    fn key(&mut self, keycode: Keycode, keymod: Mod, pressed: bool) {
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        self.modifiers = Modifiers {
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+            ctrl,
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            mac_cmd: false,
+            command: ctrl,
+        };
+        if let Some(key) = egui_key(keycode) {
+            self.events.push(egui::Event::Key {
+                key,
+                pressed,
+                modifiers: self.modifiers,
+            });
+        }
+    }
+
+    // runs a frame of ui over a window of `size`. what comes out is for
+    // Renderer::set_gui
+    pub fn run(
+        &mut self,
+        (width, height): (u32, u32),
+        timestamp: Timestamp,
+        ui: impl FnOnce(&Context),
+    ) -> (TexturesDelta, Vec<ClippedPrimitive>) {
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(width as f32, height as f32),
+            )),
+            pixels_per_point: Some(1.0),
+            time: Some(timestamp as f64 / 1000.0),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            ..RawInput::default()
+        };
+        let output = self.ctx.run(input, ui);
+        (output.textures_delta, self.ctx.tessellate(output.shapes))
+    }
+}
+
+// the keys egui's text fields and widgets react to
+fn egui_key(keycode: Keycode) -> Option<egui::Key> {
+    let key = match keycode {
+        Keycode::Down => egui::Key::ArrowDown,
+        Keycode::Left => egui::Key::ArrowLeft,
+        Keycode::Right => egui::Key::ArrowRight,
+        Keycode::Up => egui::Key::ArrowUp,
+        Keycode::Escape => egui::Key::Escape,
+        Keycode::Tab => egui::Key::Tab,
+        Keycode::Backspace => egui::Key::Backspace,
+        Keycode::Return | Keycode::KpEnter => egui::Key::Enter,
+        Keycode::Space => egui::Key::Space,
+        Keycode::Insert => egui::Key::Insert,
+        Keycode::Delete => egui::Key::Delete,
+        Keycode::Home => egui::Key::Home,
+        Keycode::End => egui::Key::End,
+        Keycode::PageUp => egui::Key::PageUp,
+        Keycode::PageDown => egui::Key::PageDown,
+        Keycode::A => egui::Key::A,
+        Keycode::C => egui::Key::C,
+        Keycode::V => egui::Key::V,
+        Keycode::X => egui::Key::X,
+        Keycode::Z => egui::Key::Z,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_and_typing_become_egui_events() {
+        let mut gui = Gui::new();
+        gui.process_event(&Event::MouseButtonDown {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x: 10,
+            y: 20,
+        });
+        gui.process_event(&Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(Keycode::Backspace),
+            scancode: None,
+            keymod: Mod::LCTRLMOD,
+            repeat: false,
+        });
+        assert!(matches!(
+            gui.events[0],
+            egui::Event::PointerButton {
+                button: egui::PointerButton::Primary,
+                pressed: true,
+                ..
+            }
+        ));
+        assert_eq!(gui.pointer, Pos2::new(10.0, 20.0));
+        assert!(matches!(
+            gui.events[1],
+            egui::Event::Key {
+                key: egui::Key::Backspace,
+                pressed: true,
+                modifiers: Modifiers { ctrl: true, .. },
+            }
+        ));
+    }
+}
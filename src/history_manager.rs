@@ -1,15 +1,119 @@
-use crate::solver::SolverState;
+use crate::clock::Timestamp;
+use crate::solver::{Placement, SolverState};
 
+// a placement along with the state recorded as it locked, before any lines
+// were cleared
+#[derive(Clone)]
+pub struct HistoryNode {
+    pub state: SolverState,
+    pub placement: Placement,
+    pub timestamp: Timestamp, // when the piece locked
+    pub inputs: u32,          // key presses spent placing it
+    parent: Option<usize>,
+}
+
+// every placement made, as a tree. undoing a placement keeps it in the tree,
+// so placing a different piece afterwards starts a new branch
 pub struct HistoryManager {
-    states: Vec<SolverState>,
+    nodes: Vec<HistoryNode>,
+    current: Option<usize>, // the last placement on the branch being played
+}
+
+impl Default for HistoryManager {
+    fn default() -> Self {
+        HistoryManager::new()
+    }
 }
 
 impl HistoryManager {
     pub fn new() -> HistoryManager {
-        HistoryManager { states: Vec::new() }
+        HistoryManager {
+            nodes: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        state: SolverState,
+        placement: Placement,
+        timestamp: Timestamp,
+        inputs: u32,
+    ) {
+        // placing the same piece again after an undo follows the old branch
+        let existing = self
+            .children(self.current)
+            .find(|i| self.nodes[*i].placement == placement);
+        if let Some(index) = existing {
+            self.current = Some(index);
+            return;
+        }
+        self.nodes.push(HistoryNode {
+            state,
+            placement,
+            timestamp,
+            inputs,
+            parent: self.current,
+        });
+        self.current = Some(self.nodes.len() - 1);
+    }
+
+    // steps back to before the last placement and returns it
+    pub fn undo(&mut self) -> Option<HistoryNode> {
+        let node = self.nodes[self.current?].clone();
+        self.current = node.parent;
+        Some(node)
+    }
+
+    // the placements leading to the current position, oldest first
+    pub fn branch(&self) -> Vec<&HistoryNode> {
+        let mut branch = Vec::new();
+        let mut index = self.current;
+        while let Some(i) = index {
+            branch.push(&self.nodes[i]);
+            index = self.nodes[i].parent;
+        }
+        branch.reverse();
+        branch
+    }
+
+    // how many different placements have been tried from the current position
+    pub fn branches(&self) -> usize {
+        self.children(self.current).count()
+    }
+
+    fn children(&self, parent: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(move |i| self.nodes[*i].parent == parent)
     }
-    
-    pub fn add(&mut self, state: SolverState) {
-        self.states.push(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetris::{Piece, Rotation};
+
+    fn placement(col: usize) -> Placement {
+        Placement {
+            piece: Piece::O,
+            rotation: Rotation::Spawn,
+            line: 23,
+            col,
+        }
+    }
+
+    #[test]
+    fn undo_keeps_the_old_branch() {
+        let mut history = HistoryManager::new();
+        history.add(SolverState::new(0), placement(0), 0, 0);
+        history.add(SolverState::new(0), placement(2), 0, 0);
+        assert_eq!(history.undo().unwrap().placement, placement(2));
+        history.add(SolverState::new(0), placement(4), 0, 0);
+        assert_eq!(history.branches(), 0);
+        history.undo();
+        assert_eq!(history.branches(), 2);
+        history.add(SolverState::new(0), placement(2), 0, 0);
+        let branch: Vec<Placement> = history.branch().iter().map(|n| n.placement).collect();
+        assert_eq!(branch, vec![placement(0), placement(2)]);
+        assert_eq!(history.branches(), 0);
     }
 }
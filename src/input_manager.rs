@@ -1,15 +1,20 @@
-use std::time::{Duration, SystemTime};
+use std::collections::HashMap;
 
+#[cfg(feature = "sdl")]
+use sdl2::controller::Axis;
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    clock::Timestamp,
+    settings::{Button, ControllerSettings, Key, Keybinds, Settings},
     softdrop,
     tetris::{Direction, Rotation, State, Tetris},
 };
 
 pub struct InputManager {
-    events: Vec<(Input, SystemTime, u32)>,
+    events: Vec<(Input, Timestamp, u32)>,
 
     // handling settings
     arr: u32, // auto-repeat rate: time in ms it takes between each movement after das has started
@@ -20,14 +25,31 @@ pub struct InputManager {
     cdwcd: u32, // cancel das when changing directions: time in ms to wait until existing das kicks in again
 
     // keybinds
-    left: Vec<Scancode>,
-    right: Vec<Scancode>,
-    softdrop: Vec<Scancode>,
-    harddrop: Vec<Scancode>,
-    rot_counterclockwise: Vec<Scancode>,
-    rot_clockwise: Vec<Scancode>,
-    rot_180: Vec<Scancode>,
-    swap: Vec<Scancode>,
+    left: Vec<Key>,
+    right: Vec<Key>,
+    softdrop: Vec<Key>,
+    harddrop: Vec<Key>,
+    rot_counterclockwise: Vec<Key>,
+    rot_clockwise: Vec<Key>,
+    rot_180: Vec<Key>,
+    swap: Vec<Key>,
+
+    // controllers
+    controller: ControllerSettings,
+    controller_names: HashMap<u32, String>, // by the instance id sdl gives each pad
+    sticks: HashMap<u32, Action>,           // the way each pad's left stick is held
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateCounterclockwise,
+    RotateClockwise,
+    Rotate180,
+    Swap,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -38,108 +60,341 @@ enum Input {
 }
 
 impl InputManager {
-    pub fn new() -> Self {
+    pub fn new(settings: &Settings) -> Self {
+        InputManager::with_keybinds(settings, &settings.keybinds)
+    }
+
+    // uses the handling settings with another keymap, e.g. a versus player's
+    pub fn with_keybinds(settings: &Settings, keybinds: &Keybinds) -> Self {
+        let handling = &settings.handling;
         InputManager {
             events: Vec::new(),
-            arr: 0,
-            das: 100,
-            dcd: 0,
-            sdf: 100,
-            pahd: 100,
-            cdwcd: 100,
-            left: vec![Scancode::Left],
-            right: vec![Scancode::Right],
-            softdrop: vec![Scancode::Down],
-            harddrop: vec![Scancode::Space],
-            rot_counterclockwise: vec![Scancode::Z],
-            rot_clockwise: vec![Scancode::X, Scancode::Up],
-            rot_180: vec![Scancode::A],
-            swap: vec![Scancode::LShift],
-        }
-    }
-
-    pub fn process_input(&mut self, event: Event, game: &mut Tetris, timestamp: SystemTime) {
-        if let Event::KeyDown {
-            repeat: false,
-            scancode,
-            ..
-        } = event
-        {
-            let sc = scancode.expect("no scancode?");
-            if self.left.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.move_active(Direction::Left);
-                    self.events.push((Input::Left, timestamp, 0)); // for das timings
-                    if self.cdwcd > 0 {
-                        for ev in self.events.iter_mut() {
-                            if ev.0 == Input::Right {
-                                *ev = (Input::Right, timestamp, 0);
-                            }
+            arr: handling.arr,
+            das: handling.das,
+            dcd: handling.dcd,
+            sdf: handling.sdf,
+            pahd: handling.pahd,
+            cdwcd: handling.cdwcd,
+            left: keybinds.left.clone(),
+            right: keybinds.right.clone(),
+            softdrop: keybinds.softdrop.clone(),
+            harddrop: keybinds.harddrop.clone(),
+            rot_counterclockwise: keybinds.rot_counterclockwise.clone(),
+            rot_clockwise: keybinds.rot_clockwise.clone(),
+            rot_180: keybinds.rot_180.clone(),
+            swap: keybinds.swap.clone(),
+            controller: settings.controller.clone(),
+            controller_names: HashMap::new(),
+            sticks: HashMap::new(),
+        }
+    }
+
+    // takes changed settings mid-game, keeping the keys being held and the
+    // controllers plugged in
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        let events = std::mem::take(&mut self.events);
+        let controller_names = std::mem::take(&mut self.controller_names);
+        let sticks = std::mem::take(&mut self.sticks);
+        *self = InputManager::new(settings);
+        self.events = events;
+        self.controller_names = controller_names;
+        self.sticks = sticks;
+    }
+
+    // carries the keys being held over to a new game whose time starts again
+    // from `timestamp`, as if they were pressed then. returns them, so the
+    // new game's replay starts with them held
+    pub fn rewind(&mut self, timestamp: Timestamp) -> Vec<(Action, bool)> {
+        let mut held = Vec::new();
+        for event in self.events.iter_mut() {
+            *event = (event.0, timestamp, 0);
+            held.push(match event.0 {
+                Input::Left => (Action::Left, true),
+                Input::Right => (Action::Right, true),
+                Input::Down => (Action::SoftDrop, true),
+            });
+        }
+        held
+    }
+
+    // starts listening to the controller with instance id `which`, using the
+    // binds for pads called `name`
+    pub fn add_controller(&mut self, which: u32, name: &str) {
+        self.controller_names.insert(which, name.to_string());
+    }
+
+    // forgets an unplugged controller, letting go of its stick
+    pub fn remove_controller(
+        &mut self,
+        which: u32,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) -> Vec<(Action, bool)> {
+        self.controller_names.remove(&which);
+        let stick = self.sticks.remove(&which);
+        for action in stick.iter() {
+            self.process_action(*action, false, game, timestamp);
+        }
+        Vec::from_iter(stick.map(|action| (action, false)))
+    }
+
+    pub fn action_for_button(&self, which: u32, button: &Button) -> Option<Action> {
+        let binds = self
+            .controller
+            .binds_for(self.controller_names.get(&which)?);
+        [
+            (&binds.left, Action::Left),
+            (&binds.right, Action::Right),
+            (&binds.softdrop, Action::SoftDrop),
+            (&binds.rot_counterclockwise, Action::RotateCounterclockwise),
+            (&binds.rot_clockwise, Action::RotateClockwise),
+            (&binds.rot_180, Action::Rotate180),
+            (&binds.harddrop, Action::HardDrop),
+            (&binds.swap, Action::Swap),
+        ]
+        .into_iter()
+        .find(|(buttons, _)| buttons.contains(button))
+        .map(|(_, action)| action)
+    }
+
+    pub fn action_for(&self, key: &Key) -> Option<Action> {
+        if self.left.contains(key) {
+            Some(Action::Left)
+        } else if self.right.contains(key) {
+            Some(Action::Right)
+        } else if self.softdrop.contains(key) {
+            Some(Action::SoftDrop)
+        } else if self.rot_counterclockwise.contains(key) {
+            Some(Action::RotateCounterclockwise)
+        } else if self.rot_clockwise.contains(key) {
+            Some(Action::RotateClockwise)
+        } else if self.rot_180.contains(key) {
+            Some(Action::Rotate180)
+        } else if self.harddrop.contains(key) {
+            Some(Action::HardDrop)
+        } else if self.swap.contains(key) {
+            Some(Action::Swap)
+        } else {
+            None
+        }
+    }
+
+    // handles a keyboard event and returns the actions it was mapped to along
+    // with whether each was pressed, so callers can record them
+    #[cfg(feature = "sdl")]
+    pub fn process_input(
+        &mut self,
+        event: Event,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) -> Vec<(Action, bool)> {
+        let (scancode, pressed) = match event {
+            Event::KeyDown {
+                repeat: false,
+                scancode,
+                ..
+            } => (scancode, true),
+            Event::KeyUp {
+                repeat: false,
+                scancode,
+                ..
+            } => (scancode, false),
+            Event::ControllerButtonDown { which, button, .. } => {
+                let button = Button::from(button);
+                return Vec::from_iter(self.process_button(which, &button, true, game, timestamp));
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                let button = Button::from(button);
+                return Vec::from_iter(self.process_button(which, &button, false, game, timestamp));
+            }
+            Event::ControllerAxisMotion {
+                which,
+                axis: Axis::LeftX,
+                value,
+                ..
+            } => return self.process_stick(which, value, game, timestamp),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                return self.remove_controller(which, game, timestamp)
+            }
+            _ => return Vec::new(),
+        };
+        let key = Key::from(scancode.expect("no scancode?"));
+        Vec::from_iter(self.process_key(&key, pressed, game, timestamp))
+    }
+
+    // the same for a key pressed or released, whatever it came from
+    pub fn process_key(
+        &mut self,
+        key: &Key,
+        pressed: bool,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) -> Option<(Action, bool)> {
+        let action = self.action_for(key)?;
+        self.process_action(action, pressed, game, timestamp);
+        Some((action, pressed))
+    }
+
+    // the same for a controller's button
+    pub fn process_button(
+        &mut self,
+        which: u32,
+        button: &Button,
+        pressed: bool,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) -> Option<(Action, bool)> {
+        let action = self.action_for_button(which, button)?;
+        self.process_action(action, pressed, game, timestamp);
+        Some((action, pressed))
+    }
+
+    // tilting a controller's left stick past the dead zone holds left or
+    // right. a stick swung from one side to the other in a single step lets
+    // go of the first and holds the second
+    pub fn process_stick(
+        &mut self,
+        which: u32,
+        value: i16,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) -> Vec<(Action, bool)> {
+        let mut actions = Vec::new();
+        if !self.controller_names.contains_key(&which) {
+            return actions;
+        }
+        let threshold = i16::MAX as i32 * self.controller.dead_zone as i32 / 100;
+        let direction = if (value as i32) < -threshold {
+            Some(Action::Left)
+        } else if value as i32 > threshold {
+            Some(Action::Right)
+        } else {
+            None
+        };
+        let held = self.sticks.get(&which).copied();
+        if direction == held {
+            return actions;
+        }
+        if let Some(held) = held {
+            self.sticks.remove(&which);
+            self.process_action(held, false, game, timestamp);
+            actions.push((held, false));
+        }
+        if let Some(action) = direction {
+            self.sticks.insert(which, action);
+            self.process_action(action, true, game, timestamp);
+            actions.push((action, true));
+        }
+        actions
+    }
+
+    pub fn process_action(
+        &mut self,
+        action: Action,
+        pressed: bool,
+        game: &mut Tetris,
+        timestamp: Timestamp,
+    ) {
+        if !pressed {
+            match action {
+                Action::Left => self.events.retain(|e| e.0 != Input::Left),
+                Action::Right => self.events.retain(|e| e.0 != Input::Right),
+                Action::SoftDrop => {
+                    game.softdrop_stop();
+                    self.events.retain(|e| e.0 != Input::Down)
+                }
+                _ => {}
+            }
+            return;
+        }
+        if game.state() != State::Playing {
+            return;
+        }
+        if !matches!(action, Action::HardDrop | Action::Swap) {
+            game.count_input();
+        }
+        match action {
+            Action::Left => {
+                game.move_active(Direction::Left);
+                self.events.push((Input::Left, timestamp, 0)); // for das timings
+                if self.cdwcd > 0 {
+                    for ev in self.events.iter_mut() {
+                        if ev.0 == Input::Right {
+                            *ev = (Input::Right, timestamp, 0);
                         }
                     }
                 }
-            } else if self.right.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.move_active(Direction::Right);
-                    self.events.push((Input::Right, timestamp, 0)); // for das timings
-                    if self.cdwcd > 0 {
-                        for ev in self.events.iter_mut() {
-                            if ev.0 == Input::Left {
-                                *ev = (Input::Left, timestamp, 0);
-                            }
+            }
+            Action::Right => {
+                game.move_active(Direction::Right);
+                self.events.push((Input::Right, timestamp, 0)); // for das timings
+                if self.cdwcd > 0 {
+                    for ev in self.events.iter_mut() {
+                        if ev.0 == Input::Left {
+                            *ev = (Input::Left, timestamp, 0);
                         }
                     }
                 }
-            } else if self.softdrop.contains(&sc) {
-                if game.state() == State::Playing {
-                    softdrop!(self, game, timestamp);
-                    self.events.push((Input::Down, timestamp, 0));
-                }
-            } else if self.rot_counterclockwise.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.rot_active(Rotation::Left);
-                }
-            } else if self.rot_clockwise.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.rot_active(Rotation::Right);
-                }
-            } else if self.rot_180.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.rot_active(Rotation::Flip);
-                }
-            } else if self.harddrop.contains(&sc) {
-                if game.state() == State::Playing {
+            }
+            Action::SoftDrop => {
+                softdrop!(self, game, timestamp);
+                self.events.push((Input::Down, timestamp, 0));
+            }
+            Action::RotateCounterclockwise => {
+                game.rot_active(Rotation::Left);
+                self.cut_das(timestamp);
+            }
+            Action::RotateClockwise => {
+                game.rot_active(Rotation::Right);
+                self.cut_das(timestamp);
+            }
+            Action::Rotate180 => {
+                game.rot_active(Rotation::Flip);
+                self.cut_das(timestamp);
+            }
+            Action::HardDrop => {
+                // ignore hard drops right after a piece locked on its own
+                let hard_drop_allowed = game
+                    .autolock_timestamp()
+                    .is_none_or(|t| timestamp.saturating_sub(t) >= self.pahd as Timestamp);
+                if hard_drop_allowed {
                     game.harddrop(timestamp);
-                }
-            } else if self.swap.contains(&sc) {
-                if game.state() == State::Playing {
-                    game.swap();
+                    self.cut_das(timestamp);
                 }
             }
-        } else if let Event::KeyUp {
-            repeat: false,
-            scancode,
-            ..
-        } = event
-        {
-            let sc = scancode.expect("no scancode?");
-            if self.left.contains(&sc) {
-                self.events.retain(|e| e.0 != Input::Left)
-            } else if self.right.contains(&sc) {
-                self.events.retain(|e| e.0 != Input::Right)
-            } else if self.softdrop.contains(&sc) {
-                game.softdrop_stop();
-                self.events.retain(|e| e.0 != Input::Down)
+            Action::Swap => game.swap(),
+        }
+    }
+
+    // steps the game one ms at a time from `from` (exclusive) to `to`, so the
+    // outcome doesn't depend on how often frames happen to be drawn
+    pub fn advance(&mut self, game: &mut Tetris, from: Timestamp, to: Timestamp) {
+        for timestamp in from + 1..=to {
+            self.update(timestamp, game);
+            game.update(timestamp);
+        }
+    }
+
+    // pauses any ongoing das movement for dcd ms by pushing back the start of
+    // held left/right inputs, so das resumes charged once the delay is over
+    fn cut_das(&mut self, timestamp: Timestamp) {
+        if self.dcd == 0 {
+            return;
+        }
+        let resume = (timestamp + self.dcd as Timestamp).saturating_sub(self.das as Timestamp);
+        for event in self.events.iter_mut() {
+            if event.0 != Input::Down && event.1 < resume {
+                *event = (event.0, resume, 0);
             }
         }
     }
 
-    pub fn update(&mut self, timestamp: SystemTime, game: &mut Tetris) {
+    pub fn update(&mut self, timestamp: Timestamp, game: &mut Tetris) {
         for event in self.events.iter_mut() {
-            if let Ok(dur) = timestamp.duration_since(event.1) {
-                if dur.as_millis() as u32 > self.das {
+            if let Some(dur) = timestamp.checked_sub(event.1) {
+                if dur as u32 > self.das {
                     if self.arr > 0 {
-                        let moves = ((dur.as_millis() as u32 - self.das) / self.arr) - event.2;
+                        let moves = ((dur as u32 - self.das) / self.arr) - event.2;
                         *event = (event.0, event.1, event.2 + moves);
                         match event.0 {
                             Input::Left => {
@@ -169,3 +424,236 @@ impl InputManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{ControllerBinds, GameplaySettings};
+
+    fn key_down(input: &mut InputManager, name: &str, game: &mut Tetris, timestamp: Timestamp) {
+        input.process_key(&Key::from(name), true, game, timestamp);
+    }
+
+    fn setup(dcd: u32, pahd: u32, gameplay: GameplaySettings) -> (InputManager, Tetris<'static>) {
+        let mut settings = Settings::default();
+        settings.handling.arr = 0;
+        settings.handling.das = 100;
+        settings.handling.dcd = dcd;
+        settings.handling.pahd = pahd;
+        let mut game = Tetris::new(None, &gameplay, 0);
+        game.start(0);
+        (InputManager::new(&settings), game)
+    }
+
+    fn no_gravity() -> GameplaySettings {
+        GameplaySettings {
+            gravity: 0,
+            ..GameplaySettings::default()
+        }
+    }
+
+    fn wall_col(game: &Tetris) -> usize {
+        let mut game = game.clone();
+        while game.move_active(Direction::Left) {}
+        game.active_piece().3
+    }
+
+    #[test]
+    fn das_moves_to_wall_without_dcd() {
+        let (mut input, mut game) = setup(0, 0, no_gravity());
+        key_down(&mut input, "Left", &mut game, 0);
+        input.update(150, &mut game);
+        key_down(&mut input, "Space", &mut game, 200);
+        let wall = wall_col(&game);
+        input.update(201, &mut game);
+        assert_eq!(game.active_piece().3, wall);
+    }
+
+    #[test]
+    fn dcd_pauses_das_after_harddrop() {
+        let (mut input, mut game) = setup(50, 0, no_gravity());
+        key_down(&mut input, "Left", &mut game, 0);
+        input.update(150, &mut game);
+        key_down(&mut input, "Space", &mut game, 200);
+        let spawn_col = game.active_piece().3;
+        let wall = wall_col(&game);
+
+        input.update(220, &mut game);
+        assert_eq!(game.active_piece().3, spawn_col);
+        input.update(249, &mut game);
+        assert_eq!(game.active_piece().3, spawn_col);
+        input.update(251, &mut game);
+        assert_eq!(game.active_piece().3, wall);
+    }
+
+    #[test]
+    fn dcd_pauses_das_after_rotation() {
+        let (mut input, mut game) = setup(50, 0, no_gravity());
+        key_down(&mut input, "Left", &mut game, 0);
+        key_down(&mut input, "X", &mut game, 60);
+        let col = game.active_piece().3;
+
+        // das would have charged at 100, but the rotation cut it until 110
+        input.update(105, &mut game);
+        assert_eq!(game.active_piece().3, col);
+        input.update(111, &mut game);
+        assert_eq!(game.active_piece().3, wall_col(&game));
+    }
+
+    #[test]
+    fn pahd_ignores_harddrop_after_autolock() {
+        let gameplay = GameplaySettings {
+            gravity: 10,
+            lock_delay: 0,
+            max_lock_reset_count: 20,
+            ..GameplaySettings::default()
+        };
+        let (mut input, mut game) = setup(0, 100, gameplay);
+
+        // let the first piece fall and lock on its own
+        game.update(1000);
+        game.update(1001);
+        assert_eq!(game.autolock_timestamp(), Some(1001));
+
+        let queue = game.get_queue();
+        key_down(&mut input, "Space", &mut game, 1050);
+        assert_eq!(game.get_queue(), queue);
+        key_down(&mut input, "Space", &mut game, 1101);
+        assert_ne!(game.get_queue(), queue);
+    }
+
+    #[test]
+    fn held_softdrop_keeps_falling_at_gravity_over_sdf() {
+        let gameplay = GameplaySettings {
+            gravity: 1000,
+            lock_delay: 60000,
+            ..GameplaySettings::default()
+        };
+        let (mut input, mut game) = setup(0, 0, gameplay);
+        input.sdf = 20;
+        let line = game.active_piece().2;
+        key_down(&mut input, "Down", &mut game, 0);
+        // a line every 50 ms, however many updates happen in between
+        input.advance(&mut game, 0, 120);
+        assert_eq!(game.active_piece().2, line + 2);
+        input.process_key(&Key::from("Down"), false, &mut game, 120);
+        input.advance(&mut game, 120, 900);
+        assert_eq!(game.active_piece().2, line + 2);
+    }
+
+    #[test]
+    fn held_keys_carry_over_when_time_starts_again() {
+        let (mut input, mut game) = setup(0, 0, no_gravity());
+        input.arr = 10;
+        key_down(&mut input, "Left", &mut game, 500);
+        input.advance(&mut game, 500, 620);
+
+        // a retry takes game time back to 0 with left still held
+        let (_, mut game) = setup(0, 0, no_gravity());
+        let spawn_col = game.active_piece().3;
+        assert_eq!(input.rewind(0), [(Action::Left, true)]);
+        input.advance(&mut game, 0, 100);
+        assert_eq!(game.active_piece().3, spawn_col);
+        input.advance(&mut game, 100, 120);
+        assert_eq!(game.active_piece().3, spawn_col - 2);
+        input.advance(&mut game, 120, 1000);
+        assert_eq!(game.active_piece().3, wall_col(&game));
+    }
+
+    #[test]
+    fn each_controller_uses_its_own_binds() {
+        let mut settings = Settings::default();
+        settings.controller.controllers.insert(
+            "Lefty".to_string(),
+            ControllerBinds {
+                left: vec![Button::from("x")],
+                ..ControllerBinds::default()
+            },
+        );
+        let mut input = InputManager::new(&settings);
+        input.add_controller(1, "Generic");
+        input.add_controller(2, "Lefty");
+        assert_eq!(input.action_for_button(1, &Button::from("x")), None);
+        assert_eq!(
+            input.action_for_button(2, &Button::from("x")),
+            Some(Action::Left)
+        );
+        assert_eq!(
+            input.action_for_button(1, &Button::from("DPLeft")),
+            Some(Action::Left)
+        );
+        assert_eq!(input.action_for_button(3, &Button::from("dpleft")), None);
+    }
+
+    #[cfg(feature = "sdl")]
+    #[test]
+    fn controller_events_map_to_actions() {
+        use sdl2::controller::Button as PadButton;
+
+        let (mut input, mut game) = setup(0, 0, no_gravity());
+        let mut process =
+            |input: &mut InputManager, event| input.process_input(event, &mut game, 0);
+        let button = |button, pressed| {
+            if pressed {
+                Event::ControllerButtonDown {
+                    timestamp: 0,
+                    which: 3,
+                    button,
+                }
+            } else {
+                Event::ControllerButtonUp {
+                    timestamp: 0,
+                    which: 3,
+                    button,
+                }
+            }
+        };
+        let stick = |value| Event::ControllerAxisMotion {
+            timestamp: 0,
+            which: 3,
+            axis: Axis::LeftX,
+            value,
+        };
+
+        // pads are only listened to once they're added
+        assert_eq!(process(&mut input, button(PadButton::DPadLeft, true)), []);
+        input.add_controller(3, "Pad");
+        assert_eq!(
+            process(&mut input, button(PadButton::DPadLeft, true)),
+            [(Action::Left, true)]
+        );
+        assert_eq!(
+            process(&mut input, button(PadButton::DPadLeft, false)),
+            [(Action::Left, false)]
+        );
+        assert_eq!(
+            process(&mut input, button(PadButton::A, true)),
+            [(Action::RotateCounterclockwise, true)]
+        );
+
+        // the default dead zone is 30% of the stick's tilt
+        assert_eq!(process(&mut input, stick(9000)), []);
+        assert_eq!(process(&mut input, stick(20000)), [(Action::Right, true)]);
+        assert_eq!(process(&mut input, stick(30000)), []);
+        assert_eq!(process(&mut input, stick(0)), [(Action::Right, false)]);
+
+        // swinging all the way across lets go of one side and holds the other
+        assert_eq!(process(&mut input, stick(i16::MIN)), [(Action::Left, true)]);
+        assert_eq!(
+            process(&mut input, stick(i16::MAX)),
+            [(Action::Left, false), (Action::Right, true)]
+        );
+        assert!(input.events.iter().all(|e| e.0 != Input::Left));
+        assert_eq!(process(&mut input, stick(0)), [(Action::Right, false)]);
+
+        // unplugging a pad lets go of its stick
+        process(&mut input, stick(i16::MIN));
+        let removed = Event::ControllerDeviceRemoved {
+            timestamp: 0,
+            which: 3,
+        };
+        assert_eq!(process(&mut input, removed), [(Action::Left, false)]);
+        assert!(input.events.iter().all(|e| e.0 != Input::Left));
+        assert_eq!(process(&mut input, button(PadButton::DPadLeft, true)), []);
+    }
+}
@@ -0,0 +1,39 @@
+// the trainer as a library: the game rules, solver and everything else that
+// runs headless, so tools and tests build without sdl. drawing and editing
+// the board need the sdl feature, which the game itself is built with
+pub mod bot;
+pub mod clock;
+#[cfg(feature = "sdl")]
+pub mod controllers;
+pub mod drill;
+#[cfg(feature = "sdl")]
+pub mod editor;
+pub mod events;
+pub mod export;
+pub mod finesse;
+#[cfg(feature = "sdl")]
+pub mod font;
+pub mod fumen;
+pub mod garbage;
+#[cfg(feature = "sdl")]
+pub mod gui;
+pub mod history_manager;
+pub mod input_manager;
+mod macros;
+#[cfg(feature = "sdl")]
+pub mod menu;
+pub mod modes;
+pub mod net;
+pub mod practice;
+pub mod puzzle;
+#[cfg(feature = "sdl")]
+pub mod render;
+pub mod replay;
+pub mod scoring;
+pub mod session;
+pub mod settings;
+pub mod setups;
+pub mod solver;
+pub mod tbp;
+pub mod tetris;
+pub mod versus;
@@ -34,13 +34,14 @@ macro_rules! get_at {
 macro_rules! get_color {
     ($piece:expr) => {
         match $piece {
-            Piece::T => Some(crate::T_COLOR),
-            Piece::I => Some(crate::I_COLOR),
-            Piece::J => Some(crate::J_COLOR),
-            Piece::L => Some(crate::L_COLOR),
-            Piece::S => Some(crate::S_COLOR),
-            Piece::Z => Some(crate::Z_COLOR),
-            Piece::O => Some(crate::O_COLOR),
+            Piece::T => Some($crate::render::T_COLOR),
+            Piece::I => Some($crate::render::I_COLOR),
+            Piece::J => Some($crate::render::J_COLOR),
+            Piece::L => Some($crate::render::L_COLOR),
+            Piece::S => Some($crate::render::S_COLOR),
+            Piece::Z => Some($crate::render::Z_COLOR),
+            Piece::O => Some($crate::render::O_COLOR),
+            Piece::Garbage => Some($crate::render::GARBAGE_COLOR),
             Piece::None => None,
         }
     };
@@ -120,7 +121,7 @@ macro_rules! get_deltas {
                 Rotation::Flip => ((0, -1), (1, 0), (1, 1)),
             },
             Piece::O => ((0, 1), (1, 0), (1, 1)),
-            Piece::None => ((0, 0), (0, 0), (0, 0)),
+            Piece::Garbage | Piece::None => ((0, 0), (0, 0), (0, 0)),
         }
     };
 }
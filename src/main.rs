@@ -1,70 +1,837 @@
-mod history_manager;
-mod input_manager;
-mod macros;
-mod render;
-mod solver;
-mod tetris;
-
-extern crate sdl2;
-
-use crate::tetris::Tetris;
-use history_manager::HistoryManager;
-use input_manager::InputManager;
-use render::Renderer;
+use pc_trainer::bot::Bot;
+use pc_trainer::clock::{format_time, Clock, GameClock, MonotonicClock, Timestamp};
+use pc_trainer::controllers::Controllers;
+use pc_trainer::drill::Drill;
+use pc_trainer::editor::Editor;
+use pc_trainer::export::{self, EXPORT_DIR};
+use pc_trainer::gui::Gui;
+use pc_trainer::history_manager::HistoryManager;
+use pc_trainer::input_manager::InputManager;
+use pc_trainer::menu::SettingsMenu;
+use pc_trainer::modes::{GameMode, MODES};
+use pc_trainer::net::{Connection, NetVersus, DEFAULT_PORT};
+use pc_trainer::practice::{PracticeLog, PRACTICE_PATH};
+use pc_trainer::puzzle::{Problem, Puzzles, PUZZLES_PATH};
+use pc_trainer::render::Renderer;
+use pc_trainer::replay::{Playback, Replay, REPLAY_DIR};
+use pc_trainer::session::{Session, SessionLog, SESSIONS_PATH};
+use pc_trainer::settings::{GameplaySettings, Key, PerfectClearAction, Settings, SETTINGS_PATH};
+use pc_trainer::tbp::TbpBot;
+use pc_trainer::tetris::{State, Tetris};
+use pc_trainer::versus::Versus;
+use pc_trainer::{fumen, modes};
 use sdl2::event::Event;
-use sdl2::pixels::Color;
-use std::time::SystemTime;
-
-const TILE_SIZE: u32 = 32;
-
-const T_COLOR: Color = Color::RGBA(162, 50, 240, 255);
-const I_COLOR: Color = Color::RGBA(0, 183, 235, 255);
-const L_COLOR: Color = Color::RGBA(255, 117, 24, 255);
-const J_COLOR: Color = Color::RGBA(0, 0, 205, 255);
-const Z_COLOR: Color = Color::RGBA(220, 20, 60, 255);
-const S_COLOR: Color = Color::RGBA(50, 205, 50, 255);
-const O_COLOR: Color = Color::RGBA(255, 223, 0, 255);
+use sdl2::keyboard::Scancode;
+use std::cell::RefCell;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn main() -> Result<(), String> {
-    let mut history_manager = HistoryManager::new();
-    let mut boards = [Tetris::new(Some(&history_manager))];
+    let settings = Settings::load(SETTINGS_PATH)?;
+    let args: Vec<String> = std::env::args().collect();
 
     let (texture_creator, mut renderer) = Renderer::new()?;
     renderer.init(&texture_creator)?;
+    renderer.set_visual(&settings.visual);
+
+    match args.get(1).map(String::as_str) {
+        Some("replay") => {
+            let path = args.get(2).ok_or("usage: pc-trainer replay <file>")?;
+            run_replay(&mut renderer, Replay::load(path)?)
+        }
+        Some("fumen") => {
+            let code = args.get(2).ok_or("usage: pc-trainer fumen <fumen>")?;
+            let pages = fumen::decode(code)?;
+            let page = pages.first().ok_or("the fumen has no pages")?;
+            run_game(&mut renderer, &settings, "free", Some(page))
+        }
+        Some("puzzle") => {
+            let path = args.get(2).map(String::as_str).unwrap_or(PUZZLES_PATH);
+            run_puzzle(&mut renderer, &settings, Problem::load_all(path)?)
+        }
+        Some("drill") => run_drill(&mut renderer, &settings),
+        Some("tbp") => {
+            let command = args[2..].join(" ");
+            if command.is_empty() {
+                return Err("usage: pc-trainer tbp <bot command>".to_string());
+            }
+            run_tbp(&mut renderer, &settings, &command)
+        }
+        Some("versus") => match args.get(2).map(String::as_str) {
+            Some("bot") => run_versus(&mut renderer, &settings, true),
+            None => run_versus(&mut renderer, &settings, false),
+            Some(_) => Err("usage: pc-trainer versus [bot]".to_string()),
+        },
+        Some("host") => {
+            let port = match args.get(2) {
+                Some(port) => port.parse().map_err(|_| format!("bad port \"{}\"", port))?,
+                None => DEFAULT_PORT,
+            };
+            // nothing is drawn while waiting, so say what for beforehand
+            let empty = Tetris::new(None, &settings.gameplay, 0);
+            let text = [
+                "WAITING FOR AN OPPONENT".to_string(),
+                format!("ON PORT {}", port),
+            ];
+            renderer.render(std::slice::from_ref(&empty), &text)?;
+            let mut connection = Connection::host(port)?;
+            let seed = connection.handshake(Some(settings.gameplay.seed()))?;
+            run_net_versus(&mut renderer, &settings, connection, seed)
+        }
+        Some("join") => {
+            let address = args.get(2).ok_or("usage: pc-trainer join <host:port>")?;
+            let mut connection = Connection::join(address)?;
+            let seed = connection.handshake(None)?;
+            run_net_versus(&mut renderer, &settings, connection, seed)
+        }
+        Some(mode) if MODES.contains(&mode) => run_game(&mut renderer, &settings, mode, None),
+        _ => run_game(&mut renderer, &settings, "free", None),
+    }
+}
+
+// plays games in the mode called `mode_name`, each after a countdown. F2
+// switches between playing and editing the board, F3 pauses, F4 starts over
+// with a new seed and F9 retries the same one. F5 copies the board as fumen,
+// F6 copies every placement so far, F7 loads the fumen on the clipboard and F8
+// exports the pieces placed and every session so far. escape opens the
+// settings
+fn run_game(
+    renderer: &mut Renderer,
+    settings: &Settings,
+    mode_name: &str,
+    page: Option<&fumen::Page>,
+) -> Result<(), String> {
+    let mut mode = modes::from_name(mode_name, &settings.modes)?;
+    let history_manager = RefCell::new(HistoryManager::new());
+    // the clock starts at 0 along with the game, once counted down
+    let mut round = Round::new(&settings.gameplay);
+    let mut boards = [Tetris::new(
+        Some(&history_manager),
+        &settings.gameplay,
+        round.seed(),
+    )];
+    let mut replay = Replay::new(round.seed(), settings, mode_name);
+    // replays can't reproduce positions loaded from fumen, so stop recording
+    let mut recording = page.is_none();
 
     let mut event_pump = renderer.sdl_context().event_pump()?;
 
     let event_subsystem = renderer.sdl_context().event().map_err(|e| e.to_string())?;
-    let mut input_manager = InputManager::new();
+    let clipboard = renderer.sdl_context().video()?.clipboard();
+    let mut input_manager = InputManager::new(settings);
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
+    let mut editor = Editor::new();
     // game loop
-    let mut current_time = SystemTime::now();
-    // TODO: make game start later
-    boards[0].start(current_time);
+    let mut current_time = 0;
+    start_game(&mut boards[0], mode.as_mut(), round.seed(), page);
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    let mut session = Session::new(started.as_secs());
+    session.watch(&mut boards[0]);
+    // the settings menu changes these as the game goes
+    let mut settings = settings.clone();
+    let mut gui = Gui::new();
+    let mut menu = SettingsMenu::new();
+    // what the last hotkey did, shown under the hud
+    let mut status: Option<String> = None;
     'game_loop: loop {
         // calculate frame time and fps
-        let new_time = SystemTime::now();
-        let frame_time = new_time
-            .duration_since(current_time)
-            .map_err(|e| e.to_string())?;
+        let new_time = round.now();
+        let frame_time = new_time - current_time;
+        let fps = 1000 / frame_time.max(1);
+
+        // step the playing board up to now, processing das, gravity and locking
+        input_manager.advance(&mut boards[0], current_time, new_time);
         current_time = new_time;
-        let fps = (1.0 / frame_time.as_secs_f64()) as u32;
+        mode.update(&mut boards[0], current_time);
 
-        // update input manager to process das
-        input_manager.update(new_time, &mut boards[0]);
         // handle events
         for event in event_pump.poll_iter() {
-            let timestamp = SystemTime::now();
+            // a key pressed while the menu waits for one is bound, not played
+            if let Event::KeyDown {
+                scancode: Some(scancode),
+                repeat: false,
+                ..
+            } = event
+            {
+                if menu.is_capturing() {
+                    menu.capture(scancode);
+                    continue;
+                }
+            }
+            if let Some(restarted) = round.process_event(&event, &settings.gameplay) {
+                if restarted {
+                    if recording && !replay.events.is_empty() {
+                        save_replay(&mut replay, current_time)?;
+                    }
+                    let seed = round.seed();
+                    *history_manager.borrow_mut() = HistoryManager::new();
+                    mode = modes::from_name(mode_name, &settings.modes)?;
+                    boards[0] = Tetris::new(Some(&history_manager), &settings.gameplay, seed);
+                    current_time = round.now();
+                    start_game(&mut boards[0], mode.as_mut(), seed, page);
+                    session.watch(&mut boards[0]);
+                    replay = Replay::new(seed, &settings, mode_name);
+                    for (action, pressed) in input_manager.rewind(current_time) {
+                        replay.record(current_time, action, pressed);
+                    }
+                    recording = page.is_none();
+                    status = None;
+                }
+                continue;
+            }
             match event {
                 Event::Quit { .. } => break 'game_loop,
-                _ => input_manager.process_input(event, &mut boards[0], timestamp),
+                Event::KeyDown {
+                    scancode: Some(Scancode::F5),
+                    ..
+                } => {
+                    let code = fumen::encode(&[fumen::page_from_game(&boards[0])])?;
+                    clipboard.set_clipboard_text(&code)?;
+                    status = Some("COPIED THE BOARD".to_string());
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::F6),
+                    ..
+                } => {
+                    let pages = fumen::pages_from_history(&history_manager.borrow().branch());
+                    if !pages.is_empty() {
+                        let code = fumen::encode(&pages)?;
+                        clipboard.set_clipboard_text(&code)?;
+                        status = Some(format!("COPIED {} PLACEMENTS", pages.len()));
+                    }
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::F7),
+                    ..
+                } => match fumen::decode(&clipboard.clipboard_text()?) {
+                    Ok(pages) if !pages.is_empty() => {
+                        fumen::load_page(&mut boards[0], &pages[0], current_time);
+                        recording = false;
+                        status = None;
+                    }
+                    Ok(_) => status = Some("THE FUMEN HAS NO PAGES".to_string()),
+                    Err(e) => status = Some(format!("NO FUMEN ON THE CLIPBOARD: {}", e)),
+                },
+                Event::KeyDown {
+                    scancode: Some(Scancode::F8),
+                    ..
+                } => {
+                    let pieces = export::pieces(&history_manager.borrow().branch());
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_err(|e| e.to_string())?;
+                    let name = format!("{}-{}", now.as_secs(), mode.name());
+                    let exported = SessionLog::load(SESSIONS_PATH).and_then(|mut log| {
+                        log.sessions.push(session.stats().clone());
+                        export::export(EXPORT_DIR, &name, &pieces, &log.sessions)
+                    });
+                    status = Some(match exported {
+                        Ok(paths) => format!("EXPORTED {} FILES TO {}", paths.len(), EXPORT_DIR),
+                        Err(e) => format!("COULD NOT EXPORT: {}", e),
+                    });
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    repeat: false,
+                    ..
+                } => menu.toggle(&settings),
+                Event::KeyDown {
+                    scancode: Some(Scancode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    if boards[0].state() == State::Editing {
+                        boards[0].play(current_time);
+                    } else {
+                        boards[0].edit();
+                        recording = false;
+                    }
+                }
+                _ => {
+                    controllers.process_event(&event, std::slice::from_mut(&mut input_manager));
+                    if menu.is_open() {
+                        gui.process_event(&event);
+                    }
+                    // keys let go of still reach the game, so none stay held
+                    let for_menu = menu.is_open() && !lets_go(&event);
+                    if for_menu || round.holds_back(&event) {
+                        continue;
+                    }
+                    if boards[0].state() == State::Editing {
+                        editor.process_event(&event, &mut boards[0], renderer, current_time);
+                    }
+                    for (action, pressed) in
+                        input_manager.process_input(event, &mut boards[0], current_time)
+                    {
+                        replay.record(current_time, action, pressed);
+                        mode.update(&mut boards[0], current_time);
+                        if pressed {
+                            session.key_pressed();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut text = match boards[0].state() {
+            State::Editing => editor.hud(&boards[0]),
+            _ => mode.hud(&boards[0], current_time),
+        };
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        text.extend(status.clone());
+        session.update(&boards[0], current_time);
+        renderer.set_panel(session.stats().panel());
+
+        let mut changed = None;
+        let (textures, primitives) = gui.run(renderer.window_size(), current_time, |ctx| {
+            changed = menu.show(ctx);
+        });
+        renderer.set_gui(textures, primitives)?;
+        if let Some(new_settings) = changed {
+            settings = new_settings;
+            input_manager.apply_settings(&settings);
+            boards[0].apply_settings(&settings.gameplay);
+            renderer.set_visual(&settings.visual);
+            // replays play back with the settings they were recorded with
+            recording = false;
+        }
+        renderer.render(&boards, &text)?;
+    }
+
+    if session.stats().pieces > 0 {
+        let mut log = SessionLog::load(SESSIONS_PATH)?;
+        log.sessions.push(session.stats().clone());
+        log.save(SESSIONS_PATH)?;
+    }
+
+    if recording {
+        save_replay(&mut replay, current_time)?;
+    }
+
+    Ok(())
+}
+
+// deals a new game of `mode` on `game`, from the fumen page if there is one
+fn start_game(game: &mut Tetris, mode: &mut dyn GameMode, seed: u64, page: Option<&fumen::Page>) {
+    mode.setup(game, seed, 0);
+    game.start(0);
+    if let Some(page) = page {
+        fumen::load_page(game, page, 0);
+    }
+}
+
+// saves a game played up to `length` to the replay directory
+fn save_replay(replay: &mut Replay, length: Timestamp) -> Result<(), String> {
+    replay.length = length;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    replay.save(format!(
+        "{}/{}-{}.json",
+        REPLAY_DIR,
+        now.as_secs(),
+        replay.mode
+    ))
+}
+
+// the countdown, pause and restarts every loop played live shares. F3 pauses,
+// F4 starts over with a new seed and F9 retries the same one, both counting
+// down again first
+struct Round {
+    clock: GameClock<MonotonicClock>,
+    seed: u64,
+}
+
+impl Round {
+    fn new(settings: &GameplaySettings) -> Round {
+        Round {
+            clock: GameClock::new(MonotonicClock::new(), settings.countdown as Timestamp),
+            seed: settings.seed(),
+        }
+    }
+
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    // the seed to deal the game from
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // takes game time back to 0, with a new seed unless retrying
+    fn restart(&mut self, settings: &GameplaySettings, new_seed: bool) {
+        if new_seed {
+            self.seed = settings.seed();
+        }
+        self.clock.restart(settings.countdown as Timestamp);
+    }
+
+    // handles the round's keys. None if `event` isn't one of them, otherwise
+    // whether the game has to be dealt again
+    fn process_event(&mut self, event: &Event, settings: &GameplaySettings) -> Option<bool> {
+        match *event {
+            Event::KeyDown {
+                scancode: Some(Scancode::F3),
+                repeat: false,
+                ..
+            } => {
+                self.clock.toggle_pause();
+                Some(false)
+            }
+            Event::KeyDown {
+                scancode: Some(scancode @ (Scancode::F4 | Scancode::F9)),
+                repeat: false,
+                ..
+            } => {
+                self.restart(settings, scancode == Scancode::F4);
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+
+    // counting down or paused, with game time standing still
+    fn waiting(&self) -> bool {
+        self.clock.countdown().is_some() || self.clock.is_paused()
+    }
+
+    // whether to keep `event` from the game, as it waits or the trainer
+    // listens for the key somewhere. keys and buttons let go of still reach
+    // it, so none stay held
+    fn holds_back(&self, event: &Event) -> bool {
+        let released = lets_go(event);
+        let reserved = matches!(
+            event,
+            Event::KeyDown { scancode: Some(scancode), .. } if Key::from(*scancode).is_reserved()
+        );
+        reserved || self.waiting() && !released
+    }
+
+    fn hud(&self) -> Vec<String> {
+        let mut text = Vec::new();
+        if let Some(seconds) = self.clock.countdown() {
+            text.push(format!("READY {}", seconds));
+        }
+        if self.clock.is_paused() {
+            text.push("PAUSED".to_string());
+        }
+        text
+    }
+}
+
+// whether `event` lets go of a key, a button or a whole controller
+fn lets_go(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyUp { .. }
+            | Event::ControllerButtonUp { .. }
+            | Event::ControllerDeviceRemoved { .. }
+    )
+}
+
+// plays back a recorded game. space pauses, left/right seek by 5 seconds,
+// up/down change the speed and home restarts
+fn run_replay(renderer: &mut Renderer, replay: Replay) -> Result<(), String> {
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut playback = Playback::new(replay);
+
+    let clock = MonotonicClock::new();
+    let mut current_time = clock.now();
+    'replay_loop: loop {
+        let new_time = clock.now();
+        playback.update(new_time - current_time);
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'replay_loop,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    ..
+                } => match scancode {
+                    Scancode::Space => playback.toggle_pause(),
+                    Scancode::Left => playback.seek(playback.time().saturating_sub(5000)),
+                    Scancode::Right => playback.seek(playback.time() + 5000),
+                    Scancode::Up => playback.set_speed(playback.speed() * 2.0),
+                    Scancode::Down => playback.set_speed(playback.speed() / 2.0),
+                    Scancode::Home => playback.seek(0),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let mut text = vec![
+            "REPLAY".to_string(),
+            format!(
+                "{} / {}",
+                format_time(playback.time()),
+                format_time(playback.length())
+            ),
+            format!("SPEED X{}", playback.speed()),
+        ];
+        if playback.paused() {
+            text.push("PAUSED".to_string());
+        }
+        text.extend(playback.mode().hud(playback.game(), playback.time()));
+        renderer.render(std::slice::from_ref(playback.game()), &text)?;
+    }
+
+    Ok(())
+}
+
+// drills the setup that fits each new bag, after a countdown. F4 starts over
+// with a new bag, F9 drills the same bag again and F3 pauses
+fn run_drill(renderer: &mut Renderer, settings: &Settings) -> Result<(), String> {
+    let mut log = PracticeLog::load(PRACTICE_PATH)?;
+    let history_manager = RefCell::new(HistoryManager::new());
+    let mut round = Round::new(&settings.gameplay);
+    let mut game = Tetris::new(Some(&history_manager), &settings.gameplay, round.seed());
+    let mut drill = Drill::new(settings.visual.previews);
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut input_manager = InputManager::new(settings);
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
+
+    let mut current_time = 0;
+    game.start(current_time);
+    drill.start(&game, &mut log, current_time);
+    'drill_loop: loop {
+        let new_time = round.now();
+        input_manager.advance(&mut game, current_time, new_time);
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            let restarted = match event {
+                Event::Quit { .. } => break 'drill_loop,
+                _ => match round.process_event(&event, &settings.gameplay) {
+                    Some(restarted) => restarted,
+                    None => {
+                        controllers.process_event(&event, std::slice::from_mut(&mut input_manager));
+                        if !round.holds_back(&event) {
+                            input_manager.process_input(event, &mut game, current_time);
+                        }
+                        false
+                    }
+                },
+            };
+            if restarted {
+                *history_manager.borrow_mut() = HistoryManager::new();
+                game = Tetris::new(Some(&history_manager), &settings.gameplay, round.seed());
+                current_time = round.now();
+                input_manager.rewind(current_time);
+                game.start(current_time);
+                drill.start(&game, &mut log, current_time);
+            }
+        }
+
+        drill.update(&mut game, &history_manager, &mut log, current_time);
+        renderer.set_hint(drill.hint());
+        let mut text = drill.hud(&game, &history_manager.borrow());
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        renderer.render(std::slice::from_ref(&game), &text)?;
+    }
+
+    log.save(PRACTICE_PATH)
+}
+
+// free play with an external tbp bot's suggestion for each piece drawn over
+// the board, to compare its advice with the solver's. F4 starts over, F9
+// plays the same bags again and F3 pauses
+fn run_tbp(renderer: &mut Renderer, settings: &Settings, command: &str) -> Result<(), String> {
+    let mut bot = TbpBot::launch(command)?;
+    let mut round = Round::new(&settings.gameplay);
+    let mut game = Tetris::new(None, &settings.gameplay, round.seed());
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut input_manager = InputManager::new(settings);
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
+
+    let mut current_time = 0;
+    game.set_on_perfect_clear(PerfectClearAction::Continue);
+    game.start(current_time);
+    bot.start(&game)?;
+    'tbp_loop: loop {
+        let new_time = round.now();
+        input_manager.advance(&mut game, current_time, new_time);
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            let restarted = match event {
+                Event::Quit { .. } => break 'tbp_loop,
+                _ => match round.process_event(&event, &settings.gameplay) {
+                    Some(restarted) => restarted,
+                    None => {
+                        controllers.process_event(&event, std::slice::from_mut(&mut input_manager));
+                        if !round.holds_back(&event) {
+                            input_manager.process_input(event, &mut game, current_time);
+                        }
+                        false
+                    }
+                },
+            };
+            if restarted {
+                game = Tetris::new(None, &settings.gameplay, round.seed());
+                game.set_on_perfect_clear(PerfectClearAction::Continue);
+                current_time = round.now();
+                input_manager.rewind(current_time);
+                game.start(current_time);
+                bot.start(&game)?;
+            }
+        }
+
+        bot.update(&game)?;
+        let mut text = vec![bot.name().to_uppercase()];
+        if game.state() == State::Playing {
+            renderer.set_hint(bot.hint()?);
+        } else {
+            renderer.set_hint(None);
+            text.push("F4 TO RESTART".to_string());
+        }
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        renderer.render(std::slice::from_ref(&game), &text)?;
+    }
+
+    Ok(())
+}
+
+// two players on one keyboard, each with their own keymap, or one player
+// against the solver bot. return starts a rematch once someone has won, and
+// F4 or F9 at any time, with new bags or the same ones. F3 pauses
+fn run_versus(
+    renderer: &mut Renderer,
+    settings: &Settings,
+    against_bot: bool,
+) -> Result<(), String> {
+    let mut round = Round::new(&settings.gameplay);
+    let new_boards = |seed| {
+        [
+            Tetris::new(None, &settings.gameplay, seed),
+            Tetris::new(None, &settings.gameplay, seed),
+        ]
+    };
+    let mut boards = new_boards(round.seed());
+    let mut input_managers = [
+        InputManager::with_keybinds(settings, &settings.versus.player1),
+        InputManager::with_keybinds(settings, &settings.versus.player2),
+    ];
+    let mut bot = None;
+    if against_bot {
+        // a lone player keeps their usual keys
+        input_managers[0] = InputManager::new(settings);
+        bot = Some(Bot::new(settings, rand::random()));
+    }
+    let players = if against_bot { 1 } else { 2 };
+    let mut versus = Versus::new();
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
+
+    let mut current_time = 0;
+    versus.start(&mut boards, current_time);
+    'versus_loop: loop {
+        let new_time = round.now();
+        for (input_manager, board) in input_managers
+            .iter_mut()
+            .zip(boards.iter_mut())
+            .take(players)
+        {
+            input_manager.advance(board, current_time, new_time);
+        }
+        if let Some(bot) = &mut bot {
+            bot.advance(&mut boards[1], current_time, new_time);
+        }
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            let restarted = match event {
+                Event::Quit { .. } => break 'versus_loop,
+                Event::KeyDown {
+                    scancode: Some(Scancode::Return),
+                    repeat: false,
+                    ..
+                } if versus.winner().is_some() => {
+                    round.restart(&settings.gameplay, true);
+                    true
+                }
+                _ => match round.process_event(&event, &settings.gameplay) {
+                    Some(restarted) => restarted,
+                    None => {
+                        controllers.process_event(&event, &mut input_managers[..players]);
+                        if !round.holds_back(&event) {
+                            for (input_manager, board) in input_managers
+                                .iter_mut()
+                                .zip(boards.iter_mut())
+                                .take(players)
+                            {
+                                input_manager.process_input(event.clone(), board, current_time);
+                            }
+                        }
+                        false
+                    }
+                },
+            };
+            if restarted {
+                boards = new_boards(round.seed());
+                bot = bot.map(|_| Bot::new(settings, rand::random()));
+                current_time = round.now();
+                for input_manager in &mut input_managers {
+                    input_manager.rewind(current_time);
+                }
+                versus.start(&mut boards, current_time);
             }
         }
 
-        // update playing board
-        boards[0].update(current_time);
+        // the bot waits out the countdown and pauses along with the player
+        if let Some(bot) = bot.as_mut().filter(|_| !round.waiting()) {
+            bot.update(&mut boards[1], current_time);
+        }
+        versus.update(&mut boards, current_time);
+        let mut text = versus.hud();
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        renderer.render(&boards, &text)?;
+    }
+
+    Ok(())
+}
+
+// plays against another pc-trainer over the network, showing the opponent's
+// board next to this one. both sides play the bags from `seed`, after a
+// countdown. there's no pausing or restarting, as the opponent's game goes on
+// regardless
+fn run_net_versus(
+    renderer: &mut Renderer,
+    settings: &Settings,
+    connection: Connection,
+    seed: u64,
+) -> Result<(), String> {
+    let mut boards = [
+        Tetris::new(None, &settings.gameplay, seed),
+        Tetris::new(None, &settings.gameplay, 0),
+    ];
+    let mut versus = NetVersus::new(connection);
+    let mut input_manager = InputManager::new(settings);
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
 
-        renderer.render(&boards)?;
+    let round = Round::new(&settings.gameplay);
+    let mut current_time = 0;
+    versus.start(&mut boards, current_time);
+    'net_versus_loop: loop {
+        let new_time = round.now();
+        input_manager.advance(&mut boards[0], current_time, new_time);
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'net_versus_loop,
+                _ => {
+                    controllers.process_event(&event, std::slice::from_mut(&mut input_manager));
+                    if !round.holds_back(&event) {
+                        input_manager.process_input(event, &mut boards[0], current_time);
+                    }
+                }
+            }
+        }
+
+        versus.update(&mut boards, current_time)?;
+        let mut text = versus.hud();
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        renderer.render(&boards, &text)?;
     }
 
     Ok(())
 }
+
+// plays through perfect clear problems, each after a countdown. F4 and F9
+// both retry, as a problem's pieces are the same every time. F3 pauses, return
+// and backspace move to the next and previous problem and page up/down browse
+// missed solutions
+fn run_puzzle(
+    renderer: &mut Renderer,
+    settings: &Settings,
+    problems: Vec<Problem>,
+) -> Result<(), String> {
+    let mut log = PracticeLog::load(PRACTICE_PATH)?;
+    let mut puzzles = Puzzles::new(problems, &log)?;
+    let mut round = Round::new(&settings.gameplay);
+    let mut game = Tetris::new(None, &settings.gameplay, round.seed());
+    let mut event_pump = renderer.sdl_context().event_pump()?;
+    let mut input_manager = InputManager::new(settings);
+    let mut controllers = Controllers::new(renderer.sdl_context())?;
+
+    let mut current_time = 0;
+    game.start(current_time);
+    puzzles.start(&mut game, current_time);
+    'puzzle_loop: loop {
+        let new_time = round.now();
+        input_manager.advance(&mut game, current_time, new_time);
+        current_time = new_time;
+
+        for event in event_pump.poll_iter() {
+            let restarted = match event {
+                Event::Quit { .. } => break 'puzzle_loop,
+                Event::KeyDown {
+                    scancode:
+                        Some(
+                            scancode @ (Scancode::Return
+                            | Scancode::Backspace
+                            | Scancode::PageUp
+                            | Scancode::PageDown),
+                        ),
+                    repeat: false,
+                    ..
+                } => {
+                    // a new problem is counted down like a retry
+                    if matches!(scancode, Scancode::Return | Scancode::Backspace) {
+                        round.restart(&settings.gameplay, false);
+                        current_time = round.now();
+                        input_manager.rewind(current_time);
+                    }
+                    match scancode {
+                        Scancode::Return => puzzles.next(&mut game, current_time),
+                        Scancode::Backspace => puzzles.previous(&mut game, current_time),
+                        Scancode::PageUp => puzzles.show_previous_solution(),
+                        _ => puzzles.show_next_solution(),
+                    }
+                    false
+                }
+                _ => match round.process_event(&event, &settings.gameplay) {
+                    Some(restarted) => restarted,
+                    None => {
+                        controllers.process_event(&event, std::slice::from_mut(&mut input_manager));
+                        if !round.holds_back(&event) {
+                            input_manager.process_input(event, &mut game, current_time);
+                        }
+                        false
+                    }
+                },
+            };
+            if restarted {
+                current_time = round.now();
+                input_manager.rewind(current_time);
+                puzzles.start(&mut game, current_time);
+            }
+        }
+
+        puzzles.update(&game, &mut log, current_time);
+        let mut text = puzzles.hud(&game);
+        text.extend(round.hud());
+        text.extend(controllers.hud());
+        match puzzles.shown_board() {
+            Some(board) => {
+                let mut view = game.clone();
+                view.edit();
+                view.set_board(board);
+                renderer.render(&[view], &text)?;
+            }
+            None => renderer.render(std::slice::from_ref(&game), &text)?,
+        }
+    }
+
+    log.save(PRACTICE_PATH)
+}
@@ -0,0 +1,297 @@
+use std::ops::RangeInclusive;
+
+use egui::{Context, DragValue, Grid, Slider, Ui};
+use sdl2::keyboard::Scancode;
+
+use crate::settings::{Key, Keybinds, Randomizer, Settings, SETTINGS_PATH};
+
+// the in-game settings window. changes are played with as soon as they're
+// valid, and only written to the settings file when saved
+pub struct SettingsMenu {
+    open: bool,
+    draft: Settings,
+    capturing: Option<usize>, // the action waiting for a key to be pressed
+    captured: bool,           // whether a key was bound since the menu was last shown
+    status: Option<String>,   // why the draft can't be used, where it was saved, etc
+}
+
+impl Default for SettingsMenu {
+    fn default() -> Self {
+        SettingsMenu::new()
+    }
+}
+
+impl SettingsMenu {
+    pub fn new() -> SettingsMenu {
+        SettingsMenu {
+            open: false,
+            draft: Settings::default(),
+            capturing: None,
+            captured: false,
+            status: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    // opens the menu on the settings being played with, or closes it
+    pub fn toggle(&mut self, settings: &Settings) {
+        self.open = !self.open;
+        if self.open {
+            self.draft = settings.clone();
+            self.status = None;
+        }
+        self.capturing = None;
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    // binds the key pressed while an action waits for one. escape gives up
+    pub fn capture(&mut self, scancode: Scancode) {
+        let index = match self.capturing.take() {
+            Some(index) => index,
+            None => return,
+        };
+        let key = Key::from(scancode);
+        if key == Key::from("Escape") {
+            return;
+        }
+        if key.is_reserved() {
+            self.status = Some(format!("{} is used by the trainer", key.name()));
+            return;
+        }
+        let name = key.name().to_string();
+        self.status = self
+            .draft
+            .keybinds
+            .bind(index, key)
+            .map(|other| format!("{} was taken from {}", name, other));
+        self.captured = true;
+    }
+
+    // draws the menu, returning the new settings once a change leaves them
+    // valid
+    pub fn show(&mut self, ctx: &Context) -> Option<Settings> {
+        if !self.open {
+            return None;
+        }
+        let mut open = true;
+        let captured = std::mem::take(&mut self.captured);
+        let mut changed = captured;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .vscroll(true)
+            .show(ctx, |ui| changed |= self.contents(ui));
+        self.open = open;
+        if !open {
+            self.capturing = None;
+        }
+        if !changed {
+            return None;
+        }
+        match self.draft.validate() {
+            Ok(()) => {
+                // a key taken from another action is still worth mentioning
+                if !captured {
+                    self.status = None;
+                }
+                Some(self.draft.clone())
+            }
+            Err(e) => {
+                self.status = Some(e);
+                None
+            }
+        }
+    }
+
+    // returns whether anything was changed
+    fn contents(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        ui.collapsing("Handling", |ui| {
+            let handling = &mut self.draft.handling;
+            changed |= slider(ui, "ARR", &mut handling.arr, 0..=1000, " ms");
+            changed |= slider(ui, "DAS", &mut handling.das, 0..=5000, " ms");
+            changed |= slider(ui, "DCD", &mut handling.dcd, 0..=1000, " ms");
+            changed |= slider(ui, "SDF", &mut handling.sdf, 1..=100, "x");
+            changed |= slider(
+                ui,
+                "Prevent accidental hard drop",
+                &mut handling.pahd,
+                0..=1000,
+                " ms",
+            );
+            changed |= slider(
+                ui,
+                "Cancel DAS when changing direction",
+                &mut handling.cdwcd,
+                0..=1000,
+                " ms",
+            );
+        });
+
+        ui.collapsing("Keybinds", |ui| changed |= self.keybinds(ui));
+
+        ui.collapsing("Gameplay", |ui| {
+            let gameplay = &mut self.draft.gameplay;
+            changed |= slider(ui, "Gravity", &mut gameplay.gravity, 0..=60000, " ms");
+            changed |= slider(ui, "Lock delay", &mut gameplay.lock_delay, 0..=60000, " ms");
+            changed |= slider(
+                ui,
+                "Lock resets",
+                &mut gameplay.max_lock_reset_count,
+                0..=1000,
+                "",
+            );
+            changed |= slider(ui, "Countdown", &mut gameplay.countdown, 0..=10000, " ms");
+            ui.horizontal(|ui| {
+                ui.label("Randomizer");
+                let randomizer = &mut gameplay.randomizer;
+                changed |= ui
+                    .radio_value(randomizer, Randomizer::SevenBag, "7-bag")
+                    .changed();
+                changed |= ui
+                    .radio_value(randomizer, Randomizer::Random, "Random")
+                    .changed();
+            });
+            ui.horizontal(|ui| {
+                let mut fixed = gameplay.seed.is_some();
+                if ui.checkbox(&mut fixed, "Fixed seed").changed() {
+                    gameplay.seed = fixed.then(rand::random);
+                    changed = true;
+                }
+                if let Some(seed) = &mut gameplay.seed {
+                    changed |= ui.add(DragValue::new(seed)).changed();
+                }
+            });
+            ui.weak("The seed is used from the next game on");
+        });
+
+        ui.collapsing("Visual", |ui| {
+            let visual = &mut self.draft.visual;
+            changed |= ui.checkbox(&mut visual.ghost, "Ghost piece").changed();
+            changed |= slider(ui, "Previews", &mut visual.previews, 0..=5, "");
+            changed |= ui
+                .checkbox(&mut visual.session_panel, "Session stats")
+                .changed();
+        });
+
+        ui.separator();
+        if ui.button("Save").clicked() {
+            self.status = Some(match self.draft.save(SETTINGS_PATH) {
+                Ok(()) => format!("Saved to {}", SETTINGS_PATH),
+                Err(e) => e,
+            });
+        }
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+        changed
+    }
+
+    // each action's keys, which unbind when clicked, with + to press a new
+    // one. returns whether anything was changed
+    fn keybinds(&mut self, ui: &mut Ui) -> bool {
+        let mut unbind = None;
+        let mut reset = None;
+        let actions: Vec<(&str, Vec<Key>)> = self
+            .draft
+            .keybinds
+            .actions()
+            .iter()
+            .map(|(name, keys)| (*name, keys.to_vec()))
+            .collect();
+        Grid::new("keybinds").num_columns(2).show(ui, |ui| {
+            for (index, (name, keys)) in actions.into_iter().enumerate() {
+                ui.label(name);
+                ui.horizontal(|ui| {
+                    for key in keys {
+                        if ui.button(key.name()).on_hover_text("Unbind").clicked() {
+                            unbind = Some((index, key));
+                        }
+                    }
+                    if self.capturing == Some(index) {
+                        ui.label("Press a key...");
+                    } else if ui.button("+").on_hover_text("Bind another key").clicked() {
+                        self.capturing = Some(index);
+                    }
+                    if ui.button("Reset").clicked() {
+                        reset = Some(index);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        let reset_all = ui.button("Reset all keybinds").clicked();
+
+        let keybinds = &mut self.draft.keybinds;
+        if let Some((index, key)) = &unbind {
+            keybinds.unbind(*index, key);
+        }
+        if let Some(index) = reset {
+            keybinds.reset(index);
+        }
+        if reset_all {
+            *keybinds = Keybinds::default();
+        }
+        unbind.is_some() || reset.is_some() || reset_all
+    }
+}
+
+fn slider(
+    ui: &mut Ui,
+    text: &str,
+    value: &mut u32,
+    range: RangeInclusive<u32>,
+    suffix: &str,
+) -> bool {
+    // the long ranges are mostly used near their start
+    let logarithmic = *range.end() > 1000;
+    ui.add(
+        Slider::new(value, range)
+            .text(text)
+            .suffix(suffix)
+            .logarithmic(logarithmic),
+    )
+    .changed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_pressed_key_is_bound_and_taken_from_its_old_action() {
+        let mut menu = SettingsMenu::new();
+        menu.toggle(&Settings::default());
+        menu.capturing = Some(0);
+        menu.capture(Scancode::LShift);
+        assert!(!menu.is_capturing());
+        assert!(menu.captured);
+        assert_eq!(
+            menu.draft.keybinds.left,
+            vec![Key::from("Left"), Key::from("Left Shift")]
+        );
+        assert_eq!(
+            menu.status,
+            Some("Left Shift was taken from swap".to_string())
+        );
+    }
+
+    #[test]
+    fn keys_the_trainer_uses_are_not_bound() {
+        let mut menu = SettingsMenu::new();
+        menu.toggle(&Settings::default());
+        menu.capturing = Some(3);
+        menu.capture(Scancode::PageDown);
+        assert_eq!(menu.draft.keybinds.harddrop, vec![Key::from("Space")]);
+        assert_eq!(
+            menu.status,
+            Some("PageDown is used by the trainer".to_string())
+        );
+    }
+}
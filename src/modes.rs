@@ -0,0 +1,468 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::clock::{format_time, Timestamp};
+use crate::garbage::garbage_lines;
+use crate::settings::{ModeSettings, PerfectClearAction};
+use crate::tetris::{Piece, State, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+pub const MODES: [&str; 5] = ["free", "sprint", "ultra", "dig", "pcloop"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Completed,
+    ToppedOut,
+}
+
+// the rules a game is played by: how it's set up, when it's over and what the
+// hud shows. modes only ever look at the game, so replays end the same way
+pub trait GameMode {
+    fn name(&self) -> &'static str;
+    // prepares a game that hasn't started yet. anything random is drawn from
+    // `seed` so the same seed always gives the same game
+    fn setup(&mut self, game: &mut Tetris, seed: u64, timestamp: Timestamp);
+    // checks the win and lose conditions, ending the game once either is met
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp);
+    fn outcome(&self) -> Option<Outcome>;
+    fn hud(&self, game: &Tetris, timestamp: Timestamp) -> Vec<String>;
+}
+
+pub fn from_name(name: &str, settings: &ModeSettings) -> Result<Box<dyn GameMode>, String> {
+    Ok(match name {
+        "free" => Box::new(Free::new()),
+        "sprint" => Box::new(Sprint::new(settings.sprint_lines)),
+        "ultra" => Box::new(Ultra::new(settings.ultra_time as Timestamp)),
+        "dig" => Box::new(Dig::new(settings.dig_lines, settings.dig_messiness)),
+        "pcloop" => Box::new(PcLoop::new()),
+        _ => {
+            return Err(format!(
+                "unknown mode \"{}\", expected one of {}",
+                name,
+                MODES.join(", ")
+            ))
+        }
+    })
+}
+
+// the timer and result every mode keeps
+#[derive(Default)]
+struct Run {
+    start: Timestamp,
+    end: Option<Timestamp>,
+    outcome: Option<Outcome>,
+}
+
+impl Run {
+    fn elapsed(&self, timestamp: Timestamp) -> Timestamp {
+        self.end.unwrap_or(timestamp).saturating_sub(self.start)
+    }
+
+    // ends the run when the game tops out or `completed` says the goal is met
+    fn update(&mut self, game: &mut Tetris, completed: bool, timestamp: Timestamp) {
+        if self.outcome.is_some() {
+            return;
+        }
+        if game.state() == State::ToppedOut {
+            self.outcome = Some(Outcome::ToppedOut);
+        } else if completed {
+            game.finish();
+            self.outcome = Some(Outcome::Completed);
+        } else {
+            return;
+        }
+        self.end = Some(timestamp);
+    }
+
+    fn hud(&self, text: &mut Vec<String>, completed: &str) {
+        match self.outcome {
+            Some(Outcome::Completed) => text.push(completed.to_string()),
+            Some(Outcome::ToppedOut) => text.push("TOPPED OUT".to_string()),
+            None => {}
+        }
+    }
+}
+
+// endless play. what a perfect clear does is up to the settings; when it
+// stops the game, the hud turns into a results screen
+pub struct Free {
+    run: Run,
+    results: Option<Timestamp>, // time of the perfect clear being shown
+}
+
+impl Default for Free {
+    fn default() -> Self {
+        Free::new()
+    }
+}
+
+impl Free {
+    pub fn new() -> Free {
+        Free {
+            run: Run::default(),
+            results: None,
+        }
+    }
+}
+
+impl GameMode for Free {
+    fn name(&self) -> &'static str {
+        "free"
+    }
+
+    fn setup(&mut self, _game: &mut Tetris, _seed: u64, timestamp: Timestamp) {
+        self.run = Run {
+            start: timestamp,
+            ..Run::default()
+        };
+        self.results = None;
+    }
+
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        self.run.update(game, false, timestamp);
+        if game.state() != State::Paused {
+            self.results = None;
+        } else if self.results.is_none() {
+            self.results = Some(self.run.elapsed(timestamp));
+        }
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.run.outcome
+    }
+
+    fn hud(&self, game: &Tetris, _timestamp: Timestamp) -> Vec<String> {
+        let stats = game.stats();
+        let mut text = Vec::new();
+        if let Some(time) = self.results {
+            text.push("PERFECT CLEAR".to_string());
+            text.push(format_time(time));
+            text.push(format!("PIECES {}", stats.pieces));
+            text.push(format!(
+                "PPS {:.2}",
+                stats.pieces as f64 * 1000.0 / time.max(1) as f64
+            ));
+            text.push(format!("SCORE {}", stats.score));
+            text.push(format!("FINESSE {}", game.finesse_faults()));
+        } else if stats.perfect_clears > 0 {
+            text.push(format!("PCS {}", stats.perfect_clears));
+        }
+        self.run.hud(&mut text, "");
+        text
+    }
+}
+
+// clear a number of lines as fast as possible
+pub struct Sprint {
+    lines: u32,
+    run: Run,
+}
+
+impl Sprint {
+    pub fn new(lines: u32) -> Sprint {
+        Sprint {
+            lines,
+            run: Run::default(),
+        }
+    }
+}
+
+impl GameMode for Sprint {
+    fn name(&self) -> &'static str {
+        "sprint"
+    }
+
+    fn setup(&mut self, game: &mut Tetris, _seed: u64, timestamp: Timestamp) {
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        self.run = Run {
+            start: timestamp,
+            ..Run::default()
+        };
+    }
+
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        let completed = game.stats().lines >= self.lines;
+        self.run.update(game, completed, timestamp);
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.run.outcome
+    }
+
+    fn hud(&self, game: &Tetris, timestamp: Timestamp) -> Vec<String> {
+        let stats = game.stats();
+        let mut text = vec![
+            format!("{}L SPRINT", self.lines),
+            format!("LINES {}/{}", stats.lines.min(self.lines), self.lines),
+            format_time(self.run.elapsed(timestamp)),
+            format!("PIECES {}", stats.pieces),
+        ];
+        self.run.hud(&mut text, "FINISHED");
+        text
+    }
+}
+
+// score as much as possible before time runs out
+pub struct Ultra {
+    time: Timestamp,
+    run: Run,
+}
+
+impl Ultra {
+    pub fn new(time: Timestamp) -> Ultra {
+        Ultra {
+            time,
+            run: Run::default(),
+        }
+    }
+}
+
+impl GameMode for Ultra {
+    fn name(&self) -> &'static str {
+        "ultra"
+    }
+
+    fn setup(&mut self, game: &mut Tetris, _seed: u64, timestamp: Timestamp) {
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        self.run = Run {
+            start: timestamp,
+            ..Run::default()
+        };
+    }
+
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        let completed = self.run.elapsed(timestamp) >= self.time;
+        self.run.update(game, completed, timestamp);
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.run.outcome
+    }
+
+    fn hud(&self, game: &Tetris, timestamp: Timestamp) -> Vec<String> {
+        let stats = game.stats();
+        let mut text = vec![
+            "ULTRA".to_string(),
+            format!("SCORE {}", stats.score),
+            format_time(self.time.saturating_sub(self.run.elapsed(timestamp))),
+            format!("LINES {}", stats.lines),
+        ];
+        if stats.combo > 1 {
+            text.push(format!("COMBO {}", stats.combo - 1));
+        }
+        if stats.back_to_back {
+            text.push("B2B".to_string());
+        }
+        self.run.hud(&mut text, "TIME UP");
+        text
+    }
+}
+
+// dig through garbage as fast as possible
+pub struct Dig {
+    lines: u32,
+    messiness: u32, // percent chance of the hole moving from one line to the next
+    run: Run,
+}
+
+impl Dig {
+    pub fn new(lines: u32, messiness: u32) -> Dig {
+        Dig {
+            lines,
+            messiness,
+            run: Run::default(),
+        }
+    }
+}
+
+impl GameMode for Dig {
+    fn name(&self) -> &'static str {
+        "dig"
+    }
+
+    fn setup(&mut self, game: &mut Tetris, seed: u64, timestamp: Timestamp) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let hole = rng.gen_range(0..BOARD_WIDTH);
+        let lines = garbage_lines(&mut rng, self.lines, hole, self.messiness);
+        // the first line goes at the bottom
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        for (line, garbage) in board.iter_mut().rev().zip(lines) {
+            *line = garbage;
+        }
+        game.set_board(board);
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        self.run = Run {
+            start: timestamp,
+            ..Run::default()
+        };
+    }
+
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        let completed = garbage_left(game) == 0;
+        self.run.update(game, completed, timestamp);
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.run.outcome
+    }
+
+    fn hud(&self, game: &Tetris, timestamp: Timestamp) -> Vec<String> {
+        let mut text = vec![
+            "DIG".to_string(),
+            format!("GARBAGE {}/{}", garbage_left(game), self.lines),
+            format_time(self.run.elapsed(timestamp)),
+            format!("PIECES {}", game.stats().pieces),
+        ];
+        self.run.hud(&mut text, "CLEARED");
+        text
+    }
+}
+
+// lines that still have garbage in them
+fn garbage_left(game: &Tetris) -> usize {
+    game.board()
+        .iter()
+        .filter(|line| line.contains(&Piece::Garbage))
+        .count()
+}
+
+// perfect clear after perfect clear, without stopping in between
+pub struct PcLoop {
+    run: Run,
+}
+
+impl Default for PcLoop {
+    fn default() -> Self {
+        PcLoop::new()
+    }
+}
+
+impl PcLoop {
+    pub fn new() -> PcLoop {
+        PcLoop {
+            run: Run::default(),
+        }
+    }
+}
+
+impl GameMode for PcLoop {
+    fn name(&self) -> &'static str {
+        "pcloop"
+    }
+
+    fn setup(&mut self, game: &mut Tetris, _seed: u64, timestamp: Timestamp) {
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        self.run = Run {
+            start: timestamp,
+            ..Run::default()
+        };
+    }
+
+    fn update(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        self.run.update(game, false, timestamp);
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.run.outcome
+    }
+
+    fn hud(&self, game: &Tetris, timestamp: Timestamp) -> Vec<String> {
+        let stats = game.stats();
+        let mut text = vec![
+            "PC LOOP".to_string(),
+            format!("PCS {}", stats.perfect_clears),
+            format!("PIECES {}", stats.pieces),
+            format_time(self.run.elapsed(timestamp)),
+        ];
+        self.run.hud(&mut text, "");
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GameplaySettings;
+
+    fn start(mode: &mut dyn GameMode, seed: u64) -> Tetris<'static> {
+        let gameplay = GameplaySettings {
+            gravity: 0,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &gameplay, seed);
+        mode.setup(&mut game, seed, 0);
+        game.start(0);
+        game
+    }
+
+    #[test]
+    fn dig_garbage_depends_only_on_the_seed() {
+        let mut dig = Dig::new(10, 50);
+        let a = start(&mut dig, 3);
+        let b = start(&mut dig, 3);
+        assert_eq!(a.board(), b.board());
+        assert_eq!(garbage_left(&a), 10);
+        for line in a.board()[BOARD_HEIGHT - 10..].iter() {
+            let holes = line.iter().filter(|cell| **cell == Piece::None).count();
+            assert_eq!(holes, 1);
+        }
+    }
+
+    #[test]
+    fn sprint_ends_at_its_line_goal() {
+        let mut sprint = Sprint::new(1);
+        let mut game = start(&mut sprint, 0);
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[BOARD_HEIGHT - 1] = [Piece::Garbage; BOARD_WIDTH];
+        board[BOARD_HEIGHT - 1][3..7].fill(Piece::None);
+        game.load_position(board, None, &[Piece::I], 0);
+        sprint.update(&mut game, 500);
+        assert_eq!(sprint.outcome(), None);
+
+        game.harddrop(1200);
+        sprint.update(&mut game, 1200);
+        assert_eq!(sprint.outcome(), Some(Outcome::Completed));
+        // the timer stops when the goal is reached
+        assert!(sprint.hud(&game, 5000).contains(&format_time(1200)));
+    }
+
+    #[test]
+    fn ultra_ends_when_time_is_up() {
+        let mut ultra = Ultra::new(1000);
+        let mut game = start(&mut ultra, 0);
+        ultra.update(&mut game, 999);
+        assert_eq!(ultra.outcome(), None);
+        ultra.update(&mut game, 1000);
+        assert_eq!(ultra.outcome(), Some(Outcome::Completed));
+        assert!(game.state() == State::Finished);
+    }
+
+    #[test]
+    fn free_play_shows_results_at_a_perfect_clear() {
+        let mut free = Free::new();
+        let mut game = start(&mut free, 0);
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[BOARD_HEIGHT - 1] = [Piece::Garbage; BOARD_WIDTH];
+        board[BOARD_HEIGHT - 1][3..7].fill(Piece::None);
+        game.load_position(board, None, &[Piece::I, Piece::I], 0);
+        game.harddrop(2000);
+        free.update(&mut game, 2000);
+        let hud = free.hud(&game, 3000);
+        assert_eq!(hud[..3], ["PERFECT CLEAR", "0:02.0", "PIECES 1"]);
+
+        // carrying on from the results screen counts the clears instead
+        let mut game = start(&mut free, 0);
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        game.load_position(board, None, &[Piece::I, Piece::I], 0);
+        game.harddrop(2000);
+        free.update(&mut game, 2000);
+        assert_eq!(free.hud(&game, 3000), ["PCS 1"]);
+    }
+
+    #[test]
+    fn unknown_modes_are_rejected() {
+        let settings = ModeSettings::default();
+        for name in MODES {
+            assert_eq!(from_name(name, &settings).unwrap().name(), name);
+        }
+        assert!(from_name("marathon", &settings).is_err());
+    }
+}
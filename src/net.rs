@@ -0,0 +1,426 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Timestamp;
+use crate::fumen::{piece_from_char, piece_to_char};
+use crate::settings::PerfectClearAction;
+use crate::tetris::{Piece, State, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7878;
+
+// what the peers tell each other, one json object per line. each peer plays
+// its own board and only ever shows the other's
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Message {
+    // sent by the host right after connecting, so both play the same bags
+    Hello { version: u32, seed: u64 },
+    Snapshot(Snapshot),
+    // garbage lines left after cancelling, for the receiver to take
+    Attack { lines: u32 },
+    ToppedOut,
+}
+
+// everything needed to draw a board
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    board: Vec<String>, // rows from the top down, active piece included
+    hold: Option<char>,
+    queue: String,
+    incoming: u32,
+}
+
+impl Snapshot {
+    pub fn of(game: &Tetris) -> Snapshot {
+        Snapshot {
+            board: game
+                .board()
+                .iter()
+                .map(|line| line.iter().map(|cell| piece_to_char(*cell)).collect())
+                .collect(),
+            hold: game.get_hold().map(piece_to_char),
+            queue: game.get_queue().into_iter().map(piece_to_char).collect(),
+            incoming: game.incoming_garbage(),
+        }
+    }
+
+    // shows the snapshot on `view`, a board that is never played
+    fn show(&self, view: &mut Tetris) -> Result<(), String> {
+        if self.board.len() != BOARD_HEIGHT {
+            return Err(format!("snapshot has {} rows", self.board.len()));
+        }
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        for (line, row) in board.iter_mut().zip(&self.board) {
+            for (cell, c) in line.iter_mut().zip(row.chars()) {
+                *cell = match c {
+                    ' ' => Piece::None,
+                    'X' => Piece::Garbage,
+                    _ => piece_from_char(c).ok_or(format!("unknown cell \"{}\"", c))?,
+                };
+            }
+        }
+        let queue: Vec<Piece> = self.queue.chars().filter_map(piece_from_char).collect();
+        view.edit();
+        view.set_board(board);
+        view.set_hold(self.hold.and_then(piece_from_char));
+        view.set_queue(&queue);
+        // only the meter shows these; the view never inserts them
+        view.clear_garbage();
+        view.receive_garbage(self.incoming, 0);
+        Ok(())
+    }
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,            // received bytes not yet making up a whole line
+    pending: VecDeque<Message>, // read while waiting for an earlier message
+    closed: bool,               // whether the opponent can no longer be reached
+}
+
+impl Connection {
+    // waits for an opponent to connect on `port`
+    pub fn host(port: u16) -> Result<Connection, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("could not listen on port {}: {}", port, e))?;
+        let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+        Connection::new(stream)
+    }
+
+    pub fn join(address: &str) -> Result<Connection, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("could not connect to {}: {}", address, e))?;
+        Connection::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> Result<Connection, String> {
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        Ok(Connection {
+            stream,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+            closed: false,
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), String> {
+        let mut line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.stream.write_all(line.as_bytes()).map_err(|e| {
+            self.closed = true;
+            format!("could not reach the opponent: {}", e)
+        })
+    }
+
+    // every whole message that has arrived, without waiting for more
+    pub fn receive(&mut self) -> Result<Vec<Message>, String> {
+        self.stream
+            .set_nonblocking(true)
+            .map_err(|e| e.to_string())?;
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(self.close("the opponent disconnected".to_string())),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.close(e.to_string())),
+            }
+        }
+        let mut messages: Vec<Message> = self.pending.drain(..).collect();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let message = serde_json::from_slice(&line)
+                .map_err(|e| format!("bad message from the opponent: {}", e))?;
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    // waits for the next message, e.g. during the handshake. anything that
+    // came in with it is kept for the next `receive`
+    fn receive_one(&mut self) -> Result<Message, String> {
+        loop {
+            let messages = self.receive()?;
+            self.pending.extend(messages);
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(message);
+            }
+            self.stream
+                .set_nonblocking(false)
+                .map_err(|e| e.to_string())?;
+            let mut byte = [0; 1];
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Err(self.close("the opponent disconnected".to_string())),
+                Ok(_) => self.buffer.push(byte[0]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.close(e.to_string())),
+            }
+        }
+    }
+
+    // notes that the stream is gone, passing on why
+    fn close(&mut self, error: String) -> String {
+        self.closed = true;
+        error
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // agrees on a seed. the host picks it and the other side takes it
+    pub fn handshake(&mut self, seed: Option<u64>) -> Result<u64, String> {
+        if let Some(seed) = seed {
+            self.send(&Message::Hello {
+                version: PROTOCOL_VERSION,
+                seed,
+            })?;
+            return Ok(seed);
+        }
+        match self.receive_one()? {
+            Message::Hello { version, seed } if version == PROTOCOL_VERSION => Ok(seed),
+            Message::Hello { version, .. } => Err(format!(
+                "the host speaks protocol version {}, but only version {} is supported",
+                version, PROTOCOL_VERSION
+            )),
+            _ => Err("the host did not say hello".to_string()),
+        }
+    }
+}
+
+// a versus game against an opponent on another machine. the first board is
+// played here and the second shows the opponent's as last reported
+pub struct NetVersus {
+    connection: Connection,
+    last_snapshot: Option<Snapshot>,
+    sent: u32,
+    received: u32,
+    won: Option<bool>,
+    disconnected: bool,
+}
+
+impl NetVersus {
+    pub fn new(connection: Connection) -> NetVersus {
+        NetVersus {
+            connection,
+            last_snapshot: None,
+            sent: 0,
+            received: 0,
+            won: None,
+            disconnected: false,
+        }
+    }
+
+    pub fn start(&mut self, boards: &mut [Tetris; 2], timestamp: Timestamp) {
+        boards[0].set_on_perfect_clear(PerfectClearAction::Continue);
+        boards[0].start(timestamp);
+        boards[1].edit();
+    }
+
+    // trades attacks and snapshots with the opponent. an opponent that
+    // disconnects forfeits, so losing the connection ends the game rather
+    // than failing
+    pub fn update(&mut self, boards: &mut [Tetris; 2], timestamp: Timestamp) -> Result<(), String> {
+        if self.disconnected {
+            return Ok(());
+        }
+        match self.trade(boards, timestamp) {
+            Err(_) if self.connection.is_closed() => {
+                self.disconnected = true;
+                if self.won.is_none() {
+                    self.won = Some(true);
+                    boards[0].finish();
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn trade(&mut self, boards: &mut [Tetris; 2], timestamp: Timestamp) -> Result<(), String> {
+        let [game, view] = boards;
+        for message in self.connection.receive()? {
+            match message {
+                Message::Snapshot(snapshot) => snapshot.show(view)?,
+                Message::Attack { lines } => {
+                    self.received += lines;
+                    game.receive_garbage(lines, timestamp);
+                }
+                Message::ToppedOut => {
+                    if self.won.is_none() {
+                        self.won = Some(true);
+                        game.finish();
+                    }
+                }
+                Message::Hello { .. } => return Err("unexpected hello".to_string()),
+            }
+        }
+        let attack = game.take_attack();
+        if attack > 0 && self.won.is_none() {
+            self.sent += attack;
+            self.connection.send(&Message::Attack { lines: attack })?;
+        }
+        if game.state() == State::ToppedOut && self.won.is_none() {
+            self.won = Some(false);
+            self.connection.send(&Message::ToppedOut)?;
+        }
+        let snapshot = Snapshot::of(game);
+        if self.last_snapshot.as_ref() != Some(&snapshot) {
+            self.connection.send(&Message::Snapshot(snapshot.clone()))?;
+            self.last_snapshot = Some(snapshot);
+        }
+        Ok(())
+    }
+
+    pub fn hud(&self) -> Vec<String> {
+        let mut text = vec![
+            "ONLINE VERSUS".to_string(),
+            format!("SENT {}", self.sent),
+            format!("RECEIVED {}", self.received),
+        ];
+        if self.disconnected {
+            text.push("OPPONENT DISCONNECTED".to_string());
+        }
+        match self.won {
+            Some(true) => text.push("YOU WIN".to_string()),
+            Some(false) => text.push("YOU LOSE".to_string()),
+            None => {}
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GameplaySettings;
+    use crate::tetris::fixtures::{board, game};
+    use crate::tetris::{Direction, Rotation};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // a host and a guest connected over loopback
+    fn connect() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let guest = thread::spawn(move || Connection::join(&address).unwrap());
+        let (stream, _) = listener.accept().unwrap();
+        (Connection::new(stream).unwrap(), guest.join().unwrap())
+    }
+
+    // updates `versus` until `done` holds, failing after a while
+    fn update_until(
+        versus: &mut NetVersus,
+        boards: &mut [Tetris; 2],
+        done: impl Fn(&[Tetris; 2]) -> bool,
+    ) {
+        let start = Instant::now();
+        while !done(boards) {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            versus.update(boards, 0).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn peers_share_a_seed_attacks_and_boards() {
+        let (mut host, mut guest) = connect();
+        let seed = host.handshake(Some(9)).unwrap();
+        assert_eq!(guest.handshake(None).unwrap(), seed);
+
+        let settings = GameplaySettings::default();
+        let mut host_boards = [
+            Tetris::new(None, &settings, seed),
+            Tetris::new(None, &settings, 0),
+        ];
+        let mut guest_boards = [
+            Tetris::new(None, &settings, seed),
+            Tetris::new(None, &settings, 0),
+        ];
+        let mut host_versus = NetVersus::new(host);
+        let mut guest_versus = NetVersus::new(guest);
+        host_versus.start(&mut host_boards, 0);
+        guest_versus.start(&mut guest_boards, 0);
+
+        // the host perfect clears with a tetris, sending 14 lines
+        host_boards[0].load_position(board(4, 9..10), None, &[Piece::I], 0);
+        host_boards[0].rot_active(Rotation::Right);
+        while host_boards[0].move_active(Direction::Right) {}
+        host_boards[0].harddrop(0);
+        host_versus.update(&mut host_boards, 0).unwrap();
+        update_until(&mut guest_versus, &mut guest_boards, |boards| {
+            boards[0].incoming_garbage() == 14
+        });
+
+        // each side sees the other's board as it is now
+        let host_board = *host_boards[0].board();
+        update_until(&mut guest_versus, &mut guest_boards, |boards| {
+            *boards[1].board() == host_board
+        });
+        let guest_board = *guest_boards[0].board();
+        update_until(&mut host_versus, &mut host_boards, |boards| {
+            *boards[1].board() == guest_board && boards[1].incoming_garbage() == 14
+        });
+    }
+
+    #[test]
+    fn an_opponent_leaving_forfeits() {
+        let (mut host, mut guest) = connect();
+        let seed = host.handshake(Some(5)).unwrap();
+        guest.handshake(None).unwrap();
+
+        let settings = GameplaySettings::default();
+        let mut boards = [
+            Tetris::new(None, &settings, seed),
+            Tetris::new(None, &settings, 0),
+        ];
+        let mut versus = NetVersus::new(host);
+        versus.start(&mut boards, 0);
+        drop(guest);
+        update_until(&mut versus, &mut boards, |boards| {
+            boards[0].state() == State::Finished
+        });
+        let hud = versus.hud();
+        assert!(hud.contains(&"OPPONENT DISCONNECTED".to_string()));
+        assert!(hud.contains(&"YOU WIN".to_string()));
+        // nothing is sent or received once the opponent is gone
+        versus.update(&mut boards, 0).unwrap();
+    }
+
+    #[test]
+    fn messages_sent_with_the_handshake_are_kept() {
+        let (mut host, mut guest) = connect();
+        host.handshake(Some(3)).unwrap();
+        host.send(&Message::Attack { lines: 2 }).unwrap();
+        host.send(&Message::ToppedOut).unwrap();
+        // let both lines arrive before the guest reads any
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(guest.handshake(None).unwrap(), 3);
+        assert_eq!(
+            guest.receive().unwrap(),
+            [Message::Attack { lines: 2 }, Message::ToppedOut]
+        );
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut game = game(None, 4);
+        game.harddrop(0);
+        game.swap();
+        let json = serde_json::to_string(&Message::Snapshot(Snapshot::of(&game))).unwrap();
+        let mut view = Tetris::new(None, &GameplaySettings::default(), 0);
+        match serde_json::from_str(&json).unwrap() {
+            Message::Snapshot(snapshot) => snapshot.show(&mut view).unwrap(),
+            _ => panic!("not a snapshot"),
+        }
+        assert_eq!(view.board(), game.board());
+        assert_eq!(view.get_hold(), game.get_hold());
+        assert_eq!(view.get_queue(), game.get_queue());
+    }
+}
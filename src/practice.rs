@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Timestamp;
+
+pub const PRACTICE_PATH: &str = "practice.json";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const MIN_EASINESS: f64 = 1.3;
+
+// how an attempt at a puzzle or setup went
+pub struct Attempt {
+    pub success: bool,
+    pub time: Timestamp,
+    pub mistakes: u32,
+    pub finesse_faults: u32,
+}
+
+impl Attempt {
+    // the SM-2 grade of the attempt, from 0 to 5. anything below 3 is a lapse
+    fn quality(&self) -> u32 {
+        if !self.success {
+            return 1;
+        }
+        5 - (self.mistakes > 0) as u32 - (self.finesse_faults > 0) as u32
+    }
+}
+
+// the results and review schedule of one puzzle or setup
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Record {
+    pub attempts: u32,
+    pub successes: u32,
+    pub total_time: Timestamp, // ms spent over every attempt
+    pub finesse_faults: u32,
+    pub easiness: f64,
+    pub repetitions: u32, // successful reviews in a row
+    pub interval: u64,    // days until the next review
+    pub due: u64,         // day the next review is due, counted from the unix epoch
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Record {
+            attempts: 0,
+            successes: 0,
+            total_time: 0,
+            finesse_faults: 0,
+            easiness: 2.5,
+            repetitions: 0,
+            interval: 0,
+            due: 0,
+        }
+    }
+}
+
+impl Record {
+    // updates the schedule the way SM-2 does
+    fn review(&mut self, quality: u32, today: u64) {
+        if quality < 3 {
+            self.repetitions = 0;
+            self.interval = 1;
+        } else {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f64 * self.easiness).round() as u64,
+            };
+            self.repetitions += 1;
+        }
+        let miss = (5 - quality) as f64;
+        self.easiness = (self.easiness + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASINESS);
+        self.due = today + self.interval;
+    }
+}
+
+// every puzzle and setup practiced, stored as json between sessions
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PracticeLog {
+    records: BTreeMap<String, Record>,
+}
+
+impl PracticeLog {
+    // an empty log if nothing has been practiced yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PracticeLog, String> {
+        let path = path.as_ref();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(PracticeLog::default()),
+            Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Record> {
+        self.records.get(key)
+    }
+
+    pub fn record(&mut self, key: &str, attempt: &Attempt, today: u64) -> &Record {
+        let record = self.records.entry(key.to_string()).or_default();
+        record.attempts += 1;
+        record.successes += attempt.success as u32;
+        record.total_time += attempt.time;
+        record.finesse_faults += attempt.finesse_faults;
+        record.review(attempt.quality(), today);
+        record
+    }
+
+    // the day `key` should be practiced next. new items are due right away
+    pub fn due(&self, key: &str) -> u64 {
+        self.get(key).map_or(0, |record| record.due)
+    }
+}
+
+// the current day, counted from the unix epoch
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() / SECONDS_PER_DAY)
+}
+
+pub fn puzzle_key(name: &str) -> String {
+    format!("puzzle/{}", name)
+}
+
+pub fn setup_key(name: &str) -> String {
+    format!("setup/{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(success: bool, finesse_faults: u32) -> Attempt {
+        Attempt {
+            success,
+            time: 1000,
+            mistakes: 0,
+            finesse_faults,
+        }
+    }
+
+    #[test]
+    fn intervals_grow_with_each_success() {
+        let mut log = PracticeLog::default();
+        let intervals: Vec<u64> = (0..4)
+            .map(|_| log.record("setup/PCO", &attempt(true, 0), 100).interval)
+            .collect();
+        assert_eq!(intervals, vec![1, 6, 16, 45]);
+        assert_eq!(log.due("setup/PCO"), 145);
+        assert_eq!(log.due("setup/TKI-3"), 0);
+    }
+
+    #[test]
+    fn failures_start_over_and_lower_easiness() {
+        let mut log = PracticeLog::default();
+        log.record("puzzle/a", &attempt(true, 0), 0);
+        log.record("puzzle/a", &attempt(true, 0), 1);
+        let record = log.record("puzzle/a", &attempt(false, 0), 7);
+        assert_eq!((record.repetitions, record.interval, record.due), (0, 1, 8));
+        assert!(record.easiness < 2.5);
+        assert_eq!((record.attempts, record.successes), (3, 2));
+
+        // finesse faults still count as a pass, but a harder one
+        let faulty = log.record("puzzle/b", &attempt(true, 2), 0).easiness;
+        let clean = log.record("puzzle/c", &attempt(true, 0), 0).easiness;
+        assert!(faulty < clean);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut log = PracticeLog::default();
+        log.record("setup/PCO", &attempt(true, 1), 3);
+        let json = serde_json::to_string(&log).unwrap();
+        let loaded: PracticeLog = serde_json::from_str(&json).unwrap();
+        let record = loaded.get("setup/PCO").unwrap();
+        assert_eq!((record.finesse_faults, record.due), (1, 4));
+    }
+}
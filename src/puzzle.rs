@@ -0,0 +1,396 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde::Deserialize;
+
+use crate::clock::Timestamp;
+use crate::fumen::{self, piece_from_char, piece_to_char};
+use crate::practice::{self, Attempt, PracticeLog};
+use crate::settings::PerfectClearAction;
+use crate::solver::{Board, Solution, Solver, SolverState};
+use crate::tetris::{Piece, State, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+pub const PUZZLES_PATH: &str = "puzzles.toml";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProblemFile {
+    problem: Vec<ProblemData>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProblemData {
+    name: String,
+    #[serde(default)]
+    board: Vec<String>, // rows from the top down to the floor
+    fumen: Option<String>,
+    #[serde(default)]
+    hold: String,
+    queue: String,
+}
+
+pub struct Problem {
+    pub name: String,
+    pub board: Board,
+    pub hold: Option<Piece>,
+    pub queue: Vec<Piece>,
+}
+
+impl Problem {
+    pub fn load_all<P: AsRef<Path>>(path: P) -> Result<Vec<Problem>, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let file: ProblemFile = toml::from_str(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))?;
+        file.problem
+            .into_iter()
+            .map(Problem::from_data)
+            .collect::<Result<Vec<Problem>, String>>()
+            .map_err(|e| format!("invalid problem in {}: {}", path.display(), e))
+    }
+
+    fn from_data(data: ProblemData) -> Result<Problem, String> {
+        let board = match (&data.fumen, data.board.is_empty()) {
+            (Some(code), true) => {
+                fumen::decode(code)
+                    .map_err(|e| format!("{}: {}", data.name, e))?
+                    .first()
+                    .ok_or(format!("{}: the fumen has no pages", data.name))?
+                    .field
+            }
+            (None, false) => {
+                parse_board(&data.board).map_err(|e| format!("{}: {}", data.name, e))?
+            }
+            _ => {
+                return Err(format!(
+                    "{}: needs either a board or a fumen string",
+                    data.name
+                ))
+            }
+        };
+        let hold = parse_pieces(&data.hold).map_err(|e| format!("{}: {}", data.name, e))?;
+        if hold.len() > 1 {
+            return Err(format!("{}: can only hold one piece", data.name));
+        }
+        let queue = parse_pieces(&data.queue).map_err(|e| format!("{}: {}", data.name, e))?;
+        if queue.is_empty() {
+            return Err(format!("{}: the queue is empty", data.name));
+        }
+        Ok(Problem {
+            name: data.name,
+            board,
+            hold: hold.first().copied(),
+            queue,
+        })
+    }
+
+    fn solver_state(&self) -> SolverState {
+        let mut state = SolverState::new(0);
+        state.board = self.board;
+        state.queue = self.queue.iter().copied().collect();
+        state.piece_active = Piece::None;
+        state.swap_piece = self.hold.unwrap_or(Piece::None);
+        state
+    }
+}
+
+fn parse_board(rows: &[String]) -> Result<Board, String> {
+    if rows.len() > BOARD_HEIGHT {
+        return Err(format!("the board has more than {} rows", BOARD_HEIGHT));
+    }
+    let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+    for (line, row) in board[BOARD_HEIGHT - rows.len()..].iter_mut().zip(rows) {
+        if row.chars().count() != BOARD_WIDTH {
+            return Err(format!(
+                "board row \"{}\" isn't {} cells wide",
+                row, BOARD_WIDTH
+            ));
+        }
+        for (cell, c) in line.iter_mut().zip(row.chars()) {
+            *cell = match c {
+                '_' => Piece::None,
+                'X' | 'x' => Piece::Garbage,
+                _ => piece_from_char(c).ok_or_else(|| format!("unknown cell '{}'", c))?,
+            };
+        }
+    }
+    Ok(board)
+}
+
+fn parse_pieces(pieces: &str) -> Result<Vec<Piece>, String> {
+    pieces
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| piece_from_char(c).ok_or_else(|| format!("unknown piece '{}'", c)))
+        .collect()
+}
+
+pub struct PuzzleResult {
+    pub solved: bool,
+    pub missed: Vec<Solution>, // solutions other than the one the player found
+    pub review: u64,           // days until the problem is due again
+}
+
+// works through a list of problems, the ones most due for practice first.
+// each problem is solved in the background as soon as it's loaded, so the
+// results are usually ready when an attempt ends
+pub struct Puzzles {
+    problems: Vec<Problem>,
+    index: usize,
+    solving: Option<Receiver<Result<Vec<Solution>, String>>>,
+    solutions: Option<Result<Vec<Solution>, String>>,
+    result: Option<PuzzleResult>,
+    shown: usize, // index of the missed solution being shown
+    started: Timestamp,
+}
+
+impl Puzzles {
+    pub fn new(mut problems: Vec<Problem>, log: &PracticeLog) -> Result<Puzzles, String> {
+        if problems.is_empty() {
+            return Err("there are no problems to play".to_string());
+        }
+        problems.sort_by_key(|problem| log.due(&practice::puzzle_key(&problem.name)));
+        Ok(Puzzles {
+            problems,
+            index: 0,
+            solving: None,
+            solutions: None,
+            result: None,
+            shown: 0,
+            started: 0,
+        })
+    }
+
+    pub fn problem(&self) -> &Problem {
+        &self.problems[self.index]
+    }
+
+    // sets up the current problem in `game` and starts solving it if needed
+    pub fn start(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        let problem = &self.problems[self.index];
+        // the attempt ends at the perfect clear, whatever the settings say
+        game.set_on_perfect_clear(PerfectClearAction::Results);
+        game.load_fixed_position(problem.board, problem.hold, &problem.queue, timestamp);
+        self.result = None;
+        self.shown = 0;
+        self.started = timestamp;
+        if self.solving.is_none() && self.solutions.is_none() {
+            let state = problem.solver_state();
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                // the puzzle may have moved on, in which case nobody is listening
+                let _ = sender.send(Solver::new().solve(&state));
+            });
+            self.solving = Some(receiver);
+        }
+    }
+
+    pub fn next(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        self.index = (self.index + 1) % self.problems.len();
+        self.change_problem(game, timestamp);
+    }
+
+    pub fn previous(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        self.index = (self.index + self.problems.len() - 1) % self.problems.len();
+        self.change_problem(game, timestamp);
+    }
+
+    fn change_problem(&mut self, game: &mut Tetris, timestamp: Timestamp) {
+        self.solving = None;
+        self.solutions = None;
+        self.start(game, timestamp);
+    }
+
+    // ends the attempt once the game perfect clears or runs out of pieces,
+    // as soon as the solutions are known, and records it in `log`
+    pub fn update(&mut self, game: &Tetris, log: &mut PracticeLog, timestamp: Timestamp) {
+        if let Some(receiver) = &self.solving {
+            match receiver.try_recv() {
+                Ok(solutions) => self.solutions = Some(solutions),
+                Err(TryRecvError::Disconnected) => {
+                    self.solutions = Some(Err("the solver stopped unexpectedly".to_string()))
+                }
+                Err(TryRecvError::Empty) => return,
+            }
+            self.solving = None;
+        }
+        if self.result.is_some() {
+            return;
+        }
+        // anything but a perfect clear is a miss: running out of pieces
+        // finishes the game, and so does topping out
+        if game.state() == State::Playing {
+            return;
+        }
+        let perfect_clear = game.state() == State::Paused;
+        let played = Solution::from_placements(&self.problem().board, game.placements());
+        let solutions = match &self.solutions {
+            Some(Ok(solutions)) => solutions.clone(),
+            _ => Vec::new(),
+        };
+        let attempt = Attempt {
+            success: perfect_clear,
+            time: timestamp - self.started,
+            mistakes: 0,
+            finesse_faults: game.finesse_faults(),
+        };
+        let record = log.record(
+            &practice::puzzle_key(&self.problem().name),
+            &attempt,
+            practice::today(),
+        );
+        let result = PuzzleResult {
+            solved: perfect_clear,
+            missed: solutions
+                .into_iter()
+                .filter(|solution| Some(solution) != played.as_ref())
+                .collect(),
+            review: record.interval,
+        };
+        self.result = Some(result);
+    }
+
+    pub fn show_next_solution(&mut self) {
+        if let Some(result) = &self.result {
+            self.shown = (self.shown + 1).min(result.missed.len().saturating_sub(1));
+        }
+    }
+
+    pub fn show_previous_solution(&mut self) {
+        self.shown = self.shown.saturating_sub(1);
+    }
+
+    // the board of the missed solution being shown, once an attempt is over
+    pub fn shown_board(&self) -> Option<Board> {
+        let result = self.result.as_ref()?;
+        let solution = result.missed.get(self.shown)?;
+        Some(solution.draw(&self.problem().board))
+    }
+
+    pub fn hud(&self, game: &Tetris) -> Vec<String> {
+        let problem = self.problem();
+        let mut text = vec![
+            format!("PUZZLE {}/{}", self.index + 1, self.problems.len()),
+            problem.name.to_uppercase(),
+            format!(
+                "HOLD {} QUEUE {}",
+                problem.hold.map(piece_to_char).unwrap_or('-'),
+                problem
+                    .queue
+                    .iter()
+                    .map(|p| piece_to_char(*p))
+                    .collect::<String>()
+            ),
+            format!("FINESSE {}", game.finesse_faults()),
+        ];
+        match (&self.result, &self.solutions) {
+            (None, _) => {}
+            (Some(_), Some(Err(e))) => text.push(format!("NO SOLUTIONS: {}", e)),
+            (Some(result), _) => {
+                text.push(if result.solved { "SOLVED" } else { "FAILED" }.to_string());
+                text.push(format!("AGAIN IN {}D", result.review));
+                if result.missed.is_empty() {
+                    text.push("NO OTHER SOLUTIONS".to_string());
+                } else {
+                    text.push(format!("MISSED {}", result.missed.len()));
+                    text.push(format!(
+                        "SHOWING {}/{}",
+                        self.shown + 1,
+                        result.missed.len()
+                    ));
+                }
+            }
+        }
+        if self.solving.is_some() {
+            text.push("SOLVING...".to_string());
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GameplaySettings;
+
+    #[test]
+    fn shipped_problems_load() {
+        let problems = Problem::load_all(PUZZLES_PATH).unwrap();
+        assert!(!problems.is_empty());
+        for problem in problems.iter() {
+            let filled = problem
+                .board
+                .iter()
+                .flatten()
+                .filter(|c| **c != Piece::None);
+            assert!(filled.count() > 0, "{} has an empty board", problem.name);
+        }
+    }
+
+    #[test]
+    fn shipped_problems_have_a_perfect_clear() {
+        for problem in Problem::load_all(PUZZLES_PATH).unwrap() {
+            let solutions = Solver::new().solve(&problem.solver_state()).unwrap();
+            assert!(!solutions.is_empty(), "{} can't be solved", problem.name);
+        }
+    }
+
+    #[test]
+    fn a_fumen_without_pages_is_an_error() {
+        let problem = Problem::from_data(ProblemData {
+            name: "Empty".to_string(),
+            board: Vec::new(),
+            fumen: Some("v115@".to_string()),
+            hold: String::new(),
+            queue: "T".to_string(),
+        });
+        assert!(problem.is_err());
+    }
+
+    #[test]
+    fn an_attempt_only_gets_the_given_pieces() {
+        let mut problem = Problem::from_data(ProblemData {
+            name: "Two pieces".to_string(),
+            board: vec!["XXX____XXX".to_string(); 4],
+            fumen: None,
+            hold: "T".to_string(),
+            queue: "O".to_string(),
+        })
+        .unwrap();
+        problem.board[BOARD_HEIGHT - 1][3] = Piece::Garbage;
+        let mut puzzles = Puzzles::new(vec![problem], &PracticeLog::default()).unwrap();
+        let mut log = PracticeLog::default();
+        let mut game = Tetris::new(None, &GameplaySettings::default(), 0);
+        game.start(0);
+        puzzles.start(&mut game, 0);
+        puzzles.solving = None;
+        puzzles.solutions = Some(Ok(Vec::new()));
+
+        assert!(game.queue().is_empty());
+        game.harddrop(100);
+        // the held piece comes out once the queue is used up
+        assert_eq!(game.active_piece().0, Piece::T);
+        assert_eq!(game.get_hold(), None);
+        puzzles.update(&game, &mut log, 100);
+        assert!(puzzles.result.is_none());
+
+        game.harddrop(200);
+        assert!(game.state() == State::Finished);
+        puzzles.update(&game, &mut log, 200);
+        assert!(!puzzles.result.as_ref().unwrap().solved);
+    }
+
+    #[test]
+    fn parses_board_rows() {
+        let board = parse_board(&["TTT_______".to_string(), "XXXXXXXX__".to_string()]).unwrap();
+        assert_eq!(board[BOARD_HEIGHT - 2][0], Piece::T);
+        assert_eq!(board[BOARD_HEIGHT - 2][3], Piece::None);
+        assert_eq!(board[BOARD_HEIGHT - 1][7], Piece::Garbage);
+        assert!(parse_board(&["XX".to_string()]).is_err());
+        assert!(parse_pieces("TQ").is_err());
+    }
+}
@@ -1,20 +1,62 @@
 use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::ptr;
 
+use egui::epaint::textures::TexturesDelta;
+use egui::epaint::{ClippedPrimitive, ImageData, ImageDelta, Primitive, TextureId};
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
 use sdl2::video::{Window, WindowContext};
 use sdl2::Sdl;
 
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::settings::VisualSettings;
+use crate::solver::PlacedPiece;
 use crate::tetris::{Piece, Rotation, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
-use crate::{get_deltas, TILE_SIZE};
+use crate::{get_color, get_deltas};
+
+pub const TILE_SIZE: u32 = 32;
+
+pub const T_COLOR: Color = Color::RGBA(162, 50, 240, 255);
+pub const I_COLOR: Color = Color::RGBA(0, 183, 235, 255);
+pub const L_COLOR: Color = Color::RGBA(255, 117, 24, 255);
+pub const J_COLOR: Color = Color::RGBA(0, 0, 205, 255);
+pub const Z_COLOR: Color = Color::RGBA(220, 20, 60, 255);
+pub const S_COLOR: Color = Color::RGBA(50, 205, 50, 255);
+pub const O_COLOR: Color = Color::RGBA(255, 223, 0, 255);
+pub const GARBAGE_COLOR: Color = Color::RGBA(128, 128, 128, 255);
 
 const BOARD_BACKGROUND: &str = "board_bg";
+const TEXT_SCALE: u32 = 2;
+const TEXT_COLOR: Color = Color::RGB(255, 255, 255);
+const GARBAGE_METER_COLOR: Color = Color::RGB(220, 20, 20);
+
+// a vertex as SDL_RenderGeometry takes it: position, color, texture coordinate
+#[repr(C)]
+struct Vertex {
+    position: [f32; 2],
+    color: [u8; 4],
+    tex_coord: [f32; 2],
+}
+
+// sdl2 0.35 predates SDL_RenderGeometry, which egui's meshes need, so it's
+// declared here. it's been in SDL since 2.0.18
+extern "C" {
+    fn SDL_RenderGeometry(
+        renderer: *mut sdl2::sys::SDL_Renderer,
+        texture: *mut sdl2::sys::SDL_Texture,
+        vertices: *const Vertex,
+        num_vertices: c_int,
+        indices: *const c_int,
+        num_indices: c_int,
+    ) -> c_int;
+}
 
 const DEFAULT_BOARD_TEXTURE_PARAMS: (PixelFormatEnum, u32, u32) = (
     PixelFormatEnum::RGBA32,
-    4 + crate::tetris::BOARD_WIDTH as u32 * crate::TILE_SIZE,
-    4 + crate::tetris::BOARD_HEIGHT as u32 * crate::TILE_SIZE,
+    4 + BOARD_WIDTH as u32 * TILE_SIZE,
+    4 + BOARD_HEIGHT as u32 * TILE_SIZE,
 );
 
 pub struct Renderer<'a> {
@@ -24,6 +66,11 @@ pub struct Renderer<'a> {
     textures: HashMap<String, Texture<'a>>,
     // background_texture: Option<Texture<'a>>,
     canvas: Canvas<Window>,
+    hint: Option<PlacedPiece>, // cells drawn see-through over the main board
+    panel: Vec<String>,        // lines drawn beside the main board's queue
+    visual: VisualSettings,
+    gui: Vec<ClippedPrimitive>, // egui's output, drawn over everything else
+    gui_textures: HashMap<TextureId, Texture<'a>>,
 }
 
 impl<'a> Renderer<'a> {
@@ -52,6 +99,11 @@ impl<'a> Renderer<'a> {
                 textures: HashMap::new(),
                 texture_creator: None,
                 // background_texture: None,
+                hint: None,
+                panel: Vec::new(),
+                visual: VisualSettings::default(),
+                gui: Vec::new(),
+                gui_textures: HashMap::new(),
             },
         ))
     }
@@ -126,43 +178,43 @@ impl<'a> Renderer<'a> {
                             get_deltas!(Piece::T, Rotation::Spawn),
                             3 * TILE_SIZE / 2,
                             3 * TILE_SIZE / 2,
-                            crate::T_COLOR,
+                            T_COLOR,
                         )),
                         "i" => Some((
                             get_deltas!(Piece::I, Rotation::Spawn),
                             TILE_SIZE,
                             TILE_SIZE,
-                            crate::I_COLOR,
+                            I_COLOR,
                         )),
                         "j" => Some((
                             get_deltas!(Piece::J, Rotation::Spawn),
                             3 * TILE_SIZE / 2,
                             3 * TILE_SIZE / 2,
-                            crate::J_COLOR,
+                            J_COLOR,
                         )),
                         "l" => Some((
                             get_deltas!(Piece::L, Rotation::Spawn),
                             3 * TILE_SIZE / 2,
                             3 * TILE_SIZE / 2,
-                            crate::L_COLOR,
+                            L_COLOR,
                         )),
                         "s" => Some((
                             get_deltas!(Piece::S, Rotation::Spawn),
                             3 * TILE_SIZE / 2,
                             3 * TILE_SIZE / 2,
-                            crate::S_COLOR,
+                            S_COLOR,
                         )),
                         "z" => Some((
                             get_deltas!(Piece::Z, Rotation::Spawn),
                             3 * TILE_SIZE / 2,
                             3 * TILE_SIZE / 2,
-                            crate::Z_COLOR,
+                            Z_COLOR,
                         )),
                         "o" => Some((
                             get_deltas!(Piece::O, Rotation::Spawn),
                             TILE_SIZE,
                             TILE_SIZE / 2,
-                            crate::O_COLOR,
+                            O_COLOR,
                         )),
                         _ => None,
                     } {
@@ -211,43 +263,122 @@ impl<'a> Renderer<'a> {
         &self.sdl_context
     }
 
-    pub fn render(&mut self, game_boards: &[Tetris]) -> Result<(), String> {
+    // draws the boards side by side. the hint, the text and the panel go with
+    // the first board, the one being played or edited
+    pub fn render(&mut self, game_boards: &[Tetris], text: &[String]) -> Result<(), String> {
         self.canvas.set_draw_color(Color::RGB(0, 0, 0));
         self.canvas.clear();
-        let mut main_board_texture: Texture = Renderer::make_texture(
-            self.texture_creator.expect("tex creator not initalized"),
-            None,
-        )?;
-        let iter_vec = vec![(&mut main_board_texture, &game_boards[0])];
+        let mut board_textures = Vec::new();
+        for _ in game_boards {
+            board_textures.push(Renderer::make_texture(
+                self.texture_creator.expect("tex creator not initalized"),
+                None,
+            )?);
+        }
+        let iter_vec: Vec<_> = board_textures.iter_mut().zip(game_boards.iter()).collect();
+        let ghost = self.visual.ghost;
         self.canvas
             .with_multiple_texture_canvas(iter_vec.iter(), |c, game| {
-                game.draw_board_texture(c, 2, 2, true)
-                    .expect("couldn't draw board");
+                draw_board(c, game, 2, 2, ghost).expect("couldn't draw board");
             })
             .map_err(|e| e.to_string())?;
 
-        // render main board
-        let (ww, wh) = self.canvas.window().size();
-        let w = BOARD_WIDTH as u32 * TILE_SIZE + 4;
-        let h = BOARD_HEIGHT as u32 * TILE_SIZE + 4;
-        let main_board_dst = Rect::new(ww as i32 / 2 - w as i32 / 2, 10, w, h);
+        for (index, (game, board_texture)) in
+            game_boards.iter().zip(board_textures.iter()).enumerate()
+        {
+            let board_dst = self.board_dst(index, game_boards.len());
+            self.render_board(game, board_texture, board_dst, index == 0)?;
+        }
+
+        // render text below the first board's hold piece
+        let main_board_dst = self.board_dst(0, game_boards.len());
+        let mut text_y = main_board_dst.y() + 4 * TILE_SIZE as i32;
+        for line in text {
+            self.draw_text(
+                line,
+                main_board_dst.x() - (5 * TILE_SIZE as i32),
+                text_y,
+                TEXT_SCALE,
+                TEXT_COLOR,
+            )?;
+            text_y += ((GLYPH_HEIGHT + 3) * TEXT_SCALE) as i32;
+        }
+
+        // and the panel past the first board's queue
+        let mut panel_y = main_board_dst.y();
+        let panel = if self.visual.session_panel {
+            self.panel.clone()
+        } else {
+            Vec::new()
+        };
+        for line in panel {
+            self.draw_text(
+                &line,
+                main_board_dst.right() + (6 * TILE_SIZE as i32),
+                panel_y,
+                TEXT_SCALE,
+                TEXT_COLOR,
+            )?;
+            panel_y += ((GLYPH_HEIGHT + 3) * TEXT_SCALE) as i32;
+        }
+        self.draw_gui()?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn render_board(
+        &mut self,
+        game: &Tetris,
+        board_texture: &Texture,
+        board_dst: Rect,
+        main: bool,
+    ) -> Result<(), String> {
+        // render board
         self.canvas.copy(
             self.textures
                 .get(BOARD_BACKGROUND)
                 .as_ref()
                 .expect("bg tex not initialized"),
             None,
-            main_board_dst,
+            board_dst,
         )?;
-        self.canvas
-            .copy(&main_board_texture, None, main_board_dst)?;
+        self.canvas.copy(board_texture, None, board_dst)?;
+        if let Some((piece, cells)) = self.hint.filter(|_| main) {
+            if let Some(color) = get_color!(piece) {
+                let (r, g, b, _) = color.rgba();
+                self.canvas.set_blend_mode(BlendMode::Blend);
+                self.canvas.set_draw_color(Color::RGBA(r, g, b, 96));
+                for (line, col) in cells {
+                    self.canvas.fill_rect(Rect::new(
+                        board_dst.x() + 2 + (col as u32 * TILE_SIZE) as i32,
+                        board_dst.y() + 2 + (line as u32 * TILE_SIZE) as i32,
+                        TILE_SIZE,
+                        TILE_SIZE,
+                    ))?;
+                }
+                self.canvas.set_blend_mode(BlendMode::None);
+            }
+        }
+
+        // incoming damage meter, one cell high per garbage line waiting
+        let incoming = game.incoming_garbage().min(BOARD_HEIGHT as u32);
+        if incoming > 0 {
+            let height = incoming * TILE_SIZE;
+            self.canvas.set_draw_color(GARBAGE_METER_COLOR);
+            self.canvas.fill_rect(Rect::new(
+                board_dst.x() - (TILE_SIZE / 2) as i32,
+                board_dst.bottom() - 2 - height as i32,
+                TILE_SIZE / 4,
+                height,
+            ))?;
+        }
 
         // render hold piece
         // TODO: render box around hold piece
-        if let Some(piece) = game_boards[0].get_hold() {
+        if let Some(piece) = game.get_hold() {
             let hold_dst = Rect::new(
-                main_board_dst.x() - (5 * TILE_SIZE as i32),
-                main_board_dst.y(),
+                board_dst.x() - (5 * TILE_SIZE as i32),
+                board_dst.y(),
                 4 * TILE_SIZE,
                 3 * TILE_SIZE,
             );
@@ -259,8 +390,8 @@ impl<'a> Renderer<'a> {
                 Piece::S => self.textures.get("s").expect("no piece texture"),
                 Piece::Z => self.textures.get("z").expect("no piece texture"),
                 Piece::O => self.textures.get("o").expect("no piece texture"),
-                Piece::None => {
-                    panic!("get_hold() returned Piece::None");
+                Piece::Garbage | Piece::None => {
+                    panic!("get_hold() returned {:?}", piece);
                 }
             };
             self.canvas.copy(texture, None, hold_dst)?;
@@ -268,12 +399,12 @@ impl<'a> Renderer<'a> {
 
         // render queue
         let mut piece_dst = Rect::new(
-            main_board_dst.right() + TILE_SIZE as i32,
-            main_board_dst.y(),
+            board_dst.right() + TILE_SIZE as i32,
+            board_dst.y(),
             4 * TILE_SIZE,
             3 * TILE_SIZE,
         );
-        for piece in game_boards[0].get_queue() {
+        for piece in game.get_queue().iter().take(self.visual.previews as usize) {
             let texture = match piece {
                 Piece::T => self.textures.get("t").expect("no piece texture"),
                 Piece::I => self.textures.get("i").expect("no piece texture"),
@@ -282,14 +413,191 @@ impl<'a> Renderer<'a> {
                 Piece::S => self.textures.get("s").expect("no piece texture"),
                 Piece::Z => self.textures.get("z").expect("no piece texture"),
                 Piece::O => self.textures.get("o").expect("no piece texture"),
-                Piece::None => {
-                    panic!("get_hold() returned Piece::None");
+                Piece::Garbage | Piece::None => {
+                    panic!("get_queue() returned {:?}", piece);
                 }
             };
             self.canvas.copy(texture, None, piece_dst)?;
             piece_dst.offset(0, 3 * TILE_SIZE as i32);
         }
-        self.canvas.present();
+
+        Ok(())
+    }
+
+    // shows where a piece should go on the main board until it's cleared
+    pub fn set_hint(&mut self, hint: Option<PlacedPiece>) {
+        self.hint = hint;
+    }
+
+    // e.g. the session's stats
+    pub fn set_panel(&mut self, panel: Vec<String>) {
+        self.panel = panel;
+    }
+
+    pub fn set_visual(&mut self, visual: &VisualSettings) {
+        self.visual = visual.clone();
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.canvas.window().size()
+    }
+
+    // takes a frame of egui output, to be drawn with the next render
+    pub fn set_gui(
+        &mut self,
+        textures: TexturesDelta,
+        primitives: Vec<ClippedPrimitive>,
+    ) -> Result<(), String> {
+        for (id, delta) in textures.set {
+            self.update_gui_texture(id, delta)?;
+        }
+        // the textures to free were only used by earlier frames
+        for id in textures.free {
+            self.gui_textures.remove(&id);
+        }
+        self.gui = primitives;
+        Ok(())
+    }
+
+    fn update_gui_texture(&mut self, id: TextureId, delta: ImageDelta) -> Result<(), String> {
+        // egui's colors have their alpha premultiplied, sdl's blending doesn't
+        let ([width, height], pixels): ([usize; 2], Vec<u8>) = match &delta.image {
+            ImageData::Color(image) => (
+                image.size,
+                image
+                    .pixels
+                    .iter()
+                    .flat_map(|color| color.to_srgba_unmultiplied())
+                    .collect(),
+            ),
+            ImageData::Font(image) => (
+                image.size,
+                image
+                    .srgba_pixels(1.0)
+                    .flat_map(|color| color.to_srgba_unmultiplied())
+                    .collect(),
+            ),
+        };
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        let rect = Rect::new(x as i32, y as i32, width as u32, height as u32);
+        if delta.pos.is_none() {
+            let mut texture = self
+                .texture_creator
+                .expect("tex creator not initalized")
+                .create_texture_static(PixelFormatEnum::RGBA32, width as u32, height as u32)
+                .map_err(|e| e.to_string())?;
+            texture.set_blend_mode(BlendMode::Blend);
+            self.gui_textures.insert(id, texture);
+        }
+        let texture = self
+            .gui_textures
+            .get_mut(&id)
+            .ok_or(format!("no gui texture {:?}", id))?;
+        texture
+            .update(rect, &pixels, width * 4)
+            .map_err(|e| e.to_string())
+    }
+
+    fn draw_gui(&mut self) -> Result<(), String> {
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &self.gui
+        {
+            let mesh = match primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue, // nothing here paints on its own
+            };
+            self.canvas.set_clip_rect(Rect::new(
+                clip_rect.min.x as i32,
+                clip_rect.min.y as i32,
+                clip_rect.width().max(0.0) as u32,
+                clip_rect.height().max(0.0) as u32,
+            ));
+            let vertices: Vec<Vertex> = mesh
+                .vertices
+                .iter()
+                .map(|vertex| Vertex {
+                    position: [vertex.pos.x, vertex.pos.y],
+                    color: vertex.color.to_srgba_unmultiplied(),
+                    tex_coord: [vertex.uv.x, vertex.uv.y],
+                })
+                .collect();
+            let indices: Vec<c_int> = mesh.indices.iter().map(|i| *i as c_int).collect();
+            let texture = self
+                .gui_textures
+                .get(&mesh.texture_id)
+                .map_or(ptr::null_mut(), |texture| texture.raw());
+            // the vertices and indices outlive the call, and every index is
+            // within the mesh's vertices
+            let result = unsafe {
+                SDL_RenderGeometry(
+                    self.canvas.raw(),
+                    texture,
+                    vertices.as_ptr(),
+                    vertices.len() as c_int,
+                    indices.as_ptr(),
+                    indices.len() as c_int,
+                )
+            };
+            if result != 0 {
+                self.canvas.set_clip_rect(None);
+                return Err(sdl2::get_error());
+            }
+        }
+        self.canvas.set_clip_rect(None);
+        Ok(())
+    }
+
+    fn main_board_dst(&self) -> Rect {
+        self.board_dst(0, 1)
+    }
+
+    // where board `index` of `count` goes, each centered in its share of the window
+    fn board_dst(&self, index: usize, count: usize) -> Rect {
+        let (ww, _) = self.canvas.window().size();
+        let w = BOARD_WIDTH as u32 * TILE_SIZE + 4;
+        let h = BOARD_HEIGHT as u32 * TILE_SIZE + 4;
+        let center = ww as usize * (2 * index + 1) / (2 * count);
+        Rect::new(center as i32 - w as i32 / 2, 10, w, h)
+    }
+
+    // the (line, col) of the main board cell under a point in the window
+    pub fn board_cell_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let dst = self.main_board_dst();
+        let col = (x - dst.x() - 2).div_euclid(TILE_SIZE as i32);
+        let line = (y - dst.y() - 2).div_euclid(TILE_SIZE as i32);
+        if (0..BOARD_WIDTH as i32).contains(&col) && (0..BOARD_HEIGHT as i32).contains(&line) {
+            Some((line as usize, col as usize))
+        } else {
+            None
+        }
+    }
+
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: i32,
+        y: i32,
+        scale: u32,
+        color: Color,
+    ) -> Result<(), String> {
+        self.canvas.set_draw_color(color);
+        for (i, c) in text.chars().enumerate() {
+            let glyph_x = x + (i as u32 * (GLYPH_WIDTH + 1) * scale) as i32;
+            for (row, bits) in font::glyph(c).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        self.canvas.fill_rect(Rect::new(
+                            glyph_x + (col * scale) as i32,
+                            y + (row as u32 * scale) as i32,
+                            scale,
+                            scale,
+                        ))?;
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
@@ -305,3 +613,44 @@ impl<'a> Renderer<'a> {
         Ok(tex)
     }
 }
+
+// draws `game`'s board onto a board texture, along with where the active
+// piece would land if `project` is set
+fn draw_board(
+    texture_canvas: &mut Canvas<Window>,
+    game: &Tetris,
+    x_offset: i32,
+    y_offset: i32,
+    project: bool,
+) -> Result<(), String> {
+    texture_canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+    texture_canvas.clear();
+    if project {
+        if let (Some(ghost), Some(color)) = (game.ghost(), get_color!(game.active_piece().0)) {
+            let color = color.rgba();
+            texture_canvas.set_draw_color(Color::RGBA(color.0, color.1, color.2, color.3 / 2));
+            for (line, col) in ghost {
+                texture_canvas.fill_rect(Rect::new(
+                    x_offset + (col as u32 * TILE_SIZE) as i32,
+                    y_offset + (line as u32 * TILE_SIZE) as i32,
+                    TILE_SIZE,
+                    TILE_SIZE,
+                ))?;
+            }
+        }
+    }
+    for (line, row) in game.board().iter().enumerate() {
+        for (col, piece) in row.iter().enumerate() {
+            if let Some(color) = get_color!(piece) {
+                texture_canvas.set_draw_color(color);
+                texture_canvas.fill_rect(Rect::new(
+                    x_offset + (col as u32 * TILE_SIZE) as i32,
+                    y_offset + (line as u32 * TILE_SIZE) as i32,
+                    TILE_SIZE,
+                    TILE_SIZE,
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
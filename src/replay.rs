@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Timestamp;
+use crate::input_manager::{Action, InputManager};
+use crate::modes::{self, GameMode};
+use crate::settings::Settings;
+use crate::tetris::Tetris;
+
+pub const REPLAY_VERSION: u32 = 1;
+pub const REPLAY_DIR: &str = "replays";
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayEvent {
+    pub time: Timestamp,
+    pub action: Action,
+    pub pressed: bool,
+}
+
+// everything needed to play a game back exactly: the games are deterministic
+// given the seed, the settings and the timestamped inputs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub settings: Settings,
+    #[serde(default = "free_mode")]
+    pub mode: String,
+    pub length: Timestamp,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: &Settings, mode: &str) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            settings: settings.clone(),
+            mode: mode.to_string(),
+            length: 0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, time: Timestamp, action: Action, pressed: bool) {
+        self.events.push(ReplayEvent {
+            time,
+            action,
+            pressed,
+        });
+        self.length = self.length.max(time);
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let replay: Replay = serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "{} has replay version {}, but only version {} is supported",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        replay.settings.validate()?;
+        modes::from_name(&replay.mode, &replay.settings.modes)?;
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let contents = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+}
+
+// replays from before modes existed were all free play
+fn free_mode() -> String {
+    "free".to_string()
+}
+
+// feeds a replay back through an InputManager and a Tetris game
+pub struct Playback {
+    replay: Replay,
+    game: Tetris<'static>,
+    input_manager: InputManager,
+    mode: Box<dyn GameMode>,
+    time: Timestamp,   // how far the game has been simulated
+    next_event: usize, // index of the first event that hasn't been played yet
+    position: f64,     // playback position in ms; advances by real time * speed
+    speed: f64,
+    paused: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let (game, input_manager, mode) = Playback::fresh_game(&replay);
+        Playback {
+            replay,
+            game,
+            input_manager,
+            mode,
+            time: 0,
+            next_event: 0,
+            position: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    fn fresh_game(replay: &Replay) -> (Tetris<'static>, InputManager, Box<dyn GameMode>) {
+        let mut game = Tetris::new(None, &replay.settings.gameplay, replay.seed);
+        let mut mode = modes::from_name(&replay.mode, &replay.settings.modes)
+            .expect("replay modes are checked when loading");
+        mode.setup(&mut game, replay.seed, 0);
+        game.start(0);
+        (game, InputManager::new(&replay.settings), mode)
+    }
+
+    pub fn game(&self) -> &Tetris<'static> {
+        &self.game
+    }
+
+    pub fn mode(&self) -> &dyn GameMode {
+        self.mode.as_ref()
+    }
+
+    pub fn time(&self) -> Timestamp {
+        self.time
+    }
+
+    pub fn length(&self) -> Timestamp {
+        self.replay.length
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(0.125, 8.0);
+    }
+
+    // advances playback by `elapsed` ms of real time
+    pub fn update(&mut self, elapsed: Timestamp) {
+        if self.paused {
+            return;
+        }
+        self.position = (self.position + elapsed as f64 * self.speed).min(self.length() as f64);
+        self.play_to(self.position as Timestamp);
+    }
+
+    pub fn seek(&mut self, target: Timestamp) {
+        let target = target.min(self.length());
+        if target < self.time {
+            // the game can't be rewound, so replay it from the start
+            let (game, input_manager, mode) = Playback::fresh_game(&self.replay);
+            self.game = game;
+            self.input_manager = input_manager;
+            self.mode = mode;
+            self.time = 0;
+            self.next_event = 0;
+        }
+        self.position = target as f64;
+        self.play_to(target);
+    }
+
+    fn play_to(&mut self, target: Timestamp) {
+        while let Some(event) = self.replay.events.get(self.next_event) {
+            if event.time > target {
+                break;
+            }
+            self.input_manager
+                .advance(&mut self.game, self.time, event.time);
+            self.time = self.time.max(event.time);
+            self.mode.update(&mut self.game, event.time);
+            self.input_manager.process_action(
+                event.action,
+                event.pressed,
+                &mut self.game,
+                event.time,
+            );
+            self.mode.update(&mut self.game, event.time);
+            self.next_event += 1;
+        }
+        if target > self.time {
+            self.input_manager
+                .advance(&mut self.game, self.time, target);
+            self.time = target;
+            self.mode.update(&mut self.game, target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plays a game with irregular frame times, recording it like main does
+    fn record_game() -> (Replay, Tetris<'static>) {
+        let settings = Settings::default();
+        let mut replay = Replay::new(42, &settings, "free");
+        let mut game = Tetris::new(None, &settings.gameplay, 42);
+        let mut input_manager = InputManager::new(&settings);
+        game.start(0);
+        let inputs = [
+            (Action::Left, true),
+            (Action::Left, false),
+            (Action::RotateClockwise, true),
+            (Action::HardDrop, true),
+            (Action::Right, true),
+            (Action::SoftDrop, true),
+            (Action::Right, false),
+            (Action::SoftDrop, false),
+            (Action::Swap, true),
+            (Action::Rotate180, true),
+            (Action::HardDrop, true),
+        ];
+        let mut time = 0;
+        for (i, (action, pressed)) in inputs.iter().cycle().take(120).enumerate() {
+            let new_time = time + 7 + (i as Timestamp * 13) % 50;
+            input_manager.advance(&mut game, time, new_time);
+            time = new_time;
+            input_manager.process_action(*action, *pressed, &mut game, time);
+            replay.record(time, *action, *pressed);
+        }
+        input_manager.advance(&mut game, time, time + 3000);
+        replay.length = time + 3000;
+        (replay, game)
+    }
+
+    #[test]
+    fn playback_reproduces_the_game() {
+        let (replay, game) = record_game();
+        let mut playback = Playback::new(replay);
+        while playback.time() < playback.length() {
+            playback.update(16);
+        }
+        assert_eq!(playback.game().board(), game.board());
+        assert_eq!(playback.game().get_queue(), game.get_queue());
+    }
+
+    #[test]
+    fn seeking_back_and_forth_reproduces_the_game() {
+        let (replay, game) = record_game();
+        let length = replay.length;
+        let mut playback = Playback::new(replay);
+        playback.seek(length / 2);
+        playback.seek(length / 4);
+        playback.set_speed(4.0);
+        playback.update(100);
+        playback.seek(length);
+        assert_eq!(playback.game().board(), game.board());
+        assert_eq!(playback.game().get_queue(), game.get_queue());
+    }
+}
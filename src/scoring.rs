@@ -0,0 +1,145 @@
+// guideline scoring, at level 1 since the trainer has no levels
+
+// garbage sent for each clear in a row, starting with the first one
+const COMBO_ATTACK: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+const PERFECT_CLEAR_ATTACK: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+// what a single locked piece did
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Clear {
+    pub lines: u32,
+    pub spin: Spin,
+    pub perfect_clear: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Stats {
+    pub score: u64,
+    pub lines: u32,
+    pub pieces: u32,
+    pub perfect_clears: u32,
+    pub tspins: u32, // t-spins and minis that cleared lines
+    pub combo: u32,  // pieces in a row that cleared lines
+    pub max_combo: u32,
+    pub attack: u32,        // garbage lines sent, before any cancelling
+    pub back_to_back: bool, // whether the last clear was a tetris or a t-spin
+}
+
+impl Stats {
+    // counts a locked piece and returns the points it scored
+    pub fn add(&mut self, clear: Clear) -> u64 {
+        self.pieces += 1;
+        if clear.lines == 0 {
+            self.combo = 0;
+            // spins that clear nothing still score, but don't touch back to back
+            let points = match clear.spin {
+                Spin::None => 0,
+                Spin::Mini => 100,
+                Spin::Full => 400,
+            };
+            self.score += points;
+            return points;
+        }
+
+        let mut points = match (clear.spin, clear.lines) {
+            (Spin::None, 1) => 100,
+            (Spin::None, 2) => 300,
+            (Spin::None, 3) => 500,
+            (Spin::None, _) => 800,
+            (Spin::Mini, 1) => 200,
+            (Spin::Mini, _) => 400,
+            (Spin::Full, 1) => 800,
+            (Spin::Full, 2) => 1200,
+            (Spin::Full, _) => 1600,
+        };
+        let difficult = clear.lines >= 4 || clear.spin != Spin::None;
+        let back_to_back = difficult && self.back_to_back;
+        if back_to_back {
+            points += points / 2;
+        }
+        points += 50 * self.combo as u64;
+
+        let mut attack = match (clear.spin, clear.lines) {
+            (Spin::None, 1) | (Spin::Mini, 1) => 0,
+            (Spin::None, 2) | (Spin::Mini, _) => 1,
+            (Spin::None, 3) | (Spin::Full, 1) => 2,
+            (Spin::None, _) | (Spin::Full, 2) => 4,
+            (Spin::Full, _) => 6,
+        };
+        attack += back_to_back as u32;
+        attack += COMBO_ATTACK[(self.combo as usize).min(COMBO_ATTACK.len() - 1)];
+        if clear.perfect_clear {
+            attack += PERFECT_CLEAR_ATTACK;
+            points += match clear.lines {
+                1 => 800,
+                2 => 1200,
+                3 => 1800,
+                _ if back_to_back => 3200,
+                _ => 2000,
+            };
+            self.perfect_clears += 1;
+        }
+
+        self.back_to_back = difficult;
+        self.combo += 1;
+        self.max_combo = self.max_combo.max(self.combo);
+        self.lines += clear.lines;
+        self.attack += attack;
+        self.tspins += (clear.spin != Spin::None) as u32;
+        self.score += points;
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, spin: Spin) -> Clear {
+        Clear {
+            lines,
+            spin,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn back_to_back_and_combo() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.add(clear(4, Spin::None)), 800);
+        // a t-spin double right after a tetris is back to back and a combo
+        assert_eq!(stats.add(clear(2, Spin::Full)), 1800 + 50);
+        // a spin clearing nothing keeps back to back but breaks the combo
+        assert_eq!(stats.add(clear(0, Spin::Full)), 400);
+        assert_eq!(stats.add(clear(1, Spin::Mini)), 300);
+        // a plain single breaks back to back
+        assert_eq!(stats.add(clear(1, Spin::None)), 100 + 50);
+        assert_eq!(stats.add(clear(4, Spin::None)), 800 + 100);
+        assert_eq!((stats.lines, stats.pieces, stats.tspins), (12, 6, 2));
+        assert_eq!((stats.combo, stats.max_combo), (3, 3));
+        // the single is too early in the combo to send anything
+        assert_eq!(stats.attack, 4 + (4 + 1) + 1 + (4 + 1));
+    }
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let mut stats = Stats::default();
+        let pc = Clear {
+            lines: 4,
+            spin: Spin::None,
+            perfect_clear: true,
+        };
+        assert_eq!(stats.add(pc), 800 + 2000);
+        stats.add(clear(0, Spin::None));
+        assert_eq!(stats.add(pc), 1200 + 3200);
+        assert_eq!(stats.perfect_clears, 2);
+        assert_eq!(stats.attack, 4 + 10 + 5 + 10);
+    }
+}
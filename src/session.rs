@@ -0,0 +1,268 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bot::PERFECT_CLEAR_HEIGHT;
+use crate::clock::{format_time, Timestamp};
+use crate::events::GameEvent;
+use crate::tetris::{Piece, State, Tetris, BOARD_HEIGHT};
+
+pub const SESSIONS_PATH: &str = "sessions.json";
+
+// the numbers of one sitting, from starting the trainer to closing it
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionStats {
+    pub started: u64,    // unix time in seconds
+    pub time: Timestamp, // ms spent playing, not counting pauses or editing
+    pub pieces: u32,
+    pub keys: u32,
+    pub finesse_faults: u32,
+    pub lines: u32,
+    pub holds: u32,
+    pub perfect_clears: u32, // attempts won, not counting boards loaded half built
+    pub attempts: u32,       // perfect clears tried from an empty board, won or lost
+    pub pc_time: Timestamp,  // ms spent on the attempts that perfect cleared
+}
+
+impl SessionStats {
+    pub fn pps(&self) -> f64 {
+        match self.time {
+            0 => 0.0,
+            time => self.pieces as f64 * 1000.0 / time as f64,
+        }
+    }
+
+    pub fn kpp(&self) -> f64 {
+        match self.pieces {
+            0 => 0.0,
+            pieces => self.keys as f64 / pieces as f64,
+        }
+    }
+
+    // the percentage of attempts that ended in a perfect clear
+    pub fn pc_rate(&self) -> Option<u32> {
+        (self.attempts > 0).then(|| self.perfect_clears * 100 / self.attempts)
+    }
+
+    pub fn average_pc_time(&self) -> Option<Timestamp> {
+        (self.perfect_clears > 0).then(|| self.pc_time / self.perfect_clears as Timestamp)
+    }
+
+    // the side panel next to the queue
+    pub fn panel(&self) -> Vec<String> {
+        vec![
+            "SESSION".to_string(),
+            format!("PPS {:.2}", self.pps()),
+            format!("KPP {:.2}", self.kpp()),
+            format!("FINESSE {}", self.finesse_faults),
+            format!("PCS {}", self.perfect_clears),
+            match self.pc_rate() {
+                Some(rate) => format!("PC RATE {}%", rate),
+                None => "PC RATE -".to_string(),
+            },
+            match self.average_pc_time() {
+                Some(time) => format!("PC TIME {}", format_time(time)),
+                None => "PC TIME -".to_string(),
+            },
+            format!("LINES {}", self.lines),
+            format!("HOLDS {}", self.holds),
+        ]
+    }
+}
+
+// follows a game through its event stream and adds up the session's stats.
+// an attempt starts whenever the board is empty, and fails once the stack
+// grows too tall to perfect clear or the game tops out
+pub struct Session {
+    stats: SessionStats,
+    events: Option<Receiver<GameEvent>>,
+    last_update: Option<Timestamp>,
+    finesse_faults: u32,        // the game's count as of the last update
+    attempt: Option<Timestamp>, // when the attempt being played started
+}
+
+impl Session {
+    pub fn new(started: u64) -> Session {
+        Session {
+            stats: SessionStats {
+                started,
+                ..SessionStats::default()
+            },
+            events: None,
+            last_update: None,
+            finesse_faults: 0,
+            attempt: None,
+        }
+    }
+
+    pub fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
+
+    // follows `game` from now on
+    pub fn watch(&mut self, game: &mut Tetris) {
+        self.events = Some(game.subscribe());
+        self.finesse_faults = game.finesse_faults();
+        self.attempt = None;
+    }
+
+    pub fn key_pressed(&mut self) {
+        self.stats.keys += 1;
+    }
+
+    pub fn update(&mut self, game: &Tetris, timestamp: Timestamp) {
+        if let Some(last_update) = self.last_update {
+            if game.state() == State::Playing {
+                self.stats.time += timestamp.saturating_sub(last_update);
+            }
+        }
+        self.last_update = Some(timestamp);
+
+        // the game's count starts over when it does
+        let finesse_faults = game.finesse_faults();
+        let counted = if finesse_faults >= self.finesse_faults {
+            self.finesse_faults
+        } else {
+            0
+        };
+        self.stats.finesse_faults += finesse_faults - counted;
+        self.finesse_faults = finesse_faults;
+
+        let mut locked = false;
+        let events: Vec<GameEvent> = self.events.iter().flat_map(|e| e.try_iter()).collect();
+        for event in events {
+            match event {
+                GameEvent::Locked { .. } => {
+                    self.stats.pieces += 1;
+                    locked = true;
+                }
+                GameEvent::LinesCleared { lines, .. } => self.stats.lines += lines,
+                GameEvent::Held { .. } => self.stats.holds += 1,
+                GameEvent::PerfectClear => {
+                    if let Some(started) = self.attempt.take() {
+                        self.stats.perfect_clears += 1;
+                        self.stats.attempts += 1;
+                        self.stats.pc_time += timestamp.saturating_sub(started);
+                    }
+                }
+                GameEvent::ToppedOut => self.fail(),
+                _ => {}
+            }
+        }
+        if locked && stack_height(game) > PERFECT_CLEAR_HEIGHT {
+            self.fail();
+        }
+        if self.attempt.is_none() && game.state() == State::Playing && stack_height(game) == 0 {
+            self.attempt = Some(timestamp);
+        }
+    }
+
+    fn fail(&mut self) {
+        if self.attempt.take().is_some() {
+            self.stats.attempts += 1;
+        }
+    }
+}
+
+fn stack_height(game: &Tetris) -> usize {
+    game.board_without_active()
+        .iter()
+        .position(|line| line.iter().any(|cell| *cell != Piece::None))
+        .map_or(0, |line| BOARD_HEIGHT - line)
+}
+
+// every session played, stored as json so progress can be charted over weeks
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionLog {
+    pub sessions: Vec<SessionStats>,
+}
+
+impl SessionLog {
+    // an empty log before the first session
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SessionLog, String> {
+        let path = path.as_ref();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(SessionLog::default()),
+            Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetris::fixtures::{board, game};
+    use crate::tetris::Direction;
+
+    #[test]
+    fn times_perfect_clears_and_counts_failed_attempts() {
+        let mut game = game(None, 0);
+        let mut session = Session::new(0);
+        session.watch(&mut game);
+        session.update(&game, 0);
+
+        // an o finishes the bottom two lines, two seconds in
+        game.load_position(board(2, 4..6), None, &[Piece::O, Piece::O], 0);
+        session.key_pressed();
+        game.harddrop(2000);
+        session.update(&game, 2000);
+
+        // then o pieces stack up in one column until they're too tall
+        for timestamp in [3000, 4000, 5000] {
+            game.set_queue(&[Piece::O]);
+            while game.move_active(Direction::Left) {}
+            session.key_pressed();
+            game.harddrop(timestamp);
+            session.update(&game, timestamp);
+        }
+
+        let stats = session.stats();
+        assert_eq!((stats.pieces, stats.lines), (4, 2));
+        assert_eq!((stats.perfect_clears, stats.attempts), (1, 2));
+        assert_eq!(stats.pc_rate(), Some(50));
+        assert_eq!(stats.average_pc_time(), Some(2000));
+        assert_eq!(stats.kpp(), 1.0);
+        assert_eq!(stats.pps(), 0.8);
+    }
+
+    #[test]
+    fn only_counts_perfect_clears_timed_from_an_empty_board() {
+        let mut game = game(None, 0);
+        let mut session = Session::new(0);
+        session.watch(&mut game);
+        game.load_position(board(2, 4..6), None, &[Piece::O, Piece::T], 0);
+        session.update(&game, 0);
+
+        // the board was half built before the session saw it
+        game.harddrop(1000);
+        session.update(&game, 1000);
+        assert_eq!(
+            (session.stats().perfect_clears, session.stats().attempts),
+            (0, 0)
+        );
+        assert_eq!(session.stats().pc_rate(), None);
+
+        // the next attempt started from the empty board it left
+        game.load_position(board(1, 3..7), None, &[Piece::I, Piece::T], 1000);
+        game.harddrop(4000);
+        session.update(&game, 4000);
+        let stats = session.stats();
+        assert_eq!((stats.perfect_clears, stats.attempts), (1, 1));
+        assert_eq!(stats.pc_rate(), Some(100));
+        assert_eq!(stats.average_pc_time(), Some(3000));
+    }
+}
@@ -0,0 +1,724 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[cfg(feature = "sdl")]
+use sdl2::controller;
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::Scancode;
+use serde::{Deserialize, Serialize};
+
+use crate::tetris::BOARD_HEIGHT;
+
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub handling: HandlingSettings,
+    pub gameplay: GameplaySettings,
+    pub modes: ModeSettings,
+    pub keybinds: Keybinds,
+    pub versus: VersusSettings,
+    pub bot: BotSettings,
+    pub visual: VisualSettings,
+    pub controller: ControllerSettings,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HandlingSettings {
+    pub arr: u32,   // auto-repeat rate in ms
+    pub das: u32,   // delayed auto-start in ms
+    pub dcd: u32,   // das cut delay in ms
+    pub sdf: u32,   // soft drop factor; 100 is instant soft drop
+    pub pahd: u32,  // prevent accidental hard drop in ms
+    pub cdwcd: u32, // cancel das when changing directions in ms
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameplaySettings {
+    pub gravity: u32, // time in ms between the active piece moving down a cell; 0 disables gravity
+    pub lock_delay: u32, // time in ms to wait until a piece is locked automatically
+    pub max_lock_reset_count: u32, // max number of times lock can be cancelled before active locks anyway
+    pub on_perfect_clear: PerfectClearAction,
+    pub garbage_delay: u32, // time in ms before received garbage can be inserted
+    pub garbage_messiness: u32, // percent chance of the garbage hole moving between lines
+    pub garbage_cap: u32,   // most garbage lines inserted after a single piece
+    pub randomizer: Randomizer,
+    pub seed: Option<u64>, // the same pieces every game; a new seed each game if unset
+    pub countdown: u32,    // time in ms counted down before a game starts; 0 starts right away
+}
+
+// what happens once the board is perfect cleared
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PerfectClearAction {
+    Continue, // keep playing on the empty board
+    Restart,  // start over from the same seed, with the same bags
+    Results,  // stop and show the results of the run
+}
+
+// how the queue is dealt
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Randomizer {
+    SevenBag, // all seven pieces in a random order, over and over
+    Random,   // every piece picked on its own, so repeats and droughts happen
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModeSettings {
+    pub sprint_lines: u32,  // lines to clear in sprint
+    pub ultra_time: u32,    // time in ms ultra lasts
+    pub dig_lines: u32,     // garbage lines dig starts with
+    pub dig_messiness: u32, // percent chance of the garbage hole moving between lines
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keybinds {
+    pub left: Vec<Key>,
+    pub right: Vec<Key>,
+    pub softdrop: Vec<Key>,
+    pub harddrop: Vec<Key>,
+    pub rot_counterclockwise: Vec<Key>,
+    pub rot_clockwise: Vec<Key>,
+    pub rot_180: Vec<Key>,
+    pub swap: Vec<Key>,
+}
+
+// the keymaps of two players sharing a keyboard
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VersusSettings {
+    pub player1: Keybinds,
+    pub player2: Keybinds,
+}
+
+// keys the trainer itself listens for in one screen or another, which can't
+// be bound
+pub const RESERVED_KEYS: [&str; 13] = [
+    "Escape",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "Return",
+    "Backspace",
+    "PageUp",
+    "PageDown",
+];
+
+// a key by its sdl name, e.g. "Left Shift". keys are kept by name, so the
+// settings can be loaded without sdl, and only looked up by the frontend
+#[derive(Clone, Eq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Key(String);
+
+// game controllers, by default and by name for pads that need binds of
+// their own. the left stick moves the piece left and right like the d-pad
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllerSettings {
+    pub dead_zone: u32, // percent of the left stick's tilt ignored before the piece moves
+    pub binds: ControllerBinds,
+    pub controllers: BTreeMap<String, ControllerBinds>, // by the name sdl gives the pad
+}
+
+// the buttons of a controller for each action. unlike keys, an action can be
+// left without a button, as pads don't have many
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllerBinds {
+    pub left: Vec<Button>,
+    pub right: Vec<Button>,
+    pub softdrop: Vec<Button>,
+    pub harddrop: Vec<Button>,
+    pub rot_counterclockwise: Vec<Button>,
+    pub rot_clockwise: Vec<Button>,
+    pub rot_180: Vec<Button>,
+    pub swap: Vec<Button>,
+}
+
+// a controller button by its sdl name, e.g. "dpleft" or "a"
+#[derive(Clone, Eq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Button(String);
+
+// how strong the solver bot plays
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotSettings {
+    pub pps: f64,          // most pieces the bot places per second
+    pub mistake_rate: u32, // percent chance of the bot dropping a piece somewhere at random
+}
+
+// what the frontend draws besides the boards
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisualSettings {
+    pub ghost: bool,         // show where the active piece would land
+    pub previews: u32,       // pieces of the queue shown
+    pub session_panel: bool, // show the session's stats next to the queue
+}
+
+impl Default for HandlingSettings {
+    fn default() -> Self {
+        HandlingSettings {
+            arr: 0,
+            das: 100,
+            dcd: 0,
+            sdf: 100,
+            pahd: 100,
+            cdwcd: 100,
+        }
+    }
+}
+
+impl Default for GameplaySettings {
+    fn default() -> Self {
+        GameplaySettings {
+            gravity: 1000,
+            lock_delay: 500,
+            max_lock_reset_count: 20,
+            on_perfect_clear: PerfectClearAction::Results,
+            garbage_delay: 500,
+            garbage_messiness: 0,
+            garbage_cap: 8,
+            randomizer: Randomizer::SevenBag,
+            seed: None,
+            countdown: 3000,
+        }
+    }
+}
+
+impl Default for ModeSettings {
+    fn default() -> Self {
+        ModeSettings {
+            sprint_lines: 40,
+            ultra_time: 120000,
+            dig_lines: 10,
+            dig_messiness: 30,
+        }
+    }
+}
+
+impl Default for Keybinds {
+    fn default() -> Self {
+        Keybinds {
+            left: vec![Key::from("Left")],
+            right: vec![Key::from("Right")],
+            softdrop: vec![Key::from("Down")],
+            harddrop: vec![Key::from("Space")],
+            rot_counterclockwise: vec![Key::from("Z")],
+            rot_clockwise: vec![Key::from("X"), Key::from("Up")],
+            rot_180: vec![Key::from("A")],
+            swap: vec![Key::from("Left Shift")],
+        }
+    }
+}
+
+impl Default for VersusSettings {
+    fn default() -> Self {
+        VersusSettings {
+            player1: Keybinds {
+                left: vec![Key::from("A")],
+                right: vec![Key::from("D")],
+                softdrop: vec![Key::from("S")],
+                harddrop: vec![Key::from("W")],
+                rot_counterclockwise: vec![Key::from("Q")],
+                rot_clockwise: vec![Key::from("E")],
+                rot_180: vec![Key::from("Tab")],
+                swap: vec![Key::from("Left Shift")],
+            },
+            player2: Keybinds {
+                left: vec![Key::from("Left")],
+                right: vec![Key::from("Right")],
+                softdrop: vec![Key::from("Down")],
+                harddrop: vec![Key::from("Up")],
+                rot_counterclockwise: vec![Key::from(".")],
+                rot_clockwise: vec![Key::from("/")],
+                rot_180: vec![Key::from(";")],
+                swap: vec![Key::from("Right Shift")],
+            },
+        }
+    }
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        BotSettings {
+            pps: 1.5,
+            mistake_rate: 5,
+        }
+    }
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        ControllerSettings {
+            dead_zone: 30,
+            binds: ControllerBinds::default(),
+            controllers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ControllerBinds {
+    fn default() -> Self {
+        ControllerBinds {
+            left: vec![Button::from("dpleft")],
+            right: vec![Button::from("dpright")],
+            softdrop: vec![Button::from("dpdown")],
+            harddrop: vec![Button::from("dpup")],
+            rot_counterclockwise: vec![Button::from("a")],
+            rot_clockwise: vec![Button::from("b")],
+            rot_180: vec![Button::from("y")],
+            swap: vec![Button::from("leftshoulder"), Button::from("rightshoulder")],
+        }
+    }
+}
+
+impl Default for VisualSettings {
+    fn default() -> Self {
+        VisualSettings {
+            ghost: true,
+            previews: 5,
+            session_panel: true,
+        }
+    }
+}
+
+impl GameplaySettings {
+    // the seed for a new game
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(rand::random)
+    }
+}
+
+impl Settings {
+    // Loads settings from `path`. If the file does not exist yet, the defaults
+    // are written there so they can be edited by hand.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, String> {
+        let path = path.as_ref();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let settings = Settings::default();
+                settings.save(path)?;
+                return Ok(settings);
+            }
+            Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+        };
+        let settings: Settings = toml::from_str(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))?;
+        settings
+            .validate()
+            .map_err(|e| format!("invalid setting in {}: {}", path.display(), e))?;
+        Ok(settings)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        self.validate()?;
+        let contents = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let handling = &self.handling;
+        check_range("handling.arr", handling.arr, 0, 1000)?;
+        check_range("handling.das", handling.das, 0, 5000)?;
+        check_range("handling.dcd", handling.dcd, 0, 1000)?;
+        check_range("handling.sdf", handling.sdf, 1, 100)?;
+        check_range("handling.pahd", handling.pahd, 0, 1000)?;
+        check_range("handling.cdwcd", handling.cdwcd, 0, 1000)?;
+
+        let gameplay = &self.gameplay;
+        check_range("gameplay.gravity", gameplay.gravity, 0, 60000)?;
+        check_range("gameplay.lock_delay", gameplay.lock_delay, 0, 60000)?;
+        check_range(
+            "gameplay.max_lock_reset_count",
+            gameplay.max_lock_reset_count,
+            0,
+            1000,
+        )?;
+        check_range("gameplay.garbage_delay", gameplay.garbage_delay, 0, 60000)?;
+        check_range(
+            "gameplay.garbage_messiness",
+            gameplay.garbage_messiness,
+            0,
+            100,
+        )?;
+        check_range(
+            "gameplay.garbage_cap",
+            gameplay.garbage_cap,
+            1,
+            BOARD_HEIGHT as u32,
+        )?;
+        check_range("gameplay.countdown", gameplay.countdown, 0, 10000)?;
+
+        let modes = &self.modes;
+        check_range("modes.sprint_lines", modes.sprint_lines, 1, 1000)?;
+        check_range("modes.ultra_time", modes.ultra_time, 1000, 3600000)?;
+        check_range("modes.dig_lines", modes.dig_lines, 1, 18)?;
+        check_range("modes.dig_messiness", modes.dig_messiness, 0, 100)?;
+
+        let bot = &self.bot;
+        if !(0.1..=20.0).contains(&bot.pps) {
+            return Err(format!(
+                "bot.pps must be between 0.1 and 20 (got {})",
+                bot.pps
+            ));
+        }
+        check_range("bot.mistake_rate", bot.mistake_rate, 0, 100)?;
+
+        check_range("visual.previews", self.visual.previews, 0, 5)?;
+
+        self.controller.validate()?;
+
+        self.keybinds.validate("keybinds")?;
+        self.versus.validate()
+    }
+}
+
+impl Keybinds {
+    // every action paired with its name in the settings file
+    pub fn actions(&self) -> [(&'static str, &Vec<Key>); 8] {
+        [
+            ("left", &self.left),
+            ("right", &self.right),
+            ("softdrop", &self.softdrop),
+            ("harddrop", &self.harddrop),
+            ("rot_counterclockwise", &self.rot_counterclockwise),
+            ("rot_clockwise", &self.rot_clockwise),
+            ("rot_180", &self.rot_180),
+            ("swap", &self.swap),
+        ]
+    }
+
+    pub fn actions_mut(&mut self) -> [(&'static str, &mut Vec<Key>); 8] {
+        [
+            ("left", &mut self.left),
+            ("right", &mut self.right),
+            ("softdrop", &mut self.softdrop),
+            ("harddrop", &mut self.harddrop),
+            ("rot_counterclockwise", &mut self.rot_counterclockwise),
+            ("rot_clockwise", &mut self.rot_clockwise),
+            ("rot_180", &mut self.rot_180),
+            ("swap", &mut self.swap),
+        ]
+    }
+
+    // binds `key` to the action at `index` too, taking it from whichever
+    // other action had it. returns that action's name
+    pub fn bind(&mut self, index: usize, key: Key) -> Option<&'static str> {
+        let mut taken_from = None;
+        for (i, (name, keys)) in self.actions_mut().into_iter().enumerate() {
+            if i != index && keys.contains(&key) {
+                keys.retain(|other| *other != key);
+                taken_from = Some(name);
+            }
+        }
+        let (_, keys) = self.actions_mut().into_iter().nth(index)?;
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+        taken_from
+    }
+
+    pub fn unbind(&mut self, index: usize, key: &Key) {
+        if let Some((_, keys)) = self.actions_mut().into_iter().nth(index) {
+            keys.retain(|other| other != key);
+        }
+    }
+
+    // puts the action at `index` back to its default keys, taking them from
+    // other actions like `bind` does
+    pub fn reset(&mut self, index: usize) {
+        if let Some((_, keys)) = self.actions_mut().into_iter().nth(index) {
+            keys.clear();
+        }
+        let defaults = Keybinds::default();
+        if let Some((_, keys)) = defaults.actions().get(index) {
+            for key in keys.iter() {
+                self.bind(index, key.clone());
+            }
+        }
+    }
+
+    // checks the keybinds found under `section` in the settings file
+    pub fn validate(&self, section: &str) -> Result<(), String> {
+        let actions = self.actions();
+        for (i, (name, keys)) in actions.iter().enumerate() {
+            if keys.is_empty() {
+                return Err(format!("{}.{} needs at least one key", section, name));
+            }
+            for key in keys.iter() {
+                #[cfg(feature = "sdl")]
+                if key.scancode().is_none() {
+                    return Err(format!(
+                        "unknown key name \"{}\" in {}.{}",
+                        key.name(),
+                        section,
+                        name
+                    ));
+                }
+                if key.is_reserved() {
+                    return Err(format!(
+                        "key \"{}\" in {}.{} is used by the trainer",
+                        key.name(),
+                        section,
+                        name
+                    ));
+                }
+                for (other_name, other_keys) in actions[i + 1..].iter() {
+                    if other_keys.contains(key) {
+                        return Err(format!(
+                            "key \"{}\" is bound to both {}.{} and {}.{}",
+                            key.name(),
+                            section,
+                            name,
+                            section,
+                            other_name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ControllerSettings {
+    // the binds for the controller sdl calls `name`
+    pub fn binds_for(&self, name: &str) -> &ControllerBinds {
+        self.controllers.get(name).unwrap_or(&self.binds)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        check_range("controller.dead_zone", self.dead_zone, 0, 95)?;
+        self.binds.validate("controller.binds")?;
+        for (name, binds) in self.controllers.iter() {
+            binds.validate(&format!("controller.controllers.\"{}\"", name))?;
+        }
+        Ok(())
+    }
+}
+
+impl ControllerBinds {
+    pub fn actions(&self) -> [(&'static str, &Vec<Button>); 8] {
+        [
+            ("left", &self.left),
+            ("right", &self.right),
+            ("softdrop", &self.softdrop),
+            ("harddrop", &self.harddrop),
+            ("rot_counterclockwise", &self.rot_counterclockwise),
+            ("rot_clockwise", &self.rot_clockwise),
+            ("rot_180", &self.rot_180),
+            ("swap", &self.swap),
+        ]
+    }
+
+    pub fn validate(&self, section: &str) -> Result<(), String> {
+        let actions = self.actions();
+        for (i, (name, buttons)) in actions.iter().enumerate() {
+            for button in buttons.iter() {
+                #[cfg(feature = "sdl")]
+                if button.button().is_none() {
+                    return Err(format!(
+                        "unknown button name \"{}\" in {}.{}",
+                        button.name(),
+                        section,
+                        name
+                    ));
+                }
+                for (other_name, other_buttons) in actions[i + 1..].iter() {
+                    if other_buttons.contains(button) {
+                        return Err(format!(
+                            "button \"{}\" is bound to both {}.{} and {}.{}",
+                            button.name(),
+                            section,
+                            name,
+                            section,
+                            other_name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VersusSettings {
+    // each keymap on its own, then no key shared between the players
+    pub fn validate(&self) -> Result<(), String> {
+        self.player1.validate("versus.player1")?;
+        self.player2.validate("versus.player2")?;
+        for (name, keys) in self.player1.actions() {
+            for (other_name, other_keys) in self.player2.actions() {
+                if let Some(key) = keys.iter().find(|key| other_keys.contains(key)) {
+                    return Err(format!(
+                        "key \"{}\" is bound to both versus.player1.{} and versus.player2.{}",
+                        key.name(),
+                        name,
+                        other_name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Key {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        RESERVED_KEYS
+            .iter()
+            .any(|reserved| *self == Key::from(*reserved))
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl Key {
+    pub fn scancode(&self) -> Option<Scancode> {
+        Scancode::from_name(&self.0)
+    }
+}
+
+impl From<&str> for Key {
+    fn from(name: &str) -> Key {
+        Key(name.to_string())
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Scancode> for Key {
+    fn from(scancode: Scancode) -> Key {
+        Key(scancode.name().to_string())
+    }
+}
+
+// sdl looks key names up regardless of case
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Button {
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl Button {
+    pub fn button(&self) -> Option<controller::Button> {
+        controller::Button::from_string(&self.0)
+    }
+}
+
+impl From<&str> for Button {
+    fn from(name: &str) -> Button {
+        Button(name.to_string())
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<controller::Button> for Button {
+    fn from(button: controller::Button) -> Button {
+        Button(button.string())
+    }
+}
+
+impl PartialEq for Button {
+    fn eq(&self, other: &Button) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+fn check_range(name: &str, value: u32, min: u32, max: u32) -> Result<(), String> {
+    if value < min || value > max {
+        return Err(format!(
+            "{} must be between {} and {} (got {})",
+            name, min, max, value
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Settings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn an_unset_seed_round_trips() {
+        let settings = Settings::default();
+        let contents = toml::to_string_pretty(&settings).unwrap();
+        let loaded: Settings = toml::from_str(&contents).unwrap();
+        assert_eq!(loaded.gameplay.seed, None);
+        assert_eq!(loaded.gameplay.randomizer, Randomizer::SevenBag);
+    }
+
+    #[test]
+    fn binding_a_key_takes_it_from_other_actions() {
+        let mut keybinds = Keybinds::default();
+        assert_eq!(keybinds.bind(0, Key::from("left shift")), Some("swap"));
+        assert_eq!(
+            keybinds.left,
+            vec![Key::from("Left"), Key::from("Left Shift")]
+        );
+        assert!(keybinds.swap.is_empty());
+        assert!(keybinds.validate("keybinds").is_err());
+
+        keybinds.reset(7);
+        assert_eq!(keybinds.swap, vec![Key::from("Left Shift")]);
+        assert_eq!(keybinds.left, vec![Key::from("Left")]);
+        keybinds.unbind(5, &Key::from("Up"));
+        assert_eq!(keybinds.rot_clockwise, vec![Key::from("X")]);
+    }
+
+    #[test]
+    fn keys_the_trainer_uses_cannot_be_bound() {
+        let keybinds = Keybinds {
+            harddrop: vec![Key::from("return")],
+            ..Keybinds::default()
+        };
+        assert_eq!(
+            keybinds.validate("keybinds"),
+            Err("key \"return\" in keybinds.harddrop is used by the trainer".to_string())
+        );
+    }
+
+    #[test]
+    fn versus_players_cannot_share_keys() {
+        let mut settings = Settings::default();
+        settings.versus.player2.swap = vec![Key::from("Left Shift")];
+        assert_eq!(
+            settings.validate(),
+            Err(
+                "key \"Left Shift\" is bound to both versus.player1.swap and versus.player2.swap"
+                    .to_string()
+            )
+        );
+    }
+}
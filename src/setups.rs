@@ -0,0 +1,385 @@
+use crate::fumen::piece_from_char;
+use crate::solver::{self, Board, PlacedPiece};
+use crate::tetris::{Piece, BOARD_HEIGHT, BOARD_WIDTH};
+
+// the built-in setups: a name, the piece it saves for once it's built, if
+// any, its shape as rows from the top down to the floor and a fumen building
+// it a piece per page. each setup is also available mirrored
+const LIBRARY: [(&str, Option<char>, &[&str], &str); 6] = [
+    (
+        "PCO",
+        Some('T'),
+        &["______ZJJJ", "L____ZZSSJ", "L____ZSSOO", "LL__IIIIOO"],
+        "v115@vhGKJJTNJxQJ3HJsGJGDJAgH",
+    ),
+    (
+        "Block PC",
+        None,
+        &["____SIIIIZ", "____SSOOZZ", "____JSOOZL", "____JJJLLL"],
+        "v115@vhG2QJSSJvGJMIJTHJRCJAgH",
+    ),
+    (
+        "TKI-3",
+        Some('T'),
+        &["ZZ_J______", "_ZZJ____SS", "__JJ_OOSSL", "_IIIIOOLLL"],
+        "v115@vhGzLJSSJRPJ+FJXIJ0/IAgH",
+    ),
+    (
+        "TSD opener",
+        Some('T'),
+        &["ZZ_______I", "LZZ___J__I", "L___SSJOOI", "LL_SSJJOOI"],
+        "v115@vhGpIJKJJeMJXLJzMJ0/IAgH",
+    ),
+    // the first bag of it. the second bag finishes the tsd and tst slots
+    // around the saved t, which no bag can be relied on to do in order
+    (
+        "DT cannon",
+        Some('T'),
+        &[
+            "______LL__",
+            "_______L__",
+            "______SL__",
+            "______SS_I",
+            "_______S_I",
+            "_J_ZZ__OOI",
+            "_JJJZZ_OOI",
+        ],
+        "v115@vhGpIJWPJULJzMJvCJ64IAgH",
+    ),
+    (
+        "Second PC",
+        None,
+        &["__________", "____TLL___", "___TTTLJ__", "__IIIILJJJ"],
+        "v115@vhEaMJWSJxPJVLJAgH",
+    ),
+];
+
+pub struct Setup {
+    pub name: String,
+    pub keep: Option<Piece>, // must still be held or to come once the setup is built
+    targets: Vec<PlacedPiece>, // the cells each piece of the setup covers
+}
+
+impl Setup {
+    // every setup in the library followed by its mirror image
+    pub fn library() -> Vec<Setup> {
+        LIBRARY
+            .iter()
+            .flat_map(|(name, keep, rows, _)| {
+                let mirrored: Vec<String> = rows
+                    .iter()
+                    .map(|row| row.chars().rev().map(mirror_piece).collect())
+                    .collect();
+                let mirrored_keep = keep.map(mirror_piece);
+                [
+                    Setup::new(name, *keep, rows),
+                    Setup::new(&format!("{} (mirrored)", name), mirrored_keep, &mirrored),
+                ]
+            })
+            .collect()
+    }
+
+    // pieces are the groups of touching cells with the same letter
+    fn new<S: AsRef<str>>(name: &str, keep: Option<char>, rows: &[S]) -> Setup {
+        let mut cells = [[None; BOARD_WIDTH]; BOARD_HEIGHT];
+        for (line, row) in cells[BOARD_HEIGHT - rows.len()..].iter_mut().zip(rows) {
+            for (cell, c) in line.iter_mut().zip(row.as_ref().chars()) {
+                *cell = piece_from_char(c);
+            }
+        }
+        let mut targets = Vec::new();
+        for line in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                let piece = match cells[line][col].take() {
+                    Some(piece) => piece,
+                    None => continue,
+                };
+                let mut piece_cells = vec![(line, col)];
+                let mut i = 0;
+                while i < piece_cells.len() {
+                    let (l, c) = piece_cells[i];
+                    for (nl, nc) in [
+                        (l + 1, c),
+                        (l.wrapping_sub(1), c),
+                        (l, c + 1),
+                        (l, c.wrapping_sub(1)),
+                    ] {
+                        if nl < BOARD_HEIGHT && nc < BOARD_WIDTH && cells[nl][nc] == Some(piece) {
+                            cells[nl][nc] = None;
+                            piece_cells.push((nl, nc));
+                        }
+                    }
+                    i += 1;
+                }
+                let mut target = [(0, 0); 4];
+                assert_eq!(
+                    piece_cells.len(),
+                    4,
+                    "{} has a piece that isn't 4 cells",
+                    name
+                );
+                target.copy_from_slice(&piece_cells);
+                target.sort();
+                targets.push((piece, target));
+            }
+        }
+        Setup {
+            name: name.to_string(),
+            keep: keep.and_then(piece_from_char),
+            targets,
+        }
+    }
+
+    pub fn targets(&self) -> &[PlacedPiece] {
+        &self.targets
+    }
+
+    // the placements, in order, that finish the setup from `board` with
+    // `pieces` (the active piece first) and the hold, leaving the piece it
+    // keeps in hand. returns None if the board has anything on it that isn't
+    // part of the setup or the pieces can't finish it. if `more` pieces are
+    // still to come, a plan that uses up every piece without finishing will
+    // do, as will one whose kept piece may be among them
+    pub fn plan(
+        &self,
+        board: &Board,
+        pieces: &[Piece],
+        hold: Option<Piece>,
+        more: bool,
+    ) -> Option<Vec<PlacedPiece>> {
+        let filled = |cells: &[(usize, usize); 4]| {
+            cells
+                .iter()
+                .filter(|(line, col)| board[*line][*col] != Piece::None)
+                .count()
+        };
+        let mut remaining = Vec::new();
+        let mut placed = 0;
+        for target in self.targets.iter() {
+            match filled(&target.1) {
+                0 => remaining.push(*target),
+                4 => placed += 4,
+                _ => return None,
+            }
+        }
+        let total = board
+            .iter()
+            .flatten()
+            .filter(|c| **c != Piece::None)
+            .count();
+        if total != placed {
+            return None;
+        }
+        let mut board = *board;
+        let mut plan = Vec::new();
+        if search(
+            &mut board,
+            &mut remaining,
+            pieces,
+            hold.unwrap_or(Piece::None),
+            self.keep,
+            more,
+            &mut plan,
+        ) {
+            Some(plan)
+        } else {
+            None
+        }
+    }
+}
+
+fn search(
+    board: &mut Board,
+    remaining: &mut Vec<PlacedPiece>,
+    pieces: &[Piece],
+    hold: Piece,
+    keep: Option<Piece>,
+    more: bool,
+    plan: &mut Vec<PlacedPiece>,
+) -> bool {
+    if remaining.is_empty() {
+        return keep.is_none_or(|keep| more || hold == keep || pieces.contains(&keep));
+    }
+    // the piece to place, the pieces left after it and the hold afterwards
+    let mut options = Vec::new();
+    match pieces.split_first() {
+        Some((&current, rest)) => {
+            options.push((current, rest, hold));
+            if hold != Piece::None {
+                options.push((hold, rest, current));
+            } else if let Some((&next, rest)) = rest.split_first() {
+                options.push((next, rest, current));
+            }
+        }
+        None if hold != Piece::None => options.push((hold, pieces, Piece::None)),
+        None => {}
+    }
+    for (piece, rest, hold) in options {
+        if !remaining.iter().any(|target| target.0 == piece) {
+            continue;
+        }
+        let reachable = solver::reachable(board, piece);
+        for i in 0..remaining.len() {
+            let target = remaining[i];
+            if target.0 != piece || !reachable.contains(&target.1) {
+                continue;
+            }
+            for (line, col) in target.1 {
+                board[line][col] = piece;
+            }
+            remaining.remove(i);
+            plan.push(target);
+            if search(board, remaining, rest, hold, keep, more, plan) {
+                return true;
+            }
+            plan.pop();
+            remaining.insert(i, target);
+            for (line, col) in target.1 {
+                board[line][col] = Piece::None;
+            }
+        }
+    }
+    // with only the hold left, the pieces still to come may be what's needed
+    more && pieces.is_empty()
+}
+
+fn mirror_piece(c: char) -> char {
+    match c {
+        'L' => 'J',
+        'J' => 'L',
+        'S' => 'Z',
+        'Z' => 'S',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fumen;
+    use crate::tetris::PIECES;
+
+    const EMPTY: Board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+
+    #[test]
+    fn every_setup_fits_some_bag() {
+        for setup in Setup::library() {
+            // try every rotation of one bag order and its reverse
+            let fits = (0..PIECES.len()).any(|start| {
+                let bag: Vec<Piece> = PIECES.iter().cycle().skip(start).take(7).copied().collect();
+                let reversed: Vec<Piece> = bag.iter().rev().copied().collect();
+                setup.plan(&EMPTY, &bag, None, false).is_some()
+                    || setup.plan(&EMPTY, &reversed, None, false).is_some()
+            });
+            assert!(fits, "no bag builds {}", setup.name);
+        }
+    }
+
+    #[test]
+    fn every_setup_matches_its_reference_build() {
+        for (name, keep, rows, build) in LIBRARY {
+            let setup = Setup::new(name, keep, rows);
+            // a page per piece, each put where the game can place it, then
+            // the finished setup
+            let pages = fumen::decode(build).unwrap();
+            let (finished, placing) = pages.split_last().unwrap();
+            assert_eq!(placing.len(), setup.targets().len(), "{}", name);
+            for (page, next) in placing.iter().zip(&pages[1..]) {
+                let piece = page.operation.unwrap().piece;
+                let cells: Vec<(usize, usize)> = (0..BOARD_HEIGHT)
+                    .flat_map(|line| (0..BOARD_WIDTH).map(move |col| (line, col)))
+                    .filter(|(line, col)| page.field[*line][*col] != next.field[*line][*col])
+                    .collect();
+                let target = (piece, <[(usize, usize); 4]>::try_from(cells).unwrap());
+                assert!(
+                    setup.targets().contains(&target),
+                    "{} has no {:?}",
+                    name,
+                    target
+                );
+                assert!(
+                    solver::reachable(&page.field, piece).contains(&target.1),
+                    "{} can't be built with {:?}",
+                    name,
+                    target
+                );
+            }
+            let mut board = EMPTY;
+            for (piece, cells) in setup.targets() {
+                for (line, col) in cells {
+                    board[*line][*col] = *piece;
+                }
+            }
+            assert_eq!(finished.field, board, "{}", name);
+        }
+    }
+
+    #[test]
+    fn plans_from_a_partial_build() {
+        let library = Setup::library();
+        let setup = &library[0];
+        let bag = [
+            Piece::T,
+            Piece::I,
+            Piece::L,
+            Piece::O,
+            Piece::J,
+            Piece::S,
+            Piece::Z,
+        ];
+        let plan = setup.plan(&EMPTY, &bag, None, false).unwrap();
+        assert_eq!(plan.len(), 6);
+        assert_eq!(plan[0].0, Piece::I);
+
+        // the T went to hold for the I
+        let mut board = EMPTY;
+        for (line, col) in plan[0].1 {
+            board[line][col] = plan[0].0;
+        }
+        assert_eq!(
+            setup
+                .plan(&board, &bag[2..], Some(Piece::T), false)
+                .unwrap()
+                .len(),
+            5
+        );
+        // a piece somewhere else means it isn't this setup anymore
+        board[BOARD_HEIGHT - 4][2] = Piece::Garbage;
+        assert!(setup
+            .plan(&board, &bag[2..], Some(Piece::T), false)
+            .is_none());
+    }
+
+    #[test]
+    fn a_plan_can_wait_for_pieces_not_seen_yet() {
+        let library = Setup::library();
+        let setup = &library[0];
+        let seen = [Piece::T, Piece::I, Piece::L];
+        assert!(setup.plan(&EMPTY, &seen, None, false).is_none());
+        let plan = setup.plan(&EMPTY, &seen, None, true).unwrap();
+        assert_eq!(plan.len(), 2);
+        // but not if what's seen can't be placed
+        assert!(setup
+            .plan(&EMPTY, &[Piece::T, Piece::T], None, true)
+            .is_none());
+    }
+
+    #[test]
+    fn the_kept_piece_has_to_be_left_over() {
+        let library = Setup::library();
+        let setup = &library[6];
+        assert_eq!(setup.keep, Some(Piece::T));
+        let bag = [Piece::I, Piece::O, Piece::L, Piece::J, Piece::S, Piece::Z];
+        assert!(setup.plan(&EMPTY, &bag, None, false).is_none());
+        assert!(setup.plan(&EMPTY, &bag, None, true).is_some());
+        assert!(setup.plan(&EMPTY, &bag, Some(Piece::T), false).is_some());
+    }
+
+    #[test]
+    fn mirrored_setups_swap_pieces() {
+        let library = Setup::library();
+        let count = |setup: &Setup, piece| setup.targets().iter().filter(|t| t.0 == piece).count();
+        assert_eq!(count(&library[0], Piece::L), count(&library[1], Piece::J));
+        assert_eq!(library[1].targets().len(), library[0].targets().len());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     hash::Hash,
+    iter,
     sync::Arc,
 };
 
@@ -9,9 +10,18 @@ use rand::{
     SeedableRng,
 };
 
-use crate::tetris::{self, Piece, BOARD_HEIGHT, BOARD_WIDTH, PIECES};
+use crate::get_deltas;
+use crate::input_manager::Action;
+use crate::tetris::{kick_tests, Piece, Rotation, BOARD_HEIGHT, BOARD_WIDTH, PIECES};
+
+pub type Board = [[Piece; BOARD_WIDTH]; BOARD_HEIGHT];
+
+const FULL_ROW: u16 = (1 << BOARD_WIDTH) - 1;
+const SPAWN_LINE: i32 = -3; // pieces spawn above the rows being cleared, like the spawn rows of the board
+const MIN_LINE: i32 = -8; // highest a piece is moved while searching for placements
+const MAX_SEARCH_NODES: usize = 1_000_000;
+const MAX_HEIGHT: usize = BOARD_HEIGHT - 4; // the top rows are left free for pieces to spawn in
 
-const 
 #[derive(Clone, Eq)]
 pub struct SolverState {
     pub board: [[Piece; BOARD_WIDTH]; BOARD_HEIGHT],
@@ -90,10 +100,10 @@ impl PartialEq for SolverState {
 }
 
 impl SolverState {
-    pub fn new() -> SolverState {
+    pub fn new(seed: u64) -> SolverState {
         // Fill initial queue
         let mut queue: VecDeque<Piece> = VecDeque::new();
-        let mut rng: StdRng = SeedableRng::from_entropy();
+        let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
 
         let mut pieces_clone = PIECES.clone();
         pieces_clone.shuffle(&mut rng);
@@ -111,23 +121,460 @@ impl SolverState {
     }
 }
 
+// a piece locked at the position it had as the active piece
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Placement {
+    pub piece: Piece,
+    pub rotation: Rotation,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Placement {
+    pub fn cells(&self) -> [(usize, usize); 4] {
+        cells(self.piece, self.rotation, self.line as i32, self.col as i32)
+            .map(|(line, col)| (line as usize, col as usize))
+    }
+}
+
+pub type PlacedPiece = (Piece, [(usize, usize); 4]);
+type Solutions = Arc<Vec<Vec<PlacedPiece>>>;
+
+// a perfect clear as the cells each piece ends up covering, in board lines as
+// they were before any lines were cleared. the order pieces are placed in
+// doesn't matter, so the same solution is found through every order
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Solution {
+    pieces: Vec<PlacedPiece>,
+}
+
+impl Solution {
+    fn new(mut pieces: Vec<PlacedPiece>) -> Solution {
+        pieces.sort();
+        Solution { pieces }
+    }
+
+    // plays `placements` on `board` and returns the solution they form, if
+    // they perfect clear it
+    pub fn from_placements(board: &Board, placements: &[Placement]) -> Option<Solution> {
+        let mut field = Field::new(board, BOARD_HEIGHT);
+        let mut pieces = Vec::new();
+        for placement in placements {
+            let offset = (BOARD_HEIGHT - field.rows.len()) as i32;
+            let cells = placement
+                .cells()
+                .map(|(line, col)| (line as i32 - offset, col as i32));
+            if cells.iter().any(|cell| cell.0 < 0) || !field.fits(&cells) {
+                return None;
+            }
+            pieces.push((placement.piece, field.place(&cells)));
+        }
+        if field.is_clear() {
+            Some(Solution::new(pieces))
+        } else {
+            None
+        }
+    }
+
+    pub fn pieces(&self) -> &[PlacedPiece] {
+        &self.pieces
+    }
+
+    // `board` with the solution's pieces drawn on it
+    pub fn draw(&self, board: &Board) -> Board {
+        let mut board = *board;
+        for (piece, cells) in self.pieces.iter() {
+            for (line, col) in cells {
+                board[*line][*col] = *piece;
+            }
+        }
+        board
+    }
+}
+
+// the rows that still have to be cleared, top to bottom, as bitmasks of their
+// filled cells, along with the board line each row started out on
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Field {
+    rows: Vec<u16>,
+    lines: Vec<usize>,
+}
+
+impl Field {
+    // the bottom `height` rows of `board`
+    fn new(board: &Board, height: usize) -> Field {
+        let lines: Vec<usize> = (BOARD_HEIGHT - height..BOARD_HEIGHT).collect();
+        let rows = lines
+            .iter()
+            .map(|line| {
+                board[*line]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cell)| **cell != Piece::None)
+                    .fold(0, |row, (col, _)| row | 1 << col)
+            })
+            .collect();
+        Field { rows, lines }
+    }
+
+    // everything above the field is empty
+    fn occupied(&self, line: i32, col: i32) -> bool {
+        if col < 0 || col >= BOARD_WIDTH as i32 || line >= self.rows.len() as i32 {
+            return true;
+        }
+        line >= 0 && self.rows[line as usize] & 1 << col != 0
+    }
+
+    fn fits(&self, cells: &[(i32, i32); 4]) -> bool {
+        cells.iter().all(|(line, col)| !self.occupied(*line, *col))
+    }
+
+    // fills the cells, clears full rows and returns the board cells filled
+    fn place(&mut self, cells: &[(i32, i32); 4]) -> [(usize, usize); 4] {
+        let mut placed = [(0, 0); 4];
+        for (i, (line, col)) in cells.iter().enumerate() {
+            self.rows[*line as usize] |= 1 << col;
+            placed[i] = (self.lines[*line as usize], *col as usize);
+        }
+        placed.sort();
+        let mut i = 0;
+        while i < self.rows.len() {
+            if self.rows[i] == FULL_ROW {
+                self.rows.remove(i);
+                self.lines.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        placed
+    }
+
+    // whether no cells are filled, even if there are rows left
+    fn is_clear(&self) -> bool {
+        self.rows.iter().all(|row| *row == 0)
+    }
+
+    // whether every part of the field that pieces could fill on their own has
+    // a multiple of 4 empty cells. a piece can only cross between two columns
+    // through a row where both are empty, and line clears never change that
+    fn can_be_filled(&self) -> bool {
+        let mut empty = 0;
+        for col in 0..BOARD_WIDTH {
+            empty += self.rows.iter().filter(|row| *row & 1 << col == 0).count();
+            let crossable =
+                col + 1 < BOARD_WIDTH && self.rows.iter().any(|row| (!*row >> col) & 0b11 == 0b11);
+            if !crossable {
+                if !empty.is_multiple_of(4) {
+                    return false;
+                }
+                empty = 0;
+            }
+        }
+        true
+    }
+
+    fn empty_cells(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|row| BOARD_WIDTH - row.count_ones() as usize)
+            .sum()
+    }
+}
+
+// a piece's orientation and the line and column of its centre cell
+pub(crate) type Position = (Rotation, i32, i32);
+
+pub(crate) fn cells(piece: Piece, rotation: Rotation, line: i32, col: i32) -> [(i32, i32); 4] {
+    let (a, b, c): ((i32, i32), (i32, i32), (i32, i32)) = get_deltas!(piece, rotation);
+    [
+        (line, col),
+        (line + a.0, col + a.1),
+        (line + b.0, col + b.1),
+        (line + c.0, col + c.1),
+    ]
+}
+
+// whether `piece` at `position` is on `board`, clear of what's on it
+pub(crate) fn fits_board(board: &Board, piece: Piece, (rot, line, col): Position) -> bool {
+    cells(piece, rot, line, col).iter().all(|(line, col)| {
+        (0..BOARD_HEIGHT as i32).contains(line)
+            && (0..BOARD_WIDTH as i32).contains(col)
+            && board[*line as usize][*col as usize] == Piece::None
+    })
+}
+
+// where a piece at `position` lands when dropped
+pub(crate) fn drop(fits: impl Fn(Position) -> bool, (rot, mut line, col): Position) -> Position {
+    while fits((rot, line + 1, col)) {
+        line += 1;
+    }
+    (rot, line, col)
+}
+
+// the positions `piece` can go from `position` with a single tap or rotation,
+// kicking the way the game does, and the action that takes it there. drops
+// are left to the caller, as each search goes down in its own way
+pub(crate) fn moves(
+    piece: Piece,
+    (rot, line, col): Position,
+    fits: impl Fn(Position) -> bool,
+) -> Vec<(Position, Action)> {
+    let mut moves = vec![
+        ((rot, line, col - 1), Action::Left),
+        ((rot, line, col + 1), Action::Right),
+    ];
+    if piece != Piece::O {
+        for (turn, action) in [
+            (Rotation::Left, Action::RotateCounterclockwise),
+            (Rotation::Right, Action::RotateClockwise),
+        ] {
+            let to = rot + turn;
+            let tests = kick_tests(piece, rot, to).unwrap_or_default();
+            if let Some((dl, dc)) = iter::once((0, 0))
+                .chain(tests)
+                .find(|(dl, dc)| fits((to, line + dl, col + dc)))
+            {
+                moves.push(((to, line + dl, col + dc), action));
+            }
+        }
+        moves.push(((rot + Rotation::Flip, line, col), Action::Rotate180));
+    }
+    moves.retain(|(position, _)| fits(*position));
+    moves
+}
+
+// every distinct spot `piece` can lock in, found by moving and rotating it
+// from the spawn position the same way the game does
+fn placements(field: &Field, piece: Piece) -> Vec<[(i32, i32); 4]> {
+    let fits = |(rot, line, col): Position| field.fits(&cells(piece, rot, line, col));
+    let start = (Rotation::Spawn, SPAWN_LINE, 4);
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut found: Vec<[(i32, i32); 4]> = Vec::new();
+    while let Some((rot, line, col)) = queue.pop_front() {
+        let shape = cells(piece, rot, line, col);
+        if !fits((rot, line + 1, col)) && shape.iter().all(|cell| cell.0 >= 0) {
+            let mut sorted = shape;
+            sorted.sort();
+            if !found.contains(&sorted) {
+                found.push(sorted);
+            }
+        }
+
+        let down = (rot, line + 1, col);
+        let next = moves(piece, (rot, line, col), fits)
+            .into_iter()
+            .map(|(position, _)| position)
+            .chain(fits(down).then_some(down));
+        for position in next {
+            if position.1 >= MIN_LINE && seen.insert(position) {
+                queue.push_back(position);
+            }
+        }
+    }
+    found
+}
+
+// the cells of every spot `piece` can lock in on `board`, without clearing
+// lines
+pub fn reachable(board: &Board, piece: Piece) -> Vec<[(usize, usize); 4]> {
+    placements(&Field::new(board, BOARD_HEIGHT), piece)
+        .into_iter()
+        .map(|cells| cells.map(|(line, col)| (line as usize, col as usize)))
+        .collect()
+}
+
 pub struct Solver {
-    dyn_prog: HashMap<SolverState, Arc<Vec<SolverState>>>,
+    // the solutions found from each position, as the pieces still to place
+    dyn_prog: HashMap<(Field, usize, Piece), Solutions>,
+    nodes: usize,
 }
 
 impl Solver {
     pub fn new() -> Solver {
         Solver {
             dyn_prog: HashMap::new(),
+            nodes: 0,
         }
     }
 
-    pub fn solve(&mut self, state: SolverState) {
-        if (self.dyn_prog.contains_key(&state)) {
-            return;
-        };
+    // finds every perfect clear of `state`'s board using its active piece,
+    // queue and hold. only the lowest perfect clears possible are returned
+    pub fn solve(&mut self, state: &SolverState) -> Result<Vec<Solution>, String> {
+        let pieces: Vec<Piece> = iter::once(state.piece_active)
+            .chain(state.queue.iter().copied())
+            .filter(|piece| *piece != Piece::None)
+            .collect();
+        let available = pieces.len() + (state.swap_piece != Piece::None) as usize;
+        let filled = state
+            .board
+            .iter()
+            .flatten()
+            .filter(|cell| **cell != Piece::None)
+            .count();
+        let stack = BOARD_HEIGHT
+            - state
+                .board
+                .iter()
+                .position(|line| line.iter().any(|cell| *cell != Piece::None))
+                .unwrap_or(BOARD_HEIGHT);
 
-        
+        for height in stack.max(1)..=MAX_HEIGHT {
+            let empty = height * BOARD_WIDTH - filled;
+            if !empty.is_multiple_of(4) {
+                continue;
+            }
+            if empty / 4 > available {
+                break;
+            }
+            self.dyn_prog.clear();
+            self.nodes = 0;
+            let field = Field::new(&state.board, height);
+            let found = self.search(&field, &pieces, 0, state.swap_piece)?;
+            if !found.is_empty() {
+                let mut solutions: Vec<Solution> =
+                    found.iter().map(|p| Solution::new(p.clone())).collect();
+                solutions.sort();
+                solutions.dedup();
+                return Ok(solutions);
+            }
+        }
+        Ok(Vec::new())
     }
 
+    fn search(
+        &mut self,
+        field: &Field,
+        pieces: &[Piece],
+        next: usize,
+        hold: Piece,
+    ) -> Result<Solutions, String> {
+        if field.rows.is_empty() {
+            return Ok(Arc::new(vec![Vec::new()]));
+        }
+        let key = (field.clone(), next, hold);
+        if let Some(found) = self.dyn_prog.get(&key) {
+            return Ok(found.clone());
+        }
+        self.nodes += 1;
+        if self.nodes > MAX_SEARCH_NODES {
+            return Err(format!(
+                "gave up after searching {} positions",
+                MAX_SEARCH_NODES
+            ));
+        }
+
+        // the piece to place, with where the queue and hold are left after
+        let mut options = Vec::new();
+        let remaining = pieces.len() - next.min(pieces.len()) + (hold != Piece::None) as usize;
+        if remaining * 4 >= field.empty_cells() && field.can_be_filled() {
+            match (pieces.get(next), hold) {
+                (Some(&current), Piece::None) => {
+                    options.push((current, next + 1, hold));
+                    if let Some(&second) = pieces.get(next + 1) {
+                        options.push((second, next + 2, current));
+                    }
+                }
+                (Some(&current), _) => {
+                    options.push((current, next + 1, hold));
+                    if hold != current {
+                        options.push((hold, next + 1, current));
+                    }
+                }
+                (None, Piece::None) => {}
+                (None, _) => options.push((hold, next, Piece::None)),
+            }
+        }
+
+        let mut found = Vec::new();
+        for (piece, next, hold) in options {
+            for cells in placements(field, piece) {
+                let mut child = field.clone();
+                let placed = child.place(&cells);
+                for rest in self.search(&child, pieces, next, hold)?.iter() {
+                    let mut solution = rest.clone();
+                    solution.push((piece, placed));
+                    solution.sort();
+                    found.push(solution);
+                }
+            }
+        }
+        found.sort();
+        found.dedup();
+        let found = Arc::new(found);
+        self.dyn_prog.insert(key, found.clone());
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(rows: &[&str]) -> Board {
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        for (line, row) in board[BOARD_HEIGHT - rows.len()..].iter_mut().zip(rows) {
+            for (cell, c) in line.iter_mut().zip(row.chars()) {
+                if c != '_' {
+                    *cell = Piece::Garbage;
+                }
+            }
+        }
+        board
+    }
+
+    fn state(board: Board, hold: Piece, queue: &[Piece]) -> SolverState {
+        let mut state = SolverState::new(0);
+        state.board = board;
+        state.swap_piece = hold;
+        state.piece_active = Piece::None;
+        state.queue = queue.iter().copied().collect();
+        state
+    }
+
+    #[test]
+    fn finds_the_only_solution() {
+        let board = board(&["XXX____XXX"]);
+        let solutions = Solver::new()
+            .solve(&state(board, Piece::None, &[Piece::I]))
+            .unwrap();
+        assert_eq!(solutions.len(), 1);
+        let placed = solutions[0].pieces[0];
+        assert_eq!(placed, (Piece::I, [(23, 3), (23, 4), (23, 5), (23, 6)]));
+    }
+
+    #[test]
+    fn uses_hold_and_matches_placements() {
+        let board = board(&["XXXXXX__XX", "XXXXXX__XX"]);
+        // the O has to come out of hold, since the I can't fill the gap
+        let solutions = Solver::new()
+            .solve(&state(board, Piece::O, &[Piece::I]))
+            .unwrap();
+        assert_eq!(solutions.len(), 1);
+
+        let placements = [Placement {
+            piece: Piece::O,
+            rotation: Rotation::Spawn,
+            line: BOARD_HEIGHT - 2,
+            col: 6,
+        }];
+        let played = Solution::from_placements(&board, &placements).unwrap();
+        assert_eq!(played, solutions[0]);
+    }
+
+    #[test]
+    fn finds_solutions_across_line_clears() {
+        // the bottom row clears first and the T fills the rest afterwards
+        let board = board(&["XXXX___XXX", "XXXXX_XXXX", "XXXXXX____"]);
+        let solutions = Solver::new()
+            .solve(&state(board, Piece::None, &[Piece::I, Piece::T]))
+            .unwrap();
+        assert!(!solutions.is_empty());
+        for solution in solutions.iter() {
+            let drawn = solution.draw(&board);
+            for line in drawn[BOARD_HEIGHT - 3..].iter() {
+                assert!(line.iter().all(|cell| *cell != Piece::None));
+            }
+        }
+    }
 }
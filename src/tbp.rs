@@ -0,0 +1,772 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::iter;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{place, suggest};
+use crate::fumen::{piece_from_char, piece_to_char};
+use crate::solver::{Board, PlacedPiece, Placement};
+use crate::tetris::{Piece, Rotation, State, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+
+// rows in a tbp board, counted up from the bottom
+const TBP_BOARD_HEIGHT: usize = 40;
+
+// messages from the frontend to the bot, as in the tetris bot protocol. the
+// protocol lets both sides add fields and message types, so anything unknown
+// is ignored rather than rejected
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules,
+    Start {
+        hold: Option<char>,
+        queue: Vec<char>, // starting with the piece to place now
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<Vec<Option<char>>>, // rows from the bottom up
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: char,
+    },
+    Quit,
+    #[serde(other)]
+    Unknown,
+}
+
+// messages from the bot to the frontend
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+// where a piece locks. x and y are the piece's rotation center, with y
+// counted up from the bottom row
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+struct Location {
+    #[serde(rename = "type")]
+    piece: char,
+    orientation: Orientation,
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+struct Move {
+    location: Location,
+    #[serde(default = "no_spin")]
+    spin: String,
+}
+
+fn no_spin() -> String {
+    "none".to_string()
+}
+
+impl Move {
+    fn from_placement(placement: &Placement) -> Move {
+        let orientation = match placement.rotation {
+            // an o turned here covers the same cells, but tbp would anchor it
+            // on another one, so it's always sent as it spawns
+            _ if placement.piece == Piece::O => Orientation::North,
+            Rotation::Spawn => Orientation::North,
+            Rotation::Right => Orientation::East,
+            Rotation::Flip => Orientation::South,
+            Rotation::Left => Orientation::West,
+        };
+        // the o is placed by its top left cell here, but by its bottom left
+        // one in tbp
+        let line = placement.line + (placement.piece == Piece::O) as usize;
+        Move {
+            location: Location {
+                piece: piece_to_char(placement.piece),
+                orientation,
+                x: placement.col as i32,
+                y: (BOARD_HEIGHT - 1 - line) as i32,
+            },
+            spin: no_spin(),
+        }
+    }
+
+    fn placement(&self) -> Result<Placement, String> {
+        let location = &self.location;
+        let piece = piece_from_char(location.piece)
+            .ok_or(format!("unknown piece \"{}\"", location.piece))?;
+        let rotation = match location.orientation {
+            _ if piece == Piece::O => Rotation::Spawn,
+            Orientation::North => Rotation::Spawn,
+            Orientation::East => Rotation::Right,
+            Orientation::South => Rotation::Flip,
+            Orientation::West => Rotation::Left,
+        };
+        // tbp turns the o around its bottom left cell, so find where that
+        // cell ends up. the o is placed by its top left one here
+        let (x, y) = match (piece, location.orientation) {
+            (Piece::O, Orientation::East) => (location.x, location.y - 1),
+            (Piece::O, Orientation::South) => (location.x - 1, location.y - 1),
+            (Piece::O, Orientation::West) => (location.x - 1, location.y),
+            _ => (location.x, location.y),
+        };
+        let line = BOARD_HEIGHT as i32 - 1 - y - (piece == Piece::O) as i32;
+        let placement = Placement {
+            piece,
+            rotation,
+            line: line.max(0) as usize,
+            col: x.max(0) as usize,
+        };
+        let in_bounds = placement.cells().iter().all(|(line, col)| {
+            (0..BOARD_HEIGHT as i32).contains(&(*line as i32))
+                && (0..BOARD_WIDTH as i32).contains(&(*col as i32))
+        });
+        if line < 0 || x < 0 || !in_bounds {
+            return Err(format!(
+                "the bot suggested a move off the board: {:?}",
+                location
+            ));
+        }
+        Ok(placement)
+    }
+}
+
+// what a bot knows about the game: the board, hold and pieces to come,
+// starting with the one to place now
+#[derive(Clone)]
+struct Position {
+    board: Board,
+    hold: Option<Piece>,
+    queue: VecDeque<Piece>,
+}
+
+impl Position {
+    fn of(game: &Tetris) -> Position {
+        Position {
+            board: game.board_without_active(),
+            hold: game.get_hold(),
+            queue: visible_pieces(game).into(),
+        }
+    }
+
+    // places the piece to place now on `placement`, or the one it holds for
+    // if the placement is of that. None if neither piece fits it
+    fn play(&mut self, placement: &Placement) -> Option<()> {
+        let current = self.queue.pop_front()?;
+        if placement.piece != current {
+            // the piece was held first, bringing in the hold or the next piece
+            let held = match self.hold {
+                Some(piece) => piece,
+                None => self.queue.pop_front()?,
+            };
+            if held != placement.piece {
+                return None;
+            }
+            self.hold = Some(current);
+        }
+        (self.board, _) = place(&self.board, placement.piece, &placement.cells());
+        Some(())
+    }
+}
+
+// an external bot speaking the tetris bot protocol, as cold clear 2 does. it
+// is told about every piece placed on the game it follows, and asked for a
+// suggestion once for each new piece. its messages are read on their own
+// thread, so a bot thinking for a while doesn't hold up the game
+pub struct TbpBot<W: Write> {
+    messages: Receiver<Result<BotMessage, String>>,
+    writer: W,
+    child: Option<Child>, // the bot's process, if it was launched from here
+    name: String,
+    position: Position,
+    placed: usize,        // pieces placed on the game so far
+    asked: Option<usize>, // the placements a suggestion is on its way for
+    suggestion: Option<(usize, Option<Placement>)>,
+}
+
+impl TbpBot<ChildStdin> {
+    // starts the bot with `command`, e.g. "cold-clear-2 --threads 2"
+    pub fn launch(command: &str) -> Result<Self, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("no bot command given")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not start {}: {}", program, e))?;
+        let reader = BufReader::new(child.stdout.take().ok_or("the bot has no output")?);
+        let writer = child.stdin.take().ok_or("the bot has no input")?;
+        let mut bot = TbpBot::new(reader, writer)?;
+        bot.child = Some(child);
+        Ok(bot)
+    }
+}
+
+impl<W: Write> TbpBot<W> {
+    // waits for the bot to introduce itself and accept the rules
+    pub fn new<R: BufRead + Send + 'static>(reader: R, writer: W) -> Result<Self, String> {
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for message in read_messages(reader) {
+                let stopped = message.is_err();
+                // the bot may be gone by now, in which case nobody is listening
+                if sender.send(message).is_err() || stopped {
+                    return;
+                }
+            }
+        });
+        let mut bot = TbpBot {
+            messages,
+            writer,
+            child: None,
+            name: String::new(),
+            position: Position {
+                board: [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT],
+                hold: None,
+                queue: VecDeque::new(),
+            },
+            placed: 0,
+            asked: None,
+            suggestion: None,
+        };
+        match bot.receive()? {
+            BotMessage::Info {
+                name,
+                version,
+                author,
+                ..
+            } => bot.name = format!("{} {} by {}", name, version, author),
+            _ => return Err("the bot did not introduce itself".to_string()),
+        }
+        bot.send(&FrontendMessage::Rules)?;
+        loop {
+            match bot.receive()? {
+                BotMessage::Ready => return Ok(bot),
+                BotMessage::Error { reason } => {
+                    return Err(format!("the bot refused the rules: {}", reason))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> Result<(), String> {
+        write_message(&mut self.writer, message)
+            .map_err(|e| format!("could not reach the bot: {}", e))
+    }
+
+    // waits for the next message
+    fn receive(&mut self) -> Result<BotMessage, String> {
+        self.messages
+            .recv()
+            .map_err(|_| "the bot stopped".to_string())?
+    }
+
+    // the next message if one has arrived, without waiting for it
+    fn try_receive(&mut self) -> Result<Option<BotMessage>, String> {
+        match self.messages.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("the bot stopped".to_string()),
+        }
+    }
+
+    // tells the bot about `game` from scratch
+    pub fn start(&mut self, game: &Tetris) -> Result<(), String> {
+        self.position = Position::of(game);
+        self.placed = game.placements().len();
+        self.suggestion = None;
+        let mut board = vec![vec![None; BOARD_WIDTH]; TBP_BOARD_HEIGHT];
+        for (row, line) in board.iter_mut().zip(self.position.board.iter().rev()) {
+            for (cell, piece) in row.iter_mut().zip(line) {
+                *cell = match piece {
+                    Piece::None => None,
+                    Piece::Garbage => Some('G'),
+                    _ => Some(piece_to_char(*piece)),
+                };
+            }
+        }
+        let stats = game.stats();
+        self.send(&FrontendMessage::Start {
+            hold: self.position.hold.map(piece_to_char),
+            queue: self
+                .position
+                .queue
+                .iter()
+                .copied()
+                .map(piece_to_char)
+                .collect(),
+            combo: stats.combo,
+            back_to_back: stats.back_to_back,
+            board,
+        })
+    }
+
+    // keeps the bot up to date with the pieces placed on `game` since the
+    // last update. anything it can't follow, e.g. garbage coming in, starts
+    // it over from the current position
+    pub fn update(&mut self, game: &Tetris) -> Result<(), String> {
+        if game.state() != State::Playing || game.placements().len() == self.placed {
+            return Ok(());
+        }
+        if game.placements().len() == self.placed + 1 {
+            let placement = *game.placements().last().unwrap();
+            if let Some(new_pieces) = self.follow(&placement, game) {
+                self.send(&FrontendMessage::Play {
+                    mv: Move::from_placement(&placement),
+                })?;
+                for piece in new_pieces {
+                    self.send(&FrontendMessage::NewPiece {
+                        piece: piece_to_char(piece),
+                    })?;
+                }
+                return Ok(());
+            }
+        }
+        self.send(&FrontendMessage::Stop)?;
+        self.start(game)
+    }
+
+    // plays `placement` on what the bot knows and returns the pieces that
+    // have come into view since, or None if that doesn't lead to `game`
+    fn follow(&mut self, placement: &Placement, game: &Tetris) -> Option<Vec<Piece>> {
+        let mut position = self.position.clone();
+        position.play(placement)?;
+        let mut now = Position::of(game);
+        let known = position.queue.make_contiguous();
+        if position.board != now.board
+            || position.hold != now.hold
+            || !now.queue.make_contiguous().starts_with(known)
+        {
+            return None;
+        }
+        let new_pieces = now.queue.iter().skip(known.len()).copied().collect();
+        self.position = now;
+        self.placed += 1;
+        Some(new_pieces)
+    }
+
+    // the bot's best move for the piece to place now, asked for once a piece.
+    // None until the bot has answered. an answer for a piece that has been
+    // placed since is thrown away, and the next one asked for after it
+    pub fn suggestion(&mut self) -> Result<Option<Placement>, String> {
+        while let Some(asked) = self.asked {
+            let moves = match self.try_receive()? {
+                Some(BotMessage::Suggestion { moves }) => moves,
+                Some(BotMessage::Error { reason }) => {
+                    return Err(format!("the bot failed: {}", reason))
+                }
+                Some(_) => continue,
+                None => break,
+            };
+            self.asked = None;
+            let suggestion = match moves.first() {
+                Some(first) => Some(first.placement()?),
+                None => None,
+            };
+            self.suggestion = Some((asked, suggestion));
+        }
+        match self.suggestion {
+            Some((placed, suggestion)) if placed == self.placed => Ok(suggestion),
+            _ => {
+                if self.asked.is_none() {
+                    self.send(&FrontendMessage::Suggest)?;
+                    self.asked = Some(self.placed);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    // the suggestion as a piece to draw over the board
+    pub fn hint(&mut self) -> Result<Option<PlacedPiece>, String> {
+        Ok(self
+            .suggestion()?
+            .map(|placement| (placement.piece, placement.cells())))
+    }
+}
+
+impl<W: Write> Drop for TbpBot<W> {
+    fn drop(&mut self) {
+        let _ = self.send(&FrontendMessage::Quit);
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// plays the bot's side of the protocol over `reader` and `writer`, e.g. stdin
+// and stdout, until the frontend quits. suggestions come from the same
+// planning as the versus bot, perfect clears first
+pub fn serve<R: BufRead, W: Write>(reader: R, mut writer: W) -> Result<(), String> {
+    let mut send = |message: &BotMessage| {
+        write_message(&mut writer, message)
+            .map_err(|e| format!("could not reach the frontend: {}", e))
+    };
+    send(&BotMessage::Info {
+        name: "pc-trainer".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        author: "pc-trainer".to_string(),
+        features: Vec::new(),
+    })?;
+    let mut position = None;
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = serde_json::from_str(&line)
+            .map_err(|e| format!("bad message from the frontend: {}", e))?;
+        match message {
+            FrontendMessage::Rules => send(&BotMessage::Ready)?,
+            FrontendMessage::Start {
+                hold, queue, board, ..
+            } => {
+                let hold = match hold {
+                    Some(piece) => Some(piece_from_tbp(piece)?),
+                    None => None,
+                };
+                let queue = queue
+                    .into_iter()
+                    .map(piece_from_tbp)
+                    .collect::<Result<_, _>>()?;
+                position = Some(Position {
+                    board: board_from_tbp(&board)?,
+                    hold,
+                    queue,
+                });
+            }
+            FrontendMessage::Stop => position = None,
+            FrontendMessage::Suggest => {
+                let suggestion = position.as_ref().and_then(|position| {
+                    let queue: Vec<Piece> = position.queue.iter().copied().collect();
+                    let (piece, queue) = queue.split_first()?;
+                    suggest(&position.board, *piece, position.hold, queue)
+                });
+                send(&BotMessage::Suggestion {
+                    moves: suggestion.iter().map(Move::from_placement).collect(),
+                })?;
+            }
+            FrontendMessage::Play { mv } => {
+                if let Some(position) = &mut position {
+                    position
+                        .play(&mv.placement()?)
+                        .ok_or("the frontend played a piece it didn't have")?;
+                }
+            }
+            FrontendMessage::NewPiece { piece } => {
+                if let Some(position) = &mut position {
+                    position.queue.push_back(piece_from_tbp(piece)?);
+                }
+            }
+            FrontendMessage::Quit => break,
+            FrontendMessage::Unknown => {}
+        }
+    }
+    Ok(())
+}
+
+// each message on `reader` as it arrives, ending with an error once the bot
+// stops talking
+fn read_messages<R: BufRead>(reader: R) -> impl Iterator<Item = Result<BotMessage, String>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            serde_json::from_str(&line.map_err(|e| e.to_string())?)
+                .map_err(|e| format!("bad message from the bot: {}", e))
+        })
+        .chain(iter::once(Err("the bot stopped".to_string())))
+}
+
+fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<(), String> {
+    let mut line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+    line.push('\n');
+    writer
+        .write_all(line.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| e.to_string())
+}
+
+fn piece_from_tbp(piece: char) -> Result<Piece, String> {
+    piece_from_char(piece).ok_or(format!("unknown piece \"{}\"", piece))
+}
+
+// a tbp board as ours. it has room for rows above the top of ours, which
+// have to be empty
+fn board_from_tbp(rows: &[Vec<Option<char>>]) -> Result<Board, String> {
+    let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+    for (height, row) in rows.iter().enumerate() {
+        for (col, cell) in row.iter().enumerate().take(BOARD_WIDTH) {
+            if let Some(cell) = cell {
+                if height >= BOARD_HEIGHT {
+                    return Err("the board is too tall".to_string());
+                }
+                // garbage and anything else that isn't a piece
+                board[BOARD_HEIGHT - 1 - height][col] =
+                    piece_from_char(*cell).unwrap_or(Piece::Garbage);
+            }
+        }
+    }
+    Ok(board)
+}
+
+// the active piece followed by the previews
+fn visible_pieces(game: &Tetris) -> Vec<Piece> {
+    let (active, ..) = game.active_piece();
+    iter::once(active)
+        .filter(|piece| *piece != Piece::None)
+        .chain(game.get_queue())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetris::fixtures::{board, game};
+    use std::io::{pipe, PipeWriter};
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    // a bot that always suggests dropping the piece straight down, and
+    // returns every message it got once told to quit
+    fn stub_bot() -> (TbpBot<PipeWriter>, JoinHandle<Vec<String>>) {
+        let (bot_reader, frontend_writer) = pipe().unwrap();
+        let (frontend_reader, mut writer) = pipe().unwrap();
+        let stub = thread::spawn(move || {
+            writeln!(
+                writer,
+                r#"{{"type":"info","name":"stub","version":"1","author":"nobody","features":[]}}"#
+            )
+            .unwrap();
+            let mut received = Vec::new();
+            let mut current = 'T';
+            for line in BufReader::new(bot_reader).lines() {
+                let line = line.unwrap();
+                let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                match message["type"].as_str().unwrap() {
+                    "rules" => writeln!(writer, r#"{{"type":"ready"}}"#).unwrap(),
+                    "start" => current = message["queue"][0].as_str().unwrap().parse().unwrap(),
+                    "suggest" => writeln!(
+                        writer,
+                        r#"{{"type":"suggestion","moves":[{{"location":{{"type":"{}","orientation":"north","x":4,"y":0}},"spin":"none"}}],"move_info":{{}}}}"#,
+                        current
+                    )
+                    .unwrap(),
+                    "quit" => {
+                        received.push(line);
+                        break;
+                    }
+                    _ => {}
+                }
+                received.push(line);
+            }
+            received
+        });
+        let bot = TbpBot::new(BufReader::new(frontend_reader), frontend_writer).unwrap();
+        (bot, stub)
+    }
+
+    // asks `bot` for its suggestion until it has one, failing after a while
+    fn wait_for_suggestion(bot: &mut TbpBot<PipeWriter>) -> Placement {
+        let start = Instant::now();
+        loop {
+            if let Some(suggestion) = bot.suggestion().unwrap() {
+                return suggestion;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn follows_the_game_and_suggests_moves() {
+        let (mut bot, stub) = stub_bot();
+        assert_eq!(bot.name(), "stub 1 by nobody");
+
+        let mut game = game(None, 2);
+        let first = game.active_piece().0;
+        bot.start(&game).unwrap();
+        let suggestion = wait_for_suggestion(&mut bot);
+        assert_eq!(suggestion.piece, first);
+        // y 0 is the bottom row, whatever the piece
+        let bottom = suggestion.cells().iter().map(|cell| cell.0).max();
+        assert_eq!(bottom, Some(BOARD_HEIGHT - 1));
+
+        game.harddrop(0);
+        bot.update(&game).unwrap();
+        drop(bot);
+        let received = stub.join().unwrap();
+        let types: Vec<String> = received
+            .iter()
+            .map(|line| {
+                let message: serde_json::Value = serde_json::from_str(line).unwrap();
+                message["type"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            types,
+            ["rules", "start", "suggest", "play", "new_piece", "quit"]
+        );
+    }
+
+    #[test]
+    fn serves_the_solver_as_a_bot() {
+        let (bot_reader, frontend_writer) = pipe().unwrap();
+        let (frontend_reader, bot_writer) = pipe().unwrap();
+        let server = thread::spawn(move || serve(BufReader::new(bot_reader), bot_writer));
+        let mut bot = TbpBot::new(BufReader::new(frontend_reader), frontend_writer).unwrap();
+        assert!(bot.name().starts_with("pc-trainer"));
+
+        // only the o in the hold fills the gap
+        let mut game = game(None, 0);
+        game.load_position(board(2, 4..6), Some(Piece::O), &[Piece::S, Piece::Z], 0);
+        bot.start(&game).unwrap();
+        let suggestion = wait_for_suggestion(&mut bot);
+        let mut cells = suggestion.cells();
+        cells.sort();
+        assert_eq!(suggestion.piece, Piece::O);
+        let (top, bottom) = (BOARD_HEIGHT - 2, BOARD_HEIGHT - 1);
+        assert_eq!(cells, [(top, 4), (top, 5), (bottom, 4), (bottom, 5)]);
+
+        // the bot follows along when the advice isn't taken
+        game.harddrop(0);
+        bot.update(&game).unwrap();
+        wait_for_suggestion(&mut bot);
+        drop(bot);
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn waits_for_a_suggestion_without_blocking() {
+        let (bot_reader, frontend_writer) = pipe().unwrap();
+        let (frontend_reader, mut writer) = pipe().unwrap();
+        // a bot that never gets round to suggesting anything
+        writeln!(
+            writer,
+            r#"{{"type":"info","name":"slow","version":"1","author":"nobody","features":[]}}"#
+        )
+        .unwrap();
+        writeln!(writer, r#"{{"type":"ready"}}"#).unwrap();
+        let mut bot = TbpBot::new(BufReader::new(frontend_reader), frontend_writer).unwrap();
+        bot.start(&game(None, 0)).unwrap();
+        assert_eq!(bot.suggestion(), Ok(None));
+        assert_eq!(bot.hint(), Ok(None));
+
+        // it is only asked once for the same piece
+        drop(bot);
+        let types: Vec<String> = BufReader::new(bot_reader)
+            .lines()
+            .map(|line| {
+                let message: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+                message["type"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(types, ["rules", "start", "suggest", "quit"]);
+    }
+
+    #[test]
+    fn moves_round_trip() {
+        for piece in [Piece::I, Piece::T, Piece::S] {
+            for rotation in [
+                Rotation::Spawn,
+                Rotation::Right,
+                Rotation::Flip,
+                Rotation::Left,
+            ] {
+                let placement = Placement {
+                    piece,
+                    rotation,
+                    line: 20,
+                    col: 4,
+                };
+                assert_eq!(Move::from_placement(&placement).placement(), Ok(placement));
+            }
+        }
+        // tbp places the o by its bottom left cell
+        let o = Placement {
+            piece: Piece::O,
+            rotation: Rotation::Spawn,
+            line: 22,
+            col: 0,
+        };
+        assert_eq!(Move::from_placement(&o).location.y, 0);
+    }
+
+    #[test]
+    fn o_pieces_follow_the_tbp_layout() {
+        // an o turned by a 180 here is still sent as it spawns
+        let flipped = Placement {
+            piece: Piece::O,
+            rotation: Rotation::Flip,
+            line: 22,
+            col: 4,
+        };
+        let mv = Move::from_placement(&flipped);
+        assert_eq!(
+            (mv.location.orientation, mv.location.x, mv.location.y),
+            (Orientation::North, 4, 0)
+        );
+
+        // tbp's o covers its center and the cells up and right of it when
+        // facing north, turning a quarter clockwise around it each time
+        let (bottom, top) = (BOARD_HEIGHT - 1, BOARD_HEIGHT - 2);
+        for (orientation, x, y) in [
+            (Orientation::North, 4, 0),
+            (Orientation::East, 4, 1),
+            (Orientation::South, 5, 1),
+            (Orientation::West, 5, 0),
+        ] {
+            let mv = Move {
+                location: Location {
+                    piece: 'O',
+                    orientation,
+                    x,
+                    y,
+                },
+                spin: no_spin(),
+            };
+            let placement = mv.placement().unwrap();
+            assert_eq!(placement.rotation, Rotation::Spawn);
+            let mut cells = placement.cells();
+            cells.sort();
+            assert_eq!(cells, [(top, 4), (top, 5), (bottom, 4), (bottom, 5)]);
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use rand::{prelude::*, seq::SliceRandom};
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use std::time::{Duration, SystemTime};
-use std::{collections::VecDeque, time::UNIX_EPOCH};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
 
-use crate::history_manager::HistoryManager;
-use crate::solver::SolverState;
-use crate::{get_at, get_color, get_deltas};
+use crate::clock::Timestamp;
+use crate::events::{GameEvent, Subscribers};
+use crate::finesse;
+use crate::garbage::{GarbageLine, GarbageQueue};
+use crate::history_manager::{HistoryManager, HistoryNode};
+use crate::scoring::{Clear, Spin, Stats};
+use crate::settings::{GameplaySettings, PerfectClearAction, Randomizer};
+use crate::solver::{Placement, SolverState};
+use crate::{get_at, get_deltas};
 
 pub const BOARD_HEIGHT: usize = 24;
 pub const BOARD_WIDTH: usize = 10;
@@ -24,8 +27,11 @@ pub const PIECES: [Piece; 7] = [
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum State {
-    Paused,
+    Paused, // stopped at a perfect clear to show the results
     Playing,
+    Editing,
+    ToppedOut, // the next piece had no room to spawn
+    Finished,  // a game mode's goal was reached
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Copy, Debug, PartialOrd, Ord)]
@@ -37,10 +43,11 @@ pub enum Piece {
     S,
     Z,
     O,
+    Garbage,
     None,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Rotation {
     Spawn = 0,
     Left = 1,
@@ -64,7 +71,7 @@ impl std::ops::Add for Rotation {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Direction {
     Down,
     Left,
@@ -83,22 +90,39 @@ pub struct Tetris<'a> {
     state: State,
     // rng: StdRng,
     rot_active: Rotation,
-    lock_timestamp: SystemTime, // starting timestamp to calculate when a lock should occur, if not harddropped.
-    current_gravity: u32,       // time in ms between the active piece moving down a cell
-    gravity: u32,               // time in ms between the active piece moving down a cell
-    gravity_timestamp: SystemTime,
+    lock_timestamp: Option<Timestamp>, // starting timestamp to calculate when a lock should occur, if not harddropped.
+    current_gravity: u32,              // time in ms between the active piece moving down a cell
+    gravity: u32,                      // time in ms between the active piece moving down a cell
+    gravity_timestamp: Option<Timestamp>,
     // swap_piece: Piece,
     swapped: bool,
     max_lock_reset_count: u32, // max number of times lock can be cancelled before active locks anyway
     lock_reset_count: u32,
     lock_delay: u32, // time in ms to wait until a piece is locked automatically
-    history_manager: Option<&'a HistoryManager>,
+    autolock_timestamp: Option<Timestamp>, // when a piece last locked on its own, without being harddropped
+    history_manager: Option<&'a RefCell<HistoryManager>>,
+    placements: Vec<Placement>, // every piece locked since the game started or a position was loaded
+    inputs: u32,                // key presses spent on the active piece
+    finesse_faults: u32, // pieces placed with more presses than needed, counted like placements
+    last_kick: Option<usize>, // kick used by the active piece's last move, if that move was a rotation
+    stats: Stats,
+    on_perfect_clear: PerfectClearAction,
+    seed: u64, // to restart with the same bags
+    randomizer: Randomizer,
+    fixed_queue: bool, // whether the queue is only what was loaded, without new bags
+    garbage: GarbageQueue,
+    outgoing: u32, // attack left after cancelling, waiting to be sent to an opponent
+    subscribers: Subscribers,
 }
 
 impl<'a> Tetris<'a> {
-    pub fn new(history_manager: Option<&'a HistoryManager>) -> Tetris {
-        let board: SolverState = SolverState::new();
-        Tetris {
+    pub fn new(
+        history_manager: Option<&'a RefCell<HistoryManager>>,
+        settings: &GameplaySettings,
+        seed: u64,
+    ) -> Tetris<'a> {
+        let board: SolverState = SolverState::new(seed);
+        let mut tetris = Tetris {
             board,
             // board: [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT],
             row_counts: [0; BOARD_HEIGHT],
@@ -109,51 +133,330 @@ impl<'a> Tetris<'a> {
             col_active: 0,
             line_active: 0,
             rot_active: Rotation::Spawn,
-            lock_timestamp: SystemTime::UNIX_EPOCH,
-            current_gravity: 200,
-            gravity: 0,
-            gravity_timestamp: SystemTime::UNIX_EPOCH,
+            lock_timestamp: None,
+            current_gravity: settings.gravity,
+            gravity: settings.gravity,
+            gravity_timestamp: None,
             // swap_piece: Piece::None,
             swapped: false,
-            max_lock_reset_count: 20,
+            max_lock_reset_count: settings.max_lock_reset_count,
             lock_reset_count: 0,
-            lock_delay: 500,
+            lock_delay: settings.lock_delay,
+            autolock_timestamp: None,
             history_manager,
-        }
+            placements: Vec::new(),
+            inputs: 0,
+            finesse_faults: 0,
+            last_kick: None,
+            stats: Stats::default(),
+            on_perfect_clear: settings.on_perfect_clear,
+            seed,
+            randomizer: settings.randomizer,
+            fixed_queue: false,
+            garbage: GarbageQueue::new(settings, seed),
+            outgoing: 0,
+            subscribers: Subscribers::default(),
+        };
+        tetris.deal();
+        tetris
     }
 
-    pub fn start(&mut self, timestamp: SystemTime) {
+    pub fn start(&mut self, timestamp: Timestamp) {
         self.state = State::Playing;
         self.spawn_next(None);
-        self.gravity_timestamp = timestamp;
+        self.gravity_timestamp = Some(timestamp);
     }
 
     pub fn state(&self) -> State {
         self.state
     }
 
-    pub fn softdrop_instant(&mut self, timestamp: SystemTime) {
-        self.lock_timestamp = timestamp;
+    pub fn board(&self) -> &[[Piece; BOARD_WIDTH]; BOARD_HEIGHT] {
+        &self.board.board
+    }
+
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    pub fn finesse_faults(&self) -> u32 {
+        self.finesse_faults
+    }
+
+    // a stream of everything that happens in the game from now on
+    pub fn subscribe(&mut self) -> Receiver<GameEvent> {
+        self.subscribers.subscribe()
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    // takes changed settings mid-game. what a perfect clear does is left
+    // alone, as modes set that themselves
+    pub fn apply_settings(&mut self, settings: &GameplaySettings) {
+        // a soft drop in progress keeps its speed until it's let go
+        if self.current_gravity == self.gravity {
+            self.current_gravity = settings.gravity;
+        }
+        self.gravity = settings.gravity;
+        self.lock_delay = settings.lock_delay;
+        self.max_lock_reset_count = settings.max_lock_reset_count;
+        self.randomizer = settings.randomizer;
+        self.garbage.apply_settings(settings);
+    }
+
+    // overrides the settings for modes that need a perfect clear to act a
+    // certain way
+    pub fn set_on_perfect_clear(&mut self, action: PerfectClearAction) {
+        self.on_perfect_clear = action;
+    }
+
+    // queues `lines` of garbage, to be inserted once the delay is over
+    pub fn receive_garbage(&mut self, lines: u32, timestamp: Timestamp) {
+        self.garbage.receive(lines, timestamp);
+    }
+
+    pub fn incoming_garbage(&self) -> u32 {
+        self.garbage.pending()
+    }
+
+    // drops all incoming garbage without inserting it
+    pub fn clear_garbage(&mut self) {
+        self.garbage.cancel(self.garbage.pending());
+    }
+
+    // the garbage to send to an opponent since this was last called
+    pub fn take_attack(&mut self) -> u32 {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    // pushes `lines` of garbage up from the bottom of a board being edited
+    pub fn insert_garbage(&mut self, lines: u32) {
+        if self.state != State::Editing {
+            return;
+        }
+        let lines = self.garbage.generate(lines);
+        self.shift_in(&lines);
+    }
+
+    // ends the game once a mode's goal is reached
+    pub fn finish(&mut self) {
+        if self.state == State::Playing {
+            self.state = State::Finished;
+        }
+    }
+
+    // counts a key press towards the active piece's finesse
+    pub fn count_input(&mut self) {
+        self.inputs += 1;
+    }
+
+    pub fn autolock_timestamp(&self) -> Option<Timestamp> {
+        self.autolock_timestamp
+    }
+
+    // returns the active piece, its rotation and its (line, col) position
+    pub fn active_piece(&self) -> (Piece, Rotation, usize, usize) {
+        (
+            self.board.piece_active,
+            self.rot_active,
+            self.line_active,
+            self.col_active,
+        )
+    }
+
+    // the board without the active piece on it
+    pub fn board_without_active(&self) -> [[Piece; BOARD_WIDTH]; BOARD_HEIGHT] {
+        let mut game = self.clone();
+        game.set_piece_at(
+            game.board.piece_active,
+            game.rot_active,
+            Piece::None,
+            game.line_active,
+            game.col_active,
+        );
+        game.board.board
+    }
+
+    // replaces the board, hold and upcoming pieces, e.g. with a position
+    // loaded from fumen. the queue is topped up with random bags as usual
+    pub fn load_position(
+        &mut self,
+        board: [[Piece; BOARD_WIDTH]; BOARD_HEIGHT],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        timestamp: Timestamp,
+    ) {
+        self.load(board, hold, queue, false, timestamp);
+    }
+
+    // like load_position, but with only the pieces given. the held piece is
+    // played last, and the game finishes once there's nothing left
+    pub fn load_fixed_position(
+        &mut self,
+        board: [[Piece; BOARD_WIDTH]; BOARD_HEIGHT],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        timestamp: Timestamp,
+    ) {
+        self.load(board, hold, queue, true, timestamp);
+    }
+
+    fn load(
+        &mut self,
+        board: [[Piece; BOARD_WIDTH]; BOARD_HEIGHT],
+        hold: Option<Piece>,
+        queue: &[Piece],
+        fixed_queue: bool,
+        timestamp: Timestamp,
+    ) {
+        self.set_board(board);
+        self.placements.clear();
+        self.finesse_faults = 0;
+        self.stats = Stats::default();
+        self.board.swap_piece = hold.unwrap_or(Piece::None);
+        self.board.queue = queue.iter().copied().collect();
+        self.fixed_queue = fixed_queue;
+        while !fixed_queue && self.board.queue.len() < 7 {
+            self.queue_add_bag();
+        }
+        self.swapped = false;
+        self.lock_timestamp = None;
+        self.lock_reset_count = 0;
+        self.gravity_timestamp = Some(timestamp);
+        self.state = State::Playing;
+        self.spawn_next(None);
+    }
+
+    // goes back to just before `node`'s piece was placed, after it has been
+    // undone in the history
+    pub fn rewind(&mut self, node: &HistoryNode, timestamp: Timestamp) {
+        self.board = node.state.clone();
+        for (line, col) in node.placement.cells() {
+            self.board.board[line][col] = Piece::None;
+        }
+        self.recompute_row_counts();
+        self.placements.pop();
+        self.inputs = 0;
+        self.swapped = false;
+        self.lock_timestamp = None;
+        self.lock_reset_count = 0;
+        self.gravity_timestamp = Some(timestamp);
+        self.state = State::Playing;
+        self.spawn_next(Some(node.placement.piece));
+    }
+
+    // takes the active piece off the board and back to the front of the queue,
+    // so the board, hold and queue can be edited directly
+    pub fn edit(&mut self) {
+        if self.state == State::Editing {
+            return;
+        }
+        self.set_piece_at(
+            self.board.piece_active,
+            self.rot_active,
+            Piece::None,
+            self.line_active,
+            self.col_active,
+        );
+        if self.board.piece_active != Piece::None {
+            self.board.queue.push_front(self.board.piece_active);
+            self.board.piece_active = Piece::None;
+        }
+        self.state = State::Editing;
+    }
+
+    // resumes playing from the edited position
+    pub fn play(&mut self, timestamp: Timestamp) {
+        if self.state != State::Editing {
+            return;
+        }
+        self.swapped = false;
+        self.lock_timestamp = None;
+        self.lock_reset_count = 0;
+        self.gravity_timestamp = Some(timestamp);
+        self.state = State::Playing;
+        self.spawn_next(None);
+    }
+
+    pub fn set_board(&mut self, board: [[Piece; BOARD_WIDTH]; BOARD_HEIGHT]) {
+        self.board.board = board;
+        self.recompute_row_counts();
+    }
+
+    pub fn set_cell(&mut self, line: usize, col: usize, piece: Piece) {
+        self.board.board[line][col] = piece;
+        self.recompute_row_counts();
+    }
+
+    pub fn set_hold(&mut self, piece: Option<Piece>) {
+        self.board.swap_piece = piece.unwrap_or(Piece::None);
+    }
+
+    pub fn set_queue(&mut self, queue: &[Piece]) {
+        self.board.queue = queue.iter().copied().collect();
+    }
+
+    pub fn queue(&self) -> &VecDeque<Piece> {
+        &self.board.queue
+    }
+
+    // moves the board up to make room for `lines` at the bottom, the first
+    // of them ending up lowest. blocks pushed off the top end the game
+    fn shift_in(&mut self, lines: &[GarbageLine]) {
+        let count = lines.len().min(BOARD_HEIGHT);
+        if count == 0 {
+            return;
+        }
+        if self.state == State::Playing && self.row_counts[..count].iter().any(|c| *c > 0) {
+            self.state = State::ToppedOut;
+            self.subscribers.emit(GameEvent::ToppedOut);
+        }
+        self.board.board.copy_within(count.., 0);
+        self.row_counts.copy_within(count.., 0);
+        for (i, line) in lines[..count].iter().enumerate() {
+            let row = BOARD_HEIGHT - 1 - i;
+            self.board.board[row] = *line;
+            self.row_counts[row] = line.iter().filter(|cell| **cell != Piece::None).count() as u8;
+        }
+    }
+
+    fn recompute_row_counts(&mut self) {
+        for (count, line) in self.row_counts.iter_mut().zip(self.board.board.iter()) {
+            *count = line.iter().filter(|cell| **cell != Piece::None).count() as u8;
+        }
+    }
+
+    pub fn softdrop_instant(&mut self, timestamp: Timestamp) {
+        self.lock_timestamp = Some(timestamp);
         while self.move_active(Direction::Down) {}
         // self.move_active(Direction::Down);
     }
 
+    // called for as long as soft drop is held, so the speed is set rather
+    // than divided down again each time
     pub fn softdrop_start(&mut self, sdf: u32) {
-        self.current_gravity /= sdf;
+        self.current_gravity = (self.gravity / sdf).max(1);
     }
 
     pub fn softdrop_stop(&mut self) {
         self.current_gravity = self.gravity;
     }
 
-    pub fn harddrop(&mut self, timestamp: SystemTime) {
-        while self.move_active(Direction::Down) {}
+    pub fn harddrop(&mut self, timestamp: Timestamp) {
+        let mut lines = 0;
+        while self.move_active(Direction::Down) {
+            lines += 1;
+        }
+        self.subscribers.emit(GameEvent::HardDropped { lines });
         self.lock_active(timestamp);
     }
 
     pub fn swap(&mut self) {
         if !self.swapped {
             self.swapped = true;
+            self.inputs = 0;
             self.set_piece_at(
                 self.board.piece_active,
                 self.rot_active,
@@ -164,6 +467,9 @@ impl<'a> Tetris<'a> {
             let temp = self.board.swap_piece;
             self.board.swap_piece = self.board.piece_active;
             self.board.piece_active = temp;
+            self.subscribers.emit(GameEvent::Held {
+                piece: self.board.swap_piece,
+            });
             if self.board.piece_active == Piece::None {
                 self.spawn_next(None);
             } else {
@@ -172,13 +478,35 @@ impl<'a> Tetris<'a> {
         }
     }
 
-    fn lock_active(&mut self, timestamp: SystemTime) {
+    fn lock_active(&mut self, timestamp: Timestamp) {
+        let placement = Placement {
+            piece: self.board.piece_active,
+            rotation: self.rot_active,
+            line: self.line_active,
+            col: self.col_active,
+        };
         // record state in history
+        if let Some(history_manager) = self.history_manager {
+            history_manager
+                .borrow_mut()
+                .add(self.board.clone(), placement, timestamp, self.inputs);
+        }
+        self.placements.push(placement);
+        self.subscribers.emit(GameEvent::Locked { placement });
+        let mut board = self.board.board;
+        for (line, col) in placement.cells() {
+            board[line][col] = Piece::None;
+        }
+        if finesse::min_inputs(&board, &placement).is_some_and(|min| self.inputs > min) {
+            self.finesse_faults += 1;
+        }
+        self.inputs = 0;
+        let spin = self.spin();
 
         // reset lock time
-        self.lock_timestamp = UNIX_EPOCH;
+        self.lock_timestamp = None;
         self.lock_reset_count = 0;
-        self.gravity_timestamp = timestamp;
+        self.gravity_timestamp = Some(timestamp);
 
         self.swapped = false;
         let ((a, _), (b, _), (c, _)) = get_deltas!(self.board.piece_active, self.rot_active);
@@ -189,7 +517,6 @@ impl<'a> Tetris<'a> {
         self.row_counts[(self.line_active as i32 + b) as usize] += 1;
         self.row_counts[(self.line_active as i32 + c) as usize] += 1;
 
-        // TODO: do something with lines cleared
         let mut lines_cleared = 0;
         let mut i = BOARD_HEIGHT - 1;
         while i > BOARD_HEIGHT - 20 {
@@ -215,9 +542,44 @@ impl<'a> Tetris<'a> {
                 break;
             }
         }
+        if lines_cleared > 0 {
+            self.subscribers.emit(GameEvent::LinesCleared {
+                lines: lines_cleared,
+                spin,
+            });
+        }
         if perfect_clear {
-            self.state = State::Paused;
-            return;
+            self.subscribers.emit(GameEvent::PerfectClear);
+        }
+        let attack = self.stats.attack;
+        self.stats.add(Clear {
+            lines: lines_cleared,
+            spin,
+            perfect_clear,
+        });
+        self.outgoing += self.garbage.cancel(self.stats.attack - attack);
+        // garbage only comes in when a piece clears nothing
+        if lines_cleared == 0 {
+            let lines = self.garbage.take_ready(timestamp);
+            self.shift_in(&lines);
+            if self.state == State::ToppedOut {
+                return;
+            }
+        }
+        if perfect_clear {
+            match self.on_perfect_clear {
+                PerfectClearAction::Continue => {}
+                PerfectClearAction::Restart => {
+                    // the board is empty already; only the bags start over
+                    self.board = SolverState::new(self.seed);
+                    self.deal();
+                    self.placements.clear();
+                }
+                PerfectClearAction::Results => {
+                    self.state = State::Paused;
+                    return;
+                }
+            }
         }
 
         // spawn piece
@@ -272,6 +634,7 @@ impl<'a> Tetris<'a> {
         };
         self.line_active = line;
         self.col_active = col;
+        self.last_kick = None;
         self.set_piece_at(
             self.board.piece_active,
             self.rot_active,
@@ -279,6 +642,7 @@ impl<'a> Tetris<'a> {
             self.line_active,
             self.col_active,
         );
+        self.subscribers.emit(GameEvent::Moved { direction: dir });
         true
     }
 
@@ -297,15 +661,18 @@ impl<'a> Tetris<'a> {
             let la = self.line_active as i32;
             let ca = self.col_active as i32;
             let (a, b, c) = get_deltas!(self.board.piece_active, rot_final);
-            self.rot_active = if get_at!(self, (la), (ca)) == Piece::None
+            if get_at!(self, (la), (ca)) == Piece::None
                 && get_at!(self, (la + a.0), (ca + a.1)) == Piece::None
                 && get_at!(self, (la + b.0), (ca + b.1)) == Piece::None
                 && get_at!(self, (la + c.0), (ca + c.1)) == Piece::None
             {
-                rot_final
-            } else {
-                self.rot_active
-            };
+                self.rot_active = rot_final;
+                self.last_kick = Some(0);
+                self.subscribers.emit(GameEvent::Rotated {
+                    rotation: rot_final,
+                    kick: 0,
+                });
+            }
             self.set_piece_at(
                 self.board.piece_active,
                 self.rot_active,
@@ -316,44 +683,7 @@ impl<'a> Tetris<'a> {
 
             return;
         }
-        if let Some(tests) = match self.board.piece_active {
-            Piece::T | Piece::J | Piece::L | Piece::S | Piece::Z => {
-                match (self.rot_active, rot_final) {
-                    (Rotation::Right, Rotation::Spawn) | (Rotation::Right, Rotation::Flip) => {
-                        Some([(0, 1), (1, 1), (-2, 0), (-2, 1)])
-                    }
-                    (Rotation::Spawn, Rotation::Right) | (Rotation::Flip, Rotation::Right) => {
-                        Some([(0, -1), (-1, -1), (2, 0), (2, -1)])
-                    }
-                    (Rotation::Flip, Rotation::Left) | (Rotation::Spawn, Rotation::Left) => {
-                        Some([(0, 1), (-1, 1), (2, 0), (2, 1)])
-                    }
-                    (Rotation::Left, Rotation::Flip) | (Rotation::Left, Rotation::Spawn) => {
-                        Some([(0, -1), (1, -1), (-2, 0), (-2, -1)])
-                    }
-                    _ => None,
-                }
-            }
-
-            // TODO: fix I piece rotation; something's off
-            Piece::I => match (self.rot_active, rot_final) {
-                (Rotation::Spawn, Rotation::Right) | (Rotation::Left, Rotation::Flip) => {
-                    Some([(0, -2), (0, 1), (1, -2), (-2, 1)])
-                }
-                (Rotation::Right, Rotation::Spawn) | (Rotation::Flip, Rotation::Left) => {
-                    Some([(0, 2), (0, -1), (-1, 2), (2, -1)])
-                }
-                (Rotation::Right, Rotation::Flip) | (Rotation::Spawn, Rotation::Left) => {
-                    Some([(0, -1), (0, 2), (-2, -1), (1, 2)])
-                }
-                (Rotation::Flip, Rotation::Right) | (Rotation::Left, Rotation::Spawn) => {
-                    Some([(0, 1), (0, -2), (2, 1), (-1, -2)])
-                }
-                _ => None,
-            },
-            Piece::O => None,
-            Piece::None => None,
-        } {
+        if let Some(tests) = kick_tests(self.board.piece_active, self.rot_active, rot_final) {
             // Remove current piece from board for checks
             self.set_piece_at(
                 self.board.piece_active,
@@ -367,7 +697,7 @@ impl<'a> Tetris<'a> {
                 get_deltas!(self.board.piece_active, rot_final);
             let la = self.line_active as i32;
             let ca = self.col_active as i32;
-            for test in std::iter::once((0, 0)).chain(tests.into_iter()) {
+            for (kick, test) in std::iter::once((0, 0)).chain(tests).enumerate() {
                 if get_at!(self, (la + test.0), (ca + test.1)) == Piece::None
                     && get_at!(self, (la + a.0 + test.0), (ca + a.1 + test.1)) == Piece::None
                     && get_at!(self, (la + b.0 + test.0), (ca + b.1 + test.1)) == Piece::None
@@ -384,6 +714,11 @@ impl<'a> Tetris<'a> {
                     self.line_active = (la + test.0) as usize;
                     self.col_active = (ca + test.1) as usize;
                     self.rot_active = rot_final;
+                    self.last_kick = Some(kick);
+                    self.subscribers.emit(GameEvent::Rotated {
+                        rotation: rot_final,
+                        kick,
+                    });
                     return;
                 }
             }
@@ -399,42 +734,37 @@ impl<'a> Tetris<'a> {
         }
     }
 
-    pub fn update(&mut self, timestamp: SystemTime) {
-        if self.state == State::Paused {
+    pub fn update(&mut self, timestamp: Timestamp) {
+        if self.state != State::Playing {
             return;
         }
 
         // process gravity
-        if self.current_gravity > 0 && self.gravity_timestamp != UNIX_EPOCH {
-            if let Ok(dur) = timestamp.duration_since(self.gravity_timestamp) {
-                let dur_millis = dur.as_millis() as u64;
-                let moves = dur_millis / self.current_gravity as u64;
-                if dur_millis > self.current_gravity as u64 {
+        if self.current_gravity > 0 {
+            if let Some(gravity_timestamp) = self.gravity_timestamp {
+                let gravity = self.current_gravity as u64;
+                let dur_millis = timestamp.saturating_sub(gravity_timestamp);
+                let moves = dur_millis / gravity;
+                if dur_millis > gravity {
                     for i in 0..moves {
                         if !self.move_active(Direction::Down) {
-                            self.gravity_timestamp = self
-                                .gravity_timestamp
-                                .checked_add(Duration::from_millis(i * self.current_gravity as u64))
-                                .unwrap();
-                            self.lock_timestamp = self.gravity_timestamp;
-                            self.gravity_timestamp = UNIX_EPOCH;
+                            self.lock_timestamp = Some(gravity_timestamp + i * gravity);
+                            self.gravity_timestamp = None;
                             return;
                         }
                     }
-                    self.gravity_timestamp = self
-                        .gravity_timestamp
-                        .checked_add(Duration::from_millis(moves * self.current_gravity as u64))
-                        .unwrap();
+                    self.gravity_timestamp = Some(gravity_timestamp + moves * gravity);
                 }
             }
         }
 
         // lock in piece if time is correct or number of lock delay cancels exeeds max
-        if self.lock_timestamp != UNIX_EPOCH {
-            if let Ok(dur) = timestamp.duration_since(self.lock_timestamp) {
-                if dur.as_millis() > self.lock_delay as u128
+        if let Some(lock_timestamp) = self.lock_timestamp {
+            if let Some(dur_millis) = timestamp.checked_sub(lock_timestamp) {
+                if dur_millis > self.lock_delay as u64
                     || self.lock_reset_count > self.max_lock_reset_count
                 {
+                    self.autolock_timestamp = Some(timestamp);
                     self.lock_active(timestamp);
                 }
             }
@@ -466,97 +796,114 @@ impl<'a> Tetris<'a> {
         }
     }
 
-    pub fn get_queue(&self) -> [Piece; 5] {
-        [
-            *self.board.queue.get(0).unwrap(),
-            *self.board.queue.get(1).unwrap(),
-            *self.board.queue.get(2).unwrap(),
-            *self.board.queue.get(3).unwrap(),
-            *self.board.queue.get(4).unwrap(),
-        ]
+    // the next five pieces, or fewer while a shorter queue is being edited
+    pub fn get_queue(&self) -> Vec<Piece> {
+        self.board.queue.iter().take(5).copied().collect()
     }
 
     fn queue_add_bag(&mut self) {
-        let mut pieces_clone = PIECES.clone();
-        pieces_clone.shuffle(&mut self.board.rng);
-        self.board
-            .queue
-            .append(&mut VecDeque::from_iter(pieces_clone));
-    }
-
-    pub fn draw_board_texture(
-        &self,
-        texture_canvas: &mut Canvas<Window>,
-        x_offset: i32,
-        y_offset: i32,
-        project: bool,
-    ) -> Result<(), String> {
-        texture_canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
-        texture_canvas.clear();
-        if project {
-            let (a, b, c) = get_deltas!(self.board.piece_active, self.rot_active);
-            let la = self.line_active as i32;
-            let ca = self.col_active as i32;
-            let orig = vec![
-                (la, ca),
-                (la + a.0, ca + a.1),
-                (la + b.0, ca + b.1),
-                (la + c.0, ca + c.1),
-            ];
-            let mut piece = orig.clone();
-            while (orig.contains(&piece[0]) || get_at!(self, piece[0].0, piece[0].1) == Piece::None)
-                && (orig.contains(&piece[1])
-                    || get_at!(self, piece[1].0, piece[1].1) == Piece::None)
-                && (orig.contains(&piece[2])
-                    || get_at!(self, piece[2].0, piece[2].1) == Piece::None)
-                && (orig.contains(&piece[3])
-                    || get_at!(self, piece[3].0, piece[3].1) == Piece::None)
-            {
-                piece[0] = (piece[0].0 + 1, piece[0].1);
-                piece[1] = (piece[1].0 + 1, piece[1].1);
-                piece[2] = (piece[2].0 + 1, piece[2].1);
-                piece[3] = (piece[3].0 + 1, piece[3].1);
+        match self.randomizer {
+            Randomizer::SevenBag => {
+                let mut pieces_clone = PIECES;
+                pieces_clone.shuffle(&mut self.board.rng);
+                self.board
+                    .queue
+                    .append(&mut VecDeque::from_iter(pieces_clone));
             }
-            piece[0] = (piece[0].0 - 1, piece[0].1);
-            piece[1] = (piece[1].0 - 1, piece[1].1);
-            piece[2] = (piece[2].0 - 1, piece[2].1);
-            piece[3] = (piece[3].0 - 1, piece[3].1);
-            if let Some(color) = get_color!(self.board.piece_active) {
-                let color = color.rgba();
-                texture_canvas.set_draw_color(Color::RGBA(color.0, color.1, color.2, color.3 / 2));
-                for point in piece.iter() {
-                    texture_canvas.fill_rect(Rect::new(
-                        x_offset + (point.1 as u32 * crate::TILE_SIZE) as i32,
-                        y_offset + (point.0 as u32 * crate::TILE_SIZE) as i32,
-                        crate::TILE_SIZE,
-                        crate::TILE_SIZE,
-                    ))?;
+            Randomizer::Random => {
+                for _ in 0..PIECES.len() {
+                    let piece = *PIECES.choose(&mut self.board.rng).expect("no pieces");
+                    self.board.queue.push_back(piece);
                 }
             }
         }
-        for line in 0..BOARD_HEIGHT {
-            for col in 0..BOARD_WIDTH {
-                if let Some(color) = get_color!(self.board.board[line][col]) {
-                    texture_canvas.set_draw_color(color);
-                    texture_canvas.fill_rect(Rect::new(
-                        x_offset + (col as u32 * crate::TILE_SIZE) as i32,
-                        y_offset + (line as u32 * crate::TILE_SIZE) as i32,
-                        crate::TILE_SIZE,
-                        crate::TILE_SIZE,
-                    ))?;
-                }
-            }
+    }
+
+    // a new SolverState comes with two bags already, so other randomizers
+    // deal their own queue instead
+    fn deal(&mut self) {
+        if self.randomizer != Randomizer::SevenBag {
+            self.board.queue.clear();
+            self.queue_add_bag();
+            self.queue_add_bag();
+        }
+    }
+
+    // the cells the active piece would land on if it were hard dropped
+    pub fn ghost(&self) -> Option<[(usize, usize); 4]> {
+        if self.board.piece_active == Piece::None {
+            return None;
+        }
+        let (a, b, c) = get_deltas!(self.board.piece_active, self.rot_active);
+        let la = self.line_active as i32;
+        let ca = self.col_active as i32;
+        let active = [
+            (la, ca),
+            (la + a.0, ca + a.1),
+            (la + b.0, ca + b.1),
+            (la + c.0, ca + c.1),
+        ];
+        let fits = |drop: i32| {
+            active.iter().all(|(line, col)| {
+                active.contains(&(line + drop, *col))
+                    || get_at!(self, line + drop, *col) == Piece::None
+            })
+        };
+        let mut drop = 0;
+        while fits(drop + 1) {
+            drop += 1;
+        }
+        Some(active.map(|(line, col)| ((line + drop) as usize, col as usize)))
+    }
+
+    // whether the active piece is a t that spun into place, using the three
+    // corner rule. the last kick of a rotation always counts as a full spin
+    fn spin(&self) -> Spin {
+        if self.board.piece_active != Piece::T {
+            return Spin::None;
+        }
+        let kick = match self.last_kick {
+            Some(kick) => kick,
+            None => return Spin::None,
+        };
+        let la = self.line_active as i32;
+        let ca = self.col_active as i32;
+        let filled =
+            |(line, col): (i32, i32)| get_at!(self, (la + line), (ca + col)) != Piece::None;
+        let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+        if corners.iter().filter(|corner| filled(**corner)).count() < 3 {
+            return Spin::None;
+        }
+        // the corners on either side of the t's nub
+        let front = match self.rot_active {
+            Rotation::Spawn => [(-1, -1), (-1, 1)],
+            Rotation::Right => [(-1, 1), (1, 1)],
+            Rotation::Flip => [(1, -1), (1, 1)],
+            Rotation::Left => [(-1, -1), (1, -1)],
+        };
+        if front.iter().all(|corner| filled(*corner)) || kick == 4 {
+            Spin::Full
+        } else {
+            Spin::Mini
         }
-        Ok(())
     }
 
     fn spawn_next(&mut self, piece: Option<Piece>) {
-        // TODO: check for top-out
         let fill = if piece.is_none() {
-            if self.board.queue.len() < 7 {
+            if self.board.queue.len() < 7 && !self.fixed_queue {
                 self.queue_add_bag();
             }
-            self.board.piece_active = self.board.queue.pop_front().unwrap_or(Piece::None);
+            self.board.piece_active = match self.board.queue.pop_front() {
+                Some(piece) => piece,
+                None if self.fixed_queue => {
+                    std::mem::replace(&mut self.board.swap_piece, Piece::None)
+                }
+                None => Piece::None,
+            };
+            if self.fixed_queue && self.board.piece_active == Piece::None {
+                self.finish();
+                return;
+            }
             self.board.piece_active
         } else {
             piece.unwrap()
@@ -565,6 +912,20 @@ impl<'a> Tetris<'a> {
         self.col_active = 4;
         self.line_active = 3;
         self.rot_active = Rotation::Spawn;
+        self.last_kick = None;
+        let (a, b, c) = get_deltas!(fill, self.rot_active);
+        let blocked = [(0, 0), a, b, c]
+            .iter()
+            .any(|(line, col)| get_at!(self, (3 + line), (4 + col)) != Piece::None);
+        if fill != Piece::None && blocked {
+            self.board.piece_active = Piece::None;
+            self.state = State::ToppedOut;
+            self.subscribers.emit(GameEvent::ToppedOut);
+            return;
+        }
+        if fill != Piece::None {
+            self.subscribers.emit(GameEvent::Spawned { piece: fill });
+        }
         self.set_piece_at(
             self.board.piece_active,
             self.rot_active,
@@ -575,3 +936,249 @@ impl<'a> Tetris<'a> {
         // println!("spawn piece {:?}", self.board.piece_active);
     }
 }
+
+// the SRS wall kick offsets, as (line, col), to try when rotating a piece
+// from one orientation to another. 180 rotations don't kick
+pub fn kick_tests(piece: Piece, from: Rotation, to: Rotation) -> Option<[(i32, i32); 4]> {
+    match piece {
+        Piece::T | Piece::J | Piece::L | Piece::S | Piece::Z => match (from, to) {
+            (Rotation::Right, Rotation::Spawn) | (Rotation::Right, Rotation::Flip) => {
+                Some([(0, 1), (1, 1), (-2, 0), (-2, 1)])
+            }
+            (Rotation::Spawn, Rotation::Right) | (Rotation::Flip, Rotation::Right) => {
+                Some([(0, -1), (-1, -1), (2, 0), (2, -1)])
+            }
+            (Rotation::Flip, Rotation::Left) | (Rotation::Spawn, Rotation::Left) => {
+                Some([(0, 1), (-1, 1), (2, 0), (2, 1)])
+            }
+            (Rotation::Left, Rotation::Flip) | (Rotation::Left, Rotation::Spawn) => {
+                Some([(0, -1), (1, -1), (-2, 0), (-2, -1)])
+            }
+            _ => None,
+        },
+
+        // TODO: fix I piece rotation; something's off
+        Piece::I => match (from, to) {
+            (Rotation::Spawn, Rotation::Right) | (Rotation::Left, Rotation::Flip) => {
+                Some([(0, -2), (0, 1), (1, -2), (-2, 1)])
+            }
+            (Rotation::Right, Rotation::Spawn) | (Rotation::Flip, Rotation::Left) => {
+                Some([(0, 2), (0, -1), (-1, 2), (2, -1)])
+            }
+            (Rotation::Right, Rotation::Flip) | (Rotation::Spawn, Rotation::Left) => {
+                Some([(0, -1), (0, 2), (-2, -1), (1, 2)])
+            }
+            (Rotation::Flip, Rotation::Right) | (Rotation::Left, Rotation::Spawn) => {
+                Some([(0, 1), (0, -2), (2, 1), (-1, -2)])
+            }
+            _ => None,
+        },
+        Piece::O => None,
+        Piece::Garbage | Piece::None => None,
+    }
+}
+
+// games and boards for the tests of everything built on top of a game
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use std::cell::RefCell;
+    use std::ops::Range;
+
+    use super::{Piece, Tetris, BOARD_HEIGHT, BOARD_WIDTH};
+    use crate::history_manager::HistoryManager;
+    use crate::settings::{GameplaySettings, PerfectClearAction};
+
+    // a started game whose pieces only fall when dropped, and that carries
+    // on after a perfect clear
+    pub(crate) fn game(history: Option<&RefCell<HistoryManager>>, seed: u64) -> Tetris<'_> {
+        let settings = GameplaySettings {
+            gravity: 0,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(history, &settings, seed);
+        game.set_on_perfect_clear(PerfectClearAction::Continue);
+        game.start(0);
+        game
+    }
+
+    // `lines` lines of garbage at the bottom of the board, each open in `gap`
+    pub(crate) fn board(lines: usize, gap: Range<usize>) -> [[Piece; BOARD_WIDTH]; BOARD_HEIGHT] {
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        for line in &mut board[BOARD_HEIGHT - lines..] {
+            *line = [Piece::Garbage; BOARD_WIDTH];
+            line[gap.clone()].fill(Piece::None);
+        }
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(seed: u64) -> Tetris<'static> {
+        let settings = GameplaySettings {
+            gravity: 100,
+            lock_delay: 300,
+            max_lock_reset_count: 20,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &settings, seed);
+        game.start(0);
+        for t in (0..20_000).step_by(16) {
+            if t % 1000 == 500 {
+                game.move_active(Direction::Left);
+                game.rot_active(Rotation::Right);
+            }
+            game.update(t);
+        }
+        game
+    }
+
+    #[test]
+    fn same_seed_and_timestamps_give_same_game() {
+        let (a, b) = (play(7), play(7));
+        assert_eq!(a.board(), b.board());
+        assert_eq!(a.get_queue(), b.get_queue());
+    }
+
+    #[test]
+    fn gravity_moves_one_line_per_interval() {
+        let settings = GameplaySettings {
+            gravity: 100,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &settings, 0);
+        game.start(1000);
+        let line = game.active_piece().2;
+        game.update(1250);
+        assert_eq!(game.active_piece().2, line + 2);
+        game.update(1301);
+        assert_eq!(game.active_piece().2, line + 3);
+    }
+
+    #[test]
+    fn extra_presses_are_finesse_faults() {
+        let mut game = Tetris::new(None, &GameplaySettings::default(), 0);
+        game.start(0);
+        // a piece is moved one column left: one tap is enough
+        game.count_input();
+        game.move_active(Direction::Left);
+        game.harddrop(0);
+        assert_eq!(game.finesse_faults(), 0);
+
+        // moving right and back left again ends up at the spawn column
+        for dir in [Direction::Right, Direction::Left] {
+            game.count_input();
+            game.move_active(dir);
+        }
+        game.harddrop(0);
+        assert_eq!(game.finesse_faults(), 1);
+    }
+
+    #[test]
+    fn t_spin_double_needs_three_corners() {
+        let mut game = Tetris::new(None, &GameplaySettings::default(), 0);
+        game.start(0);
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[21][3] = Piece::Garbage;
+        board[22] = [Piece::Garbage; BOARD_WIDTH];
+        board[22][3..6].fill(Piece::None);
+        board[23] = [Piece::Garbage; BOARD_WIDTH];
+        board[23][4] = Piece::None;
+        game.load_position(board, None, &[Piece::T], 0);
+
+        // put the t in the slot as if it had just been rotated there
+        game.set_piece_at(Piece::T, Rotation::Spawn, Piece::None, 3, 4);
+        game.set_piece_at(Piece::T, Rotation::Flip, Piece::T, 22, 4);
+        game.rot_active = Rotation::Flip;
+        game.line_active = 22;
+        game.last_kick = Some(0);
+        game.harddrop(0);
+        assert_eq!((game.stats().lines, game.stats().tspins), (2, 1));
+        assert_eq!(game.stats().score, 1200);
+    }
+
+    #[test]
+    fn spawning_into_blocks_tops_out() {
+        let mut game = Tetris::new(None, &GameplaySettings::default(), 0);
+        game.start(0);
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[3][4] = Piece::Garbage;
+        game.load_position(board, None, &[], 0);
+        assert!(game.state() == State::ToppedOut);
+    }
+
+    #[test]
+    fn restarting_after_a_perfect_clear_repeats_the_bags() {
+        let settings = GameplaySettings {
+            on_perfect_clear: PerfectClearAction::Restart,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &settings, 5);
+        game.start(0);
+        let first = (game.active_piece().0, game.get_queue());
+
+        // an i fills the gap in the bottom line and clears the board
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[BOARD_HEIGHT - 1] = [Piece::Garbage; BOARD_WIDTH];
+        board[BOARD_HEIGHT - 1][3..7].fill(Piece::None);
+        game.load_position(board, None, &[Piece::I], 0);
+        game.harddrop(0);
+        assert!(game.state() == State::Playing);
+        assert_eq!((game.active_piece().0, game.get_queue()), first);
+        assert_eq!(game.stats().perfect_clears, 1);
+    }
+
+    #[test]
+    fn garbage_comes_in_after_a_piece_that_clears_nothing() {
+        let settings = GameplaySettings {
+            garbage_delay: 100,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &settings, 0);
+        game.start(0);
+        let empty = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        game.load_position(empty, None, &[Piece::O, Piece::O], 0);
+        game.receive_garbage(2, 0);
+        game.harddrop(50);
+        assert_eq!(game.incoming_garbage(), 2);
+        assert_eq!(game.board()[BOARD_HEIGHT - 1][4], Piece::O);
+
+        // both os end up two lines higher than they landed
+        game.harddrop(100);
+        assert_eq!(game.incoming_garbage(), 0);
+        let board = game.board();
+        let column: Vec<Piece> = board[BOARD_HEIGHT - 6..]
+            .iter()
+            .map(|line| line[4])
+            .collect();
+        assert_eq!(column[..4], [Piece::O; 4]);
+        for line in board[BOARD_HEIGHT - 2..].iter() {
+            assert_eq!(
+                line.iter().filter(|cell| **cell == Piece::Garbage).count(),
+                9
+            );
+        }
+        let counts: Vec<u8> = game
+            .board_without_active()
+            .iter()
+            .map(|line| line.iter().filter(|cell| **cell != Piece::None).count() as u8)
+            .collect();
+        assert_eq!(game.row_counts.to_vec(), counts);
+    }
+
+    #[test]
+    fn garbage_pushed_past_the_top_tops_out() {
+        let settings = GameplaySettings {
+            garbage_delay: 0,
+            garbage_cap: BOARD_HEIGHT as u32,
+            ..GameplaySettings::default()
+        };
+        let mut game = Tetris::new(None, &settings, 0);
+        game.start(0);
+        game.receive_garbage(BOARD_HEIGHT as u32 - 1, 0);
+        game.harddrop(0);
+        assert!(game.state() == State::ToppedOut);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::clock::Timestamp;
+use crate::settings::PerfectClearAction;
+use crate::tetris::{State, Tetris};
+
+// two boards playing against each other. whatever one sends after cancelling
+// comes in as garbage on the other, and the first to top out loses
+pub struct Versus {
+    sent: [u32; 2],
+    winner: Option<usize>,
+}
+
+impl Default for Versus {
+    fn default() -> Self {
+        Versus::new()
+    }
+}
+
+impl Versus {
+    pub fn new() -> Versus {
+        Versus {
+            sent: [0; 2],
+            winner: None,
+        }
+    }
+
+    // readies both boards for a round. perfect clears are just another attack
+    pub fn start(&mut self, boards: &mut [Tetris; 2], timestamp: Timestamp) {
+        for board in boards.iter_mut() {
+            board.set_on_perfect_clear(PerfectClearAction::Continue);
+            board.start(timestamp);
+        }
+        self.sent = [0; 2];
+        self.winner = None;
+    }
+
+    pub fn update(&mut self, boards: &mut [Tetris; 2], timestamp: Timestamp) {
+        if self.winner.is_some() {
+            return;
+        }
+        for player in 0..2 {
+            let attack = boards[player].take_attack();
+            self.sent[player] += attack;
+            boards[1 - player].receive_garbage(attack, timestamp);
+        }
+        if let Some(loser) = (0..2).find(|i| boards[*i].state() == State::ToppedOut) {
+            self.winner = Some(1 - loser);
+            boards[1 - loser].finish();
+        }
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn hud(&self) -> Vec<String> {
+        let mut text = vec![
+            "VERSUS".to_string(),
+            format!("P1 SENT {}", self.sent[0]),
+            format!("P2 SENT {}", self.sent[1]),
+        ];
+        if let Some(winner) = self.winner {
+            text.push(format!("P{} WINS", winner + 1));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::GameplaySettings;
+    use crate::tetris::fixtures::board;
+    use crate::tetris::{Piece, BOARD_HEIGHT, BOARD_WIDTH};
+
+    // versus starts the games itself
+    fn boards() -> [Tetris<'static>; 2] {
+        let settings = GameplaySettings::default();
+        [
+            Tetris::new(None, &settings, 1),
+            Tetris::new(None, &settings, 1),
+        ]
+    }
+
+    #[test]
+    fn attacks_cancel_garbage_before_reaching_the_opponent() {
+        let mut boards = boards();
+        let mut versus = Versus::new();
+        versus.start(&mut boards, 0);
+
+        // p2 sends a double with an o, keeping the line below
+        let mut double = board(3, 4..6);
+        double[BOARD_HEIGHT - 1][4..6].fill(Piece::Garbage);
+        double[BOARD_HEIGHT - 1][0] = Piece::None;
+        boards[1].load_position(double, None, &[Piece::O], 0);
+        boards[1].harddrop(0);
+        versus.update(&mut boards, 0);
+        assert_eq!(boards[0].incoming_garbage(), 1);
+
+        // p1's perfect clear double is worth 11, one of which cancels it
+        boards[0].load_position(board(2, 4..6), None, &[Piece::O], 0);
+        boards[0].harddrop(0);
+        versus.update(&mut boards, 0);
+        assert_eq!(boards[0].incoming_garbage(), 0);
+        assert_eq!(boards[1].incoming_garbage(), 10);
+        assert_eq!(versus.winner(), None);
+    }
+
+    #[test]
+    fn topping_out_loses() {
+        let mut boards = boards();
+        let mut versus = Versus::new();
+        versus.start(&mut boards, 0);
+        let mut board = [[Piece::None; BOARD_WIDTH]; BOARD_HEIGHT];
+        board[3][4] = Piece::Garbage;
+        boards[1].load_position(board, None, &[], 0);
+        versus.update(&mut boards, 0);
+        assert_eq!(versus.winner(), Some(0));
+        assert!(boards[0].state() == State::Finished);
+    }
+}
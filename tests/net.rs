@@ -0,0 +1,102 @@
+// runs two copies of the game against each other over loopback, one hosting
+// and one joining. sdl's dummy drivers stand in for the window and sound, so
+// no display is needed
+#![cfg(feature = "sdl")]
+
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+// a copy of the game, killed when dropped so a failing test leaves none behind
+struct Game(Child);
+
+impl Game {
+    // each copy gets a directory of its own for the settings it writes
+    fn spawn(dir: &Path, args: &[&str]) -> Game {
+        fs::create_dir_all(dir).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_pc-trainer"))
+            .args(args)
+            .current_dir(dir)
+            .env("SDL_VIDEODRIVER", "dummy")
+            .env("SDL_AUDIODRIVER", "dummy")
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Game(child)
+    }
+
+    // what the game printed if it has exited
+    fn exited(&mut self) -> Option<String> {
+        self.0.try_wait().unwrap()?;
+        let mut stderr = String::new();
+        if let Some(mut pipe) = self.0.stderr.take() {
+            pipe.read_to_string(&mut stderr).unwrap();
+        }
+        Some(stderr)
+    }
+}
+
+impl Drop for Game {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("pc-trainer-{}-{}", name, std::process::id()))
+}
+
+#[test]
+fn the_host_outlasts_an_opponent_leaving() {
+    let dir = scratch_dir("net");
+    let port = free_port().to_string();
+    let mut host = Game::spawn(&dir.join("host"), &["host", &port]);
+
+    // joining fails until the host is listening, so keep trying
+    let address = format!("127.0.0.1:{}", port);
+    let start = Instant::now();
+    let mut guest = loop {
+        let mut guest = Game::spawn(&dir.join("guest"), &["join", &address]);
+        thread::sleep(Duration::from_millis(500));
+        match guest.exited() {
+            None => break guest,
+            Some(stderr) => assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "could not join: {}",
+                stderr
+            ),
+        }
+        if let Some(stderr) = host.exited() {
+            panic!("the host exited: {}", stderr);
+        }
+    };
+
+    // both play until one leaves
+    thread::sleep(Duration::from_millis(500));
+    if let Some(stderr) = host.exited() {
+        panic!("the host exited while playing: {}", stderr);
+    }
+    if let Some(stderr) = guest.exited() {
+        panic!("the guest exited while playing: {}", stderr);
+    }
+    drop(guest);
+
+    // the host shows the result instead of quitting
+    thread::sleep(Duration::from_secs(1));
+    if let Some(stderr) = host.exited() {
+        panic!("the host exited after the guest left: {}", stderr);
+    }
+    drop(host);
+    let _ = fs::remove_dir_all(dir);
+}